@@ -0,0 +1,671 @@
+use std::collections::HashSet;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crossbeam_channel::Sender;
+use log::error;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::mpsc;
+
+use crate::types::{Accumulators, GeyserMessage, MonitoredAccounts};
+
+/// Requests understood by the admin socket. Each request is a single line of JSON, e.g.
+/// `{"command":"add_accounts","list":"proof","pubkeys":["..."]}`, answered by a single line of
+/// JSON holding an `AdminResponse`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum AdminRequest {
+    ListAccounts { list: AccountList },
+    AddAccounts { list: AccountList, pubkeys: Vec<String> },
+    RemoveAccounts { list: AccountList, pubkeys: Vec<String> },
+    /// Prove `pubkeys` until a matching `UnwatchAccounts`. Sent by the proof server for
+    /// subscribers that register accounts in their handshake.
+    WatchAccounts { pubkeys: Vec<String> },
+    UnwatchAccounts { pubkeys: Vec<String> },
+    /// Summary of every slot currently held in the accumulators
+    ListSlots,
+    /// Everything held in the accumulators for one slot
+    DumpSlot { slot: u64 },
+    /// Drop all accumulated state for the slots in `start_slot..=end_slot`
+    EvictSlots { start_slot: u64, end_slot: u64 },
+}
+
+/// Which list of monitored accounts a request applies to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountList {
+    /// Accounts for which proofs are generated (`account_list`)
+    Proof,
+    /// Vote accounts whose votes are tracked (`vote_accounts`)
+    Vote,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum AdminResponse {
+    Accounts { pubkeys: Vec<String> },
+    Slots { slots: Vec<SlotSummary> },
+    Slot { dump: Option<SlotDump> },
+    Evicted { slots: Vec<u64> },
+    Error { message: String },
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlotSummary {
+    pub slot: u64,
+    pub raw_accounts: usize,
+    pub processed_accounts: usize,
+    pub raw_signatures: Option<u64>,
+    pub processed_signatures: Option<u64>,
+    pub raw_votes: usize,
+    pub processed_votes: usize,
+    pub has_block: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlotDump {
+    #[serde(flatten)]
+    pub summary: SlotSummary,
+    pub accounts: Vec<AccountDump>,
+    pub votes: Vec<VoteDump>,
+    pub block: Option<BlockDump>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountDump {
+    pub pubkey: String,
+    /// `raw` or `processed`
+    pub stage: String,
+    pub write_version: u64,
+    pub hash: String,
+    pub lamports: u64,
+    pub owner: String,
+    pub executable: bool,
+    pub data_len: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VoteDump {
+    pub signature: String,
+    /// `raw` or `processed`
+    pub stage: String,
+    pub vote_for_slot: u64,
+    pub vote_for_hash: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockDump {
+    pub parent_bankhash: String,
+    pub blockhash: String,
+    pub executed_transaction_count: u64,
+}
+
+/// An admin request forwarded to `process_messages`, which owns the state it inspects
+#[derive(Clone, Debug)]
+pub struct AdminCommand {
+    pub request: AdminRequest,
+    pub reply: mpsc::UnboundedSender<AdminResponse>,
+}
+
+fn parse_pubkeys(pubkeys: &[String]) -> Result<Vec<Pubkey>, String> {
+    pubkeys
+        .iter()
+        .map(|pubkey| Pubkey::from_str(pubkey).map_err(|e| format!("invalid pubkey {}: {}", pubkey, e)))
+        .collect()
+}
+
+fn slot_summary(slot: u64, accumulators: &Accumulators) -> SlotSummary {
+    SlotSummary {
+        slot,
+        raw_accounts: accumulators
+            .raw_slot_account_accumulator
+            .get(&slot)
+            .map_or(0, |accounts| accounts.len()),
+        processed_accounts: accumulators
+            .processed_slot_account_accumulator
+            .get(&slot)
+            .map_or(0, |accounts| accounts.len()),
+        raw_signatures: accumulators.raw_transaction_accumulator.get(&slot).copied(),
+        processed_signatures: accumulators
+            .processed_transaction_accumulator
+            .get(&slot)
+            .copied(),
+        raw_votes: accumulators
+            .raw_vote_accumulator
+            .get(&slot)
+            .map_or(0, |votes| votes.len()),
+        processed_votes: accumulators
+            .processed_vote_accumulator
+            .get(&slot)
+            .map_or(0, |votes| votes.len()),
+        has_block: accumulators.block_accumulator.contains_key(&slot),
+    }
+}
+
+fn slot_dump(slot: u64, accumulators: &Accumulators) -> Option<SlotDump> {
+    if !accumulators.slots().contains(&slot) {
+        return None;
+    }
+
+    let mut accounts = vec![];
+    for (stage, accumulator) in [
+        ("raw", &accumulators.raw_slot_account_accumulator),
+        ("processed", &accumulators.processed_slot_account_accumulator),
+    ] {
+        for (pubkey, (write_version, hash, account)) in accumulator.get(&slot).into_iter().flatten() {
+            accounts.push(AccountDump {
+                pubkey: pubkey.to_string(),
+                stage: stage.to_string(),
+                write_version: *write_version,
+                hash: hash.to_string(),
+                lamports: account.lamports,
+                owner: account.owner.to_string(),
+                executable: account.executable,
+                data_len: account.data.len(),
+            });
+        }
+    }
+    accounts.sort_by(|a, b| a.pubkey.cmp(&b.pubkey));
+
+    let mut votes = vec![];
+    for (stage, accumulator) in [
+        ("raw", &accumulators.raw_vote_accumulator),
+        ("processed", &accumulators.processed_vote_accumulator),
+    ] {
+        for (signature, vote) in accumulator.get(&slot).into_iter().flatten() {
+            votes.push(VoteDump {
+                signature: signature.to_string(),
+                stage: stage.to_string(),
+                vote_for_slot: vote.vote_for_slot,
+                vote_for_hash: vote.vote_for_hash.to_string(),
+            });
+        }
+    }
+    votes.sort_by(|a, b| a.signature.cmp(&b.signature));
+
+    Some(SlotDump {
+        summary: slot_summary(slot, accumulators),
+        accounts,
+        votes,
+        block: accumulators.block_accumulator.get(&slot).map(|block| BlockDump {
+            parent_bankhash: block.parent_bankhash.clone(),
+            blockhash: block.blockhash.clone(),
+            executed_transaction_count: block.executed_transaction_count,
+        }),
+    })
+}
+
+/// Apply an admin request to the state owned by `process_messages`
+pub fn execute_admin_request(
+    request: AdminRequest,
+    accumulators: &mut Accumulators,
+    monitored_accounts: &mut MonitoredAccounts,
+) -> AdminResponse {
+    let accounts = |list: AccountList, monitored_accounts: &mut MonitoredAccounts| {
+        let pubkeys = match list {
+            AccountList::Proof => &monitored_accounts.proof_accounts,
+            AccountList::Vote => &monitored_accounts.vote_accounts,
+        };
+        AdminResponse::Accounts {
+            pubkeys: pubkeys.iter().map(|pubkey| pubkey.to_string()).collect(),
+        }
+    };
+
+    let watched = |monitored_accounts: &MonitoredAccounts| {
+        let mut pubkeys: Vec<String> = monitored_accounts
+            .watched_accounts
+            .keys()
+            .map(|pubkey| pubkey.to_string())
+            .collect();
+        pubkeys.sort_unstable();
+        AdminResponse::Accounts { pubkeys }
+    };
+
+    match request {
+        AdminRequest::ListAccounts { list } => accounts(list, monitored_accounts),
+        AdminRequest::AddAccounts { list, pubkeys } => {
+            let pubkeys = match parse_pubkeys(&pubkeys) {
+                Ok(pubkeys) => pubkeys,
+                Err(message) => return AdminResponse::Error { message },
+            };
+            let current = match list {
+                AccountList::Proof => &mut monitored_accounts.proof_accounts,
+                AccountList::Vote => &mut monitored_accounts.vote_accounts,
+            };
+            for pubkey in pubkeys {
+                if !current.contains(&pubkey) {
+                    current.push(pubkey);
+                }
+            }
+            accounts(list, monitored_accounts)
+        }
+        AdminRequest::RemoveAccounts { list, pubkeys } => {
+            let pubkeys = match parse_pubkeys(&pubkeys) {
+                Ok(pubkeys) => pubkeys,
+                Err(message) => return AdminResponse::Error { message },
+            };
+            let current = match list {
+                AccountList::Proof => &mut monitored_accounts.proof_accounts,
+                AccountList::Vote => &mut monitored_accounts.vote_accounts,
+            };
+            current.retain(|pubkey| !pubkeys.contains(pubkey));
+            accounts(list, monitored_accounts)
+        }
+        AdminRequest::WatchAccounts { pubkeys } => {
+            let pubkeys = match parse_pubkeys(&pubkeys) {
+                Ok(pubkeys) => pubkeys,
+                Err(message) => return AdminResponse::Error { message },
+            };
+            let watched_accounts = &mut monitored_accounts.watched_accounts;
+            let new: HashSet<&Pubkey> = pubkeys
+                .iter()
+                .filter(|pubkey| !watched_accounts.contains_key(pubkey))
+                .collect();
+            if watched_accounts.len() + new.len() > monitored_accounts.max_watched_accounts {
+                return AdminResponse::Error {
+                    message: format!(
+                        "cannot watch more than {} accounts",
+                        monitored_accounts.max_watched_accounts
+                    ),
+                };
+            }
+            for pubkey in pubkeys {
+                *watched_accounts.entry(pubkey).or_insert(0) += 1;
+            }
+            watched(monitored_accounts)
+        }
+        AdminRequest::UnwatchAccounts { pubkeys } => {
+            let pubkeys = match parse_pubkeys(&pubkeys) {
+                Ok(pubkeys) => pubkeys,
+                Err(message) => return AdminResponse::Error { message },
+            };
+            for pubkey in pubkeys {
+                if let Some(count) = monitored_accounts.watched_accounts.get_mut(&pubkey) {
+                    *count -= 1;
+                    if *count == 0 {
+                        monitored_accounts.watched_accounts.remove(&pubkey);
+                    }
+                }
+            }
+            watched(monitored_accounts)
+        }
+        AdminRequest::ListSlots => AdminResponse::Slots {
+            slots: accumulators
+                .slots()
+                .into_iter()
+                .map(|slot| slot_summary(slot, accumulators))
+                .collect(),
+        },
+        AdminRequest::DumpSlot { slot } => AdminResponse::Slot {
+            dump: slot_dump(slot, accumulators),
+        },
+        AdminRequest::EvictSlots {
+            start_slot,
+            end_slot,
+        } => {
+            let slots = accumulators
+                .slots()
+                .into_iter()
+                .filter(|slot| (start_slot..=end_slot).contains(slot))
+                .filter(|slot| accumulators.evict(*slot))
+                .collect();
+            AdminResponse::Evicted { slots }
+        }
+    }
+}
+
+pub(crate) async fn forward_request(request: AdminRequest, geyser_sender: &Sender<GeyserMessage>) -> AdminResponse {
+    let (reply, mut response) = mpsc::unbounded_channel();
+    if geyser_sender
+        .send(GeyserMessage::AdminMessage(AdminCommand { request, reply }))
+        .is_err()
+    {
+        return AdminResponse::Error {
+            message: "plugin is shutting down".to_string(),
+        };
+    }
+    response.recv().await.unwrap_or_else(|| AdminResponse::Error {
+        message: "request was dropped by the plugin".to_string(),
+    })
+}
+
+async fn handle_admin_connection(
+    stream: UnixStream,
+    geyser_sender: Sender<GeyserMessage>,
+) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = tokio::io::BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<AdminRequest>(&line) {
+            Ok(request) => forward_request(request, &geyser_sender).await,
+            Err(e) => AdminResponse::Error {
+                message: format!("invalid request: {}", e),
+            },
+        };
+        let mut encoded = serde_json::to_vec(&response)?;
+        encoded.push(b'\n');
+        writer.write_all(&encoded).await?;
+    }
+    Ok(())
+}
+
+/// Serve admin requests on a Unix socket at `socket_path`, readable and writable by the
+/// validator's user only. Requests are handed to `process_messages` through `geyser_sender`.
+pub async fn run_admin_server<P: AsRef<Path>>(
+    socket_path: P,
+    geyser_sender: Sender<GeyserMessage>,
+) -> io::Result<()> {
+    let socket_path = socket_path.as_ref();
+    // A socket left behind by a previous run would make the bind fail
+    if socket_path.exists() {
+        std::fs::remove_file(socket_path)?;
+    }
+    let listener = UnixListener::bind(socket_path)?;
+    std::fs::set_permissions(socket_path, std::fs::Permissions::from_mode(0o600))?;
+
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                error!("Failed to accept admin connection: {:?}", e);
+                continue;
+            }
+        };
+        let geyser_sender = geyser_sender.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_admin_connection(stream, geyser_sender).await {
+                error!("Closing admin connection: {:?}", e);
+            }
+        });
+    }
+}
+
+/// Send a single request to the admin socket at `socket_path` and wait for the response
+pub fn send_admin_request<P: AsRef<Path>>(
+    socket_path: P,
+    request: &AdminRequest,
+) -> anyhow::Result<AdminResponse> {
+    let mut stream = std::os::unix::net::UnixStream::connect(socket_path)?;
+    let mut encoded = serde_json::to_vec(request)?;
+    encoded.push(b'\n');
+    stream.write_all(&encoded)?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    if line.is_empty() {
+        anyhow::bail!("admin socket closed without a response");
+    }
+    Ok(serde_json::from_str(&line)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use solana_sdk::hash::Hash;
+
+    use super::*;
+    use crate::types::{AccountInfo, BlockInfo};
+
+    fn accumulators_with_slots() -> Accumulators {
+        let mut accumulators = Accumulators::default();
+        let pubkey = Pubkey::new_unique();
+        for slot in [10, 11] {
+            let mut accounts = HashMap::new();
+            accounts.insert(
+                pubkey,
+                (
+                    1,
+                    Hash::new_unique(),
+                    AccountInfo {
+                        pubkey,
+                        slot,
+                        data: vec![0; 4],
+                        ..AccountInfo::default()
+                    },
+                ),
+            );
+            accumulators.processed_slot_account_accumulator.insert(slot, accounts);
+            accumulators.processed_transaction_accumulator.insert(slot, 3);
+        }
+        accumulators.raw_transaction_accumulator.insert(12, 1);
+        accumulators.block_accumulator.insert(
+            10,
+            BlockInfo {
+                slot: 10,
+                parent_slot: 9,
+                parent_bankhash: Hash::new_unique().to_string(),
+                blockhash: Hash::new_unique().to_string(),
+                executed_transaction_count: 2,
+            },
+        );
+        accumulators
+    }
+
+    #[test]
+    fn test_add_and_remove_accounts() {
+        let mut accumulators = Accumulators::default();
+        let mut monitored = MonitoredAccounts::default();
+        let a = Pubkey::new_unique().to_string();
+        let b = Pubkey::new_unique().to_string();
+
+        let response = execute_admin_request(
+            AdminRequest::AddAccounts {
+                list: AccountList::Proof,
+                pubkeys: vec![a.clone(), b.clone(), a.clone()],
+            },
+            &mut accumulators,
+            &mut monitored,
+        );
+        assert_eq!(
+            response,
+            AdminResponse::Accounts {
+                pubkeys: vec![a.clone(), b.clone()]
+            }
+        );
+        assert!(monitored.vote_accounts.is_empty());
+
+        execute_admin_request(
+            AdminRequest::RemoveAccounts {
+                list: AccountList::Proof,
+                pubkeys: vec![a],
+            },
+            &mut accumulators,
+            &mut monitored,
+        );
+        let response = execute_admin_request(
+            AdminRequest::ListAccounts {
+                list: AccountList::Proof,
+            },
+            &mut accumulators,
+            &mut monitored,
+        );
+        assert_eq!(response, AdminResponse::Accounts { pubkeys: vec![b] });
+
+        let response = execute_admin_request(
+            AdminRequest::AddAccounts {
+                list: AccountList::Vote,
+                pubkeys: vec!["not a pubkey".to_string()],
+            },
+            &mut accumulators,
+            &mut monitored,
+        );
+        assert!(matches!(response, AdminResponse::Error { .. }));
+        assert!(monitored.vote_accounts.is_empty());
+    }
+
+    #[test]
+    fn test_watch_accounts_counts_subscriptions() {
+        let mut accumulators = Accumulators::default();
+        let configured = Pubkey::new_unique();
+        let mut monitored = MonitoredAccounts {
+            proof_accounts: vec![configured],
+            ..MonitoredAccounts::default()
+        };
+        let watched = Pubkey::new_unique();
+        let mut watch = |request| execute_admin_request(request, &mut accumulators, &mut monitored);
+
+        // two subscriptions watch the same account, one of them also a configured account
+        watch(AdminRequest::WatchAccounts {
+            pubkeys: vec![watched.to_string()],
+        });
+        watch(AdminRequest::WatchAccounts {
+            pubkeys: vec![watched.to_string(), configured.to_string()],
+        });
+        let response = watch(AdminRequest::UnwatchAccounts {
+            pubkeys: vec![watched.to_string(), configured.to_string()],
+        });
+        assert_eq!(
+            response,
+            AdminResponse::Accounts {
+                pubkeys: vec![watched.to_string()]
+            }
+        );
+        assert_eq!(monitored.proven_accounts(), vec![configured, watched]);
+
+        execute_admin_request(
+            AdminRequest::UnwatchAccounts {
+                pubkeys: vec![watched.to_string()],
+            },
+            &mut accumulators,
+            &mut monitored,
+        );
+        // configured accounts stay proven once nobody watches them
+        assert_eq!(monitored.proven_accounts(), vec![configured]);
+    }
+
+    #[test]
+    fn test_watch_accounts_limit() {
+        let mut accumulators = Accumulators::default();
+        let mut monitored = MonitoredAccounts {
+            max_watched_accounts: 2,
+            ..MonitoredAccounts::default()
+        };
+        let (first, second) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut watch = |pubkeys: &[Pubkey]| {
+            execute_admin_request(
+                AdminRequest::WatchAccounts {
+                    pubkeys: pubkeys.iter().map(|pubkey| pubkey.to_string()).collect(),
+                },
+                &mut accumulators,
+                &mut monitored,
+            )
+        };
+
+        assert!(matches!(watch(&[first]), AdminResponse::Accounts { .. }));
+        assert_eq!(
+            watch(&[second, Pubkey::new_unique()]),
+            AdminResponse::Error {
+                message: "cannot watch more than 2 accounts".to_string()
+            }
+        );
+        // accounts that are already watched don't count again
+        assert!(matches!(watch(&[first, second]), AdminResponse::Accounts { .. }));
+        assert_eq!(monitored.watched_accounts.len(), 2);
+    }
+
+    #[test]
+    fn test_dump_and_evict_slots() {
+        let mut accumulators = accumulators_with_slots();
+        let mut monitored = MonitoredAccounts::default();
+
+        let AdminResponse::Slots { slots } =
+            execute_admin_request(AdminRequest::ListSlots, &mut accumulators, &mut monitored)
+        else {
+            panic!("expected a slot list");
+        };
+        assert_eq!(slots.iter().map(|s| s.slot).collect::<Vec<_>>(), vec![10, 11, 12]);
+        assert_eq!(slots[0].processed_signatures, Some(3));
+        assert!(slots[0].has_block);
+        assert_eq!(slots[2].raw_signatures, Some(1));
+
+        let AdminResponse::Slot { dump: Some(dump) } = execute_admin_request(
+            AdminRequest::DumpSlot { slot: 10 },
+            &mut accumulators,
+            &mut monitored,
+        ) else {
+            panic!("expected a slot dump");
+        };
+        assert_eq!(dump.accounts.len(), 1);
+        assert_eq!(dump.accounts[0].stage, "processed");
+        assert_eq!(dump.accounts[0].data_len, 4);
+        assert_eq!(dump.block.unwrap().executed_transaction_count, 2);
+
+        let response = execute_admin_request(
+            AdminRequest::EvictSlots {
+                start_slot: 0,
+                end_slot: 11,
+            },
+            &mut accumulators,
+            &mut monitored,
+        );
+        assert_eq!(response, AdminResponse::Evicted { slots: vec![10, 11] });
+        assert_eq!(accumulators.slots(), vec![12]);
+        assert_eq!(
+            execute_admin_request(
+                AdminRequest::DumpSlot { slot: 10 },
+                &mut accumulators,
+                &mut monitored
+            ),
+            AdminResponse::Slot { dump: None }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_requests_over_socket() {
+        let socket_path = std::env::temp_dir().join(format!(
+            "account_proof_admin_{}_{}.sock",
+            std::process::id(),
+            rand::random::<u64>()
+        ));
+        let (geyser_sender, geyser_receiver) = crossbeam_channel::unbounded();
+        tokio::spawn(run_admin_server(socket_path.clone(), geyser_sender));
+
+        // Stands in for `process_messages`
+        std::thread::spawn(move || {
+            let mut accumulators = Accumulators::default();
+            let mut monitored = MonitoredAccounts::default();
+            while let Ok(GeyserMessage::AdminMessage(command)) = geyser_receiver.recv() {
+                let response =
+                    execute_admin_request(command.request, &mut accumulators, &mut monitored);
+                command.reply.send(response).unwrap();
+            }
+        });
+
+        while !socket_path.exists() {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        let pubkey = Pubkey::new_unique().to_string();
+        let path = socket_path.clone();
+        let request_pubkey = pubkey.clone();
+        let response = tokio::task::spawn_blocking(move || {
+            send_admin_request(
+                &path,
+                &AdminRequest::AddAccounts {
+                    list: AccountList::Vote,
+                    pubkeys: vec![request_pubkey],
+                },
+            )
+        })
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            response,
+            AdminResponse::Accounts {
+                pubkeys: vec![pubkey]
+            }
+        );
+
+        std::fs::remove_file(socket_path).unwrap();
+    }
+}
@@ -0,0 +1,339 @@
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_sdk::hash::Hash;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::config::ArchiveConfig;
+use crate::types::{AccountDeltaProof, Update};
+
+const UPDATES_TREE: &str = "updates";
+const PUBKEY_INDEX_TREE: &str = "pubkey_index";
+const LEAVES_TREE: &str = "leaves";
+
+/// Retention is enforced every this many inserted slots rather than on every insert
+const PRUNE_INTERVAL: u64 = 64;
+
+/// Embedded on-disk store that keeps every emitted `Update`, so that proofs for slots confirmed
+/// while no client was connected can still be served later.
+///
+/// * `updates` maps a big endian slot to the borsh encoded `Update`
+/// * `pubkey_index` maps `pubkey | slot` to nothing, for every pubkey with a proof in that slot
+/// * `leaves` optionally maps a slot to the full sorted `(Pubkey, Hash)` leaf set of its accounts delta tree
+#[derive(Clone, Debug)]
+pub struct ProofArchive {
+    db: sled::Db,
+    updates: sled::Tree,
+    pubkey_index: sled::Tree,
+    leaves: sled::Tree,
+    retention_slots: Option<u64>,
+    store_leaves: bool,
+    last_pruned_slot: Arc<AtomicU64>,
+}
+
+/// A single account proof retrieved from the archive together with the bank hash it proves against
+#[derive(Clone, Debug)]
+pub struct ArchivedProof {
+    pub slot: u64,
+    pub bank_hash: Hash,
+    pub proof: AccountDeltaProof,
+}
+
+fn slot_key(slot: u64) -> [u8; 8] {
+    slot.to_be_bytes()
+}
+
+fn pubkey_slot_key(pubkey: &Pubkey, slot: u64) -> [u8; 40] {
+    let mut key = [0u8; 40];
+    key[..32].copy_from_slice(pubkey.as_ref());
+    key[32..].copy_from_slice(&slot.to_be_bytes());
+    key
+}
+
+fn slot_from_key(key: &[u8]) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&key[key.len() - 8..]);
+    u64::from_be_bytes(bytes)
+}
+
+impl ProofArchive {
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        retention_slots: Option<u64>,
+        store_leaves: bool,
+    ) -> anyhow::Result<Self> {
+        let db = sled::open(path)?;
+        Ok(ProofArchive {
+            updates: db.open_tree(UPDATES_TREE)?,
+            pubkey_index: db.open_tree(PUBKEY_INDEX_TREE)?,
+            leaves: db.open_tree(LEAVES_TREE)?,
+            db,
+            retention_slots,
+            store_leaves,
+            last_pruned_slot: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    pub fn from_config(config: &ArchiveConfig) -> anyhow::Result<Self> {
+        Self::open(&config.path, config.retention_slots, config.store_leaves)
+    }
+
+    /// Persist an update and, if enabled, the leaf set its accounts delta hash was computed from
+    pub fn insert(&self, update: &Update, leaves: &[(Pubkey, Hash)]) -> anyhow::Result<()> {
+        let slot = update.slot;
+        self.updates.insert(slot_key(slot), update.try_to_vec()?)?;
+
+        let mut index_batch = sled::Batch::default();
+        for proof in &update.proof.proofs {
+            index_batch.insert(&pubkey_slot_key(&proof.0, slot)[..], &[][..]);
+        }
+        self.pubkey_index.apply_batch(index_batch)?;
+
+        if self.store_leaves {
+            self.leaves.insert(slot_key(slot), leaves.to_vec().try_to_vec()?)?;
+        }
+
+        if slot >= self.last_pruned_slot.load(Ordering::Relaxed) + PRUNE_INTERVAL {
+            self.prune(slot)?;
+            self.last_pruned_slot.store(slot, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    pub fn get_update(&self, slot: u64) -> anyhow::Result<Option<Update>> {
+        match self.updates.get(slot_key(slot))? {
+            Some(bytes) => Ok(Some(Update::try_from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The first `limit` archived updates with a slot in `range`, oldest first
+    pub fn updates_in_range(
+        &self,
+        range: RangeInclusive<u64>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Update>> {
+        let start = slot_key(*range.start());
+        let end = slot_key(*range.end());
+        self.updates
+            .range(start..=end)
+            .take(limit)
+            .map(|entry| {
+                let (_, bytes) = entry?;
+                Ok(Update::try_from_slice(&bytes)?)
+            })
+            .collect()
+    }
+
+    /// Slots in `range` for which the archive holds a proof for `pubkey`
+    pub fn slots_for_pubkey(
+        &self,
+        pubkey: &Pubkey,
+        range: RangeInclusive<u64>,
+    ) -> anyhow::Result<Vec<u64>> {
+        let start = pubkey_slot_key(pubkey, *range.start());
+        let end = pubkey_slot_key(pubkey, *range.end());
+        self.pubkey_index
+            .range(start..=end)
+            .map(|entry| Ok(slot_from_key(&entry?.0)))
+            .collect()
+    }
+
+    /// Proofs for `pubkey` in every archived slot within `range`
+    pub fn proofs_for_pubkey(
+        &self,
+        pubkey: &Pubkey,
+        range: RangeInclusive<u64>,
+    ) -> anyhow::Result<Vec<ArchivedProof>> {
+        let mut proofs = vec![];
+        for slot in self.slots_for_pubkey(pubkey, range)? {
+            let Some(update) = self.get_update(slot)? else {
+                continue;
+            };
+            if let Some(proof) = update.proof.proofs.iter().find(|p| &p.0 == pubkey) {
+                proofs.push(ArchivedProof {
+                    slot,
+                    bank_hash: update.root,
+                    proof: proof.clone(),
+                });
+            }
+        }
+        Ok(proofs)
+    }
+
+    /// The sorted leaf set for a slot, if leaves are being archived
+    pub fn leaves(&self, slot: u64) -> anyhow::Result<Option<Vec<(Pubkey, Hash)>>> {
+        match self.leaves.get(slot_key(slot))? {
+            Some(bytes) => Ok(Some(Vec::<(Pubkey, Hash)>::try_from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn oldest_slot(&self) -> anyhow::Result<Option<u64>> {
+        Ok(self.updates.first()?.map(|(key, _)| slot_from_key(&key)))
+    }
+
+    pub fn latest_slot(&self) -> anyhow::Result<Option<u64>> {
+        Ok(self.updates.last()?.map(|(key, _)| slot_from_key(&key)))
+    }
+
+    /// Delete everything outside the window of `retention_slots` slots ending at `latest_slot`.
+    /// Returns the number of slots removed.
+    pub fn prune(&self, latest_slot: u64) -> anyhow::Result<usize> {
+        let Some(retention_slots) = self.retention_slots else {
+            return Ok(0);
+        };
+        let cutoff = latest_slot.saturating_sub(retention_slots);
+        let mut removed = 0;
+        for entry in self.updates.range(..=slot_key(cutoff)) {
+            let (key, bytes) = entry?;
+            let slot = slot_from_key(&key);
+            let update = Update::try_from_slice(&bytes)?;
+            for proof in &update.proof.proofs {
+                self.pubkey_index.remove(pubkey_slot_key(&proof.0, slot))?;
+            }
+            self.leaves.remove(&key)?;
+            self.updates.remove(&key)?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    pub fn flush(&self) -> anyhow::Result<()> {
+        self.db.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{AccountInfo, BankHashProof, Data, Proof};
+
+    fn temp_archive(
+        retention_slots: Option<u64>,
+        store_leaves: bool,
+    ) -> (ProofArchive, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!(
+            "proof_archive_{}_{}",
+            std::process::id(),
+            rand::random::<u64>()
+        ));
+        let archive = ProofArchive::open(&path, retention_slots, store_leaves).unwrap();
+        (archive, path)
+    }
+
+    fn update_for_slot(slot: u64, pubkeys: &[Pubkey]) -> Update {
+        let proofs = pubkeys
+            .iter()
+            .map(|pubkey| {
+                AccountDeltaProof(
+                    *pubkey,
+                    (
+                        Data {
+                            pubkey: *pubkey,
+                            hash: Hash::new_unique(),
+                            account: AccountInfo {
+                                pubkey: *pubkey,
+                                slot,
+                                ..AccountInfo::default()
+                            },
+                        },
+                        Proof {
+                            path: vec![],
+                            siblings: vec![],
+                        },
+                    ),
+                )
+            })
+            .collect();
+        Update {
+            slot,
+            root: Hash::new_unique(),
+            proof: BankHashProof {
+                proofs,
+                non_inclusion_proofs: vec![],
+                num_sigs: 0,
+                account_delta_root: Hash::new_unique(),
+                parent_bankhash: Hash::new_unique(),
+                blockhash: Hash::new_unique(),
+            },
+            votes: vec![],
+            copy_preimages: vec![],
+            transaction_proofs: vec![],
+        }
+    }
+
+    #[test]
+    fn test_query_by_slot_and_pubkey() {
+        let (archive, path) = temp_archive(None, true);
+        let a = Pubkey::new_unique();
+        let b = Pubkey::new_unique();
+
+        for slot in 1..=10 {
+            let pubkeys = if slot % 2 == 0 { vec![a, b] } else { vec![a] };
+            let leaves: Vec<(Pubkey, Hash)> =
+                pubkeys.iter().map(|p| (*p, Hash::new_unique())).collect();
+            archive.insert(&update_for_slot(slot, &pubkeys), &leaves).unwrap();
+        }
+
+        assert_eq!(archive.get_update(7).unwrap().unwrap().slot, 7);
+        assert!(archive.get_update(11).unwrap().is_none());
+
+        let slots: Vec<u64> = archive
+            .updates_in_range(3..=5, usize::MAX)
+            .unwrap()
+            .iter()
+            .map(|u| u.slot)
+            .collect();
+        assert_eq!(slots, vec![3, 4, 5]);
+        let slots: Vec<u64> = archive
+            .updates_in_range(3..=u64::MAX, 2)
+            .unwrap()
+            .iter()
+            .map(|u| u.slot)
+            .collect();
+        assert_eq!(slots, vec![3, 4]);
+
+        assert_eq!(
+            archive.slots_for_pubkey(&b, 0..=u64::MAX).unwrap(),
+            vec![2, 4, 6, 8, 10]
+        );
+        assert_eq!(archive.slots_for_pubkey(&a, 9..=10).unwrap(), vec![9, 10]);
+
+        let proofs = archive.proofs_for_pubkey(&b, 5..=8).unwrap();
+        assert_eq!(proofs.iter().map(|p| p.slot).collect::<Vec<_>>(), vec![6, 8]);
+        assert!(proofs.iter().all(|p| p.proof.0 == b));
+        assert_eq!(proofs[0].bank_hash, archive.get_update(6).unwrap().unwrap().root);
+
+        assert_eq!(archive.leaves(4).unwrap().unwrap().len(), 2);
+        assert_eq!(archive.oldest_slot().unwrap(), Some(1));
+        assert_eq!(archive.latest_slot().unwrap(), Some(10));
+
+        drop(archive);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_retention_prunes_updates_and_indexes() {
+        let (archive, path) = temp_archive(Some(5), false);
+        let a = Pubkey::new_unique();
+        for slot in 1..=20 {
+            archive.insert(&update_for_slot(slot, &[a]), &[]).unwrap();
+        }
+        assert_eq!(archive.prune(20).unwrap(), 15);
+        assert_eq!(archive.oldest_slot().unwrap(), Some(16));
+        assert_eq!(
+            archive.slots_for_pubkey(&a, 0..=u64::MAX).unwrap(),
+            (16..=20).collect::<Vec<_>>()
+        );
+        // leaves are not stored unless enabled
+        assert!(archive.leaves(20).unwrap().is_none());
+
+        drop(archive);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+}
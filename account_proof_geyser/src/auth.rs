@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+use crate::config::{ApiKeyConfig, Config};
+
+#[derive(Debug, Clone)]
+struct ApiKey {
+    name: String,
+    key: String,
+    max_subscriptions: usize,
+}
+
+/// Checks the bearer token presented in a client's handshake and enforces the per-client
+/// subscription limit. Without configured API keys every client is accepted and identified
+/// by its IP address.
+#[derive(Debug)]
+pub struct AccessControl {
+    api_keys: Vec<ApiKey>,
+    default_max_subscriptions: usize,
+    active: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccessError {
+    MissingToken,
+    InvalidToken,
+    SubscriptionLimitReached { client: String, limit: usize },
+}
+
+impl std::fmt::Display for AccessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AccessError::MissingToken => write!(f, "an API key is required"),
+            AccessError::InvalidToken => write!(f, "invalid API key"),
+            AccessError::SubscriptionLimitReached { client, limit } => write!(
+                f,
+                "client {} already has the maximum of {} subscriptions",
+                client, limit
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AccessError {}
+
+/// Held for as long as a client's subscription is open. Dropping it frees the slot.
+#[derive(Debug)]
+pub struct Subscription {
+    client: String,
+    active: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl Subscription {
+    pub fn client(&self) -> &str {
+        &self.client
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let mut active = self.active.lock().unwrap();
+        if let Some(count) = active.get_mut(&self.client) {
+            *count -= 1;
+            if *count == 0 {
+                active.remove(&self.client);
+            }
+        }
+    }
+}
+
+impl AccessControl {
+    pub fn new(api_keys: &[ApiKeyConfig], default_max_subscriptions: usize) -> Self {
+        AccessControl {
+            api_keys: api_keys
+                .iter()
+                .map(|k| ApiKey {
+                    name: k.name.clone(),
+                    key: k.key.clone(),
+                    max_subscriptions: k.max_subscriptions.unwrap_or(default_max_subscriptions),
+                })
+                .collect(),
+            default_max_subscriptions,
+            active: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(&config.api_keys, config.max_subscriptions_per_client)
+    }
+
+    pub fn requires_token(&self) -> bool {
+        !self.api_keys.is_empty()
+    }
+
+    /// Authenticate a client and reserve one of its subscription slots
+    pub fn admit(&self, token: Option<&str>, peer: IpAddr) -> Result<Subscription, AccessError> {
+        let (client, limit) = if self.requires_token() {
+            let token = token.ok_or(AccessError::MissingToken)?;
+            let api_key = self
+                .api_keys
+                .iter()
+                .find(|k| constant_time_eq(k.key.as_bytes(), token.as_bytes()))
+                .ok_or(AccessError::InvalidToken)?;
+            (api_key.name.clone(), api_key.max_subscriptions)
+        } else {
+            (peer.to_string(), self.default_max_subscriptions)
+        };
+
+        let mut active = self.active.lock().unwrap();
+        let count = active.entry(client.clone()).or_insert(0);
+        if *count >= limit {
+            return Err(AccessError::SubscriptionLimitReached { client, limit });
+        }
+        *count += 1;
+
+        Ok(Subscription {
+            client,
+            active: self.active.clone(),
+        })
+    }
+
+    /// Number of open subscriptions for a client
+    pub fn active_subscriptions(&self, client: &str) -> usize {
+        self.active.lock().unwrap().get(client).copied().unwrap_or(0)
+    }
+}
+
+/// Compare secrets without short-circuiting on the first differing byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    const PEER: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    #[test]
+    fn test_open_access_limits_per_ip() {
+        let access = AccessControl::new(&[], 2);
+        let first = access.admit(None, PEER).unwrap();
+        let _second = access.admit(Some("ignored"), PEER).unwrap();
+        assert!(matches!(
+            access.admit(None, PEER),
+            Err(AccessError::SubscriptionLimitReached { limit: 2, .. })
+        ));
+
+        drop(first);
+        assert_eq!(access.active_subscriptions("127.0.0.1"), 1);
+        assert!(access.admit(None, PEER).is_ok());
+    }
+
+    #[test]
+    fn test_api_keys() {
+        let access = AccessControl::new(
+            &[
+                ApiKeyConfig {
+                    name: "alice".to_string(),
+                    key: "alice-secret".to_string(),
+                    max_subscriptions: None,
+                },
+                ApiKeyConfig {
+                    name: "bob".to_string(),
+                    key: "bob-secret".to_string(),
+                    max_subscriptions: Some(1),
+                },
+            ],
+            4,
+        );
+        assert_eq!(access.admit(None, PEER).unwrap_err(), AccessError::MissingToken);
+        assert_eq!(
+            access.admit(Some("alice-secre"), PEER).unwrap_err(),
+            AccessError::InvalidToken
+        );
+
+        let alice = access.admit(Some("alice-secret"), PEER).unwrap();
+        assert_eq!(alice.client(), "alice");
+
+        let _bob = access.admit(Some("bob-secret"), PEER).unwrap();
+        assert!(matches!(
+            access.admit(Some("bob-secret"), PEER),
+            Err(AccessError::SubscriptionLimitReached { limit: 1, .. })
+        ));
+        // alice's limit is independent of bob's
+        assert!(access.admit(Some("alice-secret"), PEER).is_ok());
+    }
+}
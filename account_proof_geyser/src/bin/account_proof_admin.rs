@@ -0,0 +1,85 @@
+use std::path::PathBuf;
+
+use account_proof_geyser::admin::{send_admin_request, AccountList, AdminRequest, AdminResponse};
+use clap::{Parser, Subcommand};
+
+/// Inspect and reconfigure a running account proof geyser plugin through its admin socket
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Path of the plugin's `admin_socket_path`
+    #[arg(short, long)]
+    socket: PathBuf,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List the accounts proofs are generated for, or the tracked vote accounts with --vote
+    ListAccounts {
+        #[arg(long)]
+        vote: bool,
+    },
+    /// Start monitoring accounts
+    AddAccounts {
+        #[arg(long)]
+        vote: bool,
+        #[arg(required = true)]
+        pubkeys: Vec<String>,
+    },
+    /// Stop monitoring accounts
+    RemoveAccounts {
+        #[arg(long)]
+        vote: bool,
+        #[arg(required = true)]
+        pubkeys: Vec<String>,
+    },
+    /// Summarise every slot held in the accumulators
+    ListSlots,
+    /// Dump the accumulated state of a slot
+    DumpSlot { slot: u64 },
+    /// Drop the accumulated state of the slots from START_SLOT to END_SLOT (defaults to START_SLOT)
+    EvictSlots { start_slot: u64, end_slot: Option<u64> },
+}
+
+fn account_list(vote: bool) -> AccountList {
+    if vote {
+        AccountList::Vote
+    } else {
+        AccountList::Proof
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let request = match cli.command {
+        Command::ListAccounts { vote } => AdminRequest::ListAccounts {
+            list: account_list(vote),
+        },
+        Command::AddAccounts { vote, pubkeys } => AdminRequest::AddAccounts {
+            list: account_list(vote),
+            pubkeys,
+        },
+        Command::RemoveAccounts { vote, pubkeys } => AdminRequest::RemoveAccounts {
+            list: account_list(vote),
+            pubkeys,
+        },
+        Command::ListSlots => AdminRequest::ListSlots,
+        Command::DumpSlot { slot } => AdminRequest::DumpSlot { slot },
+        Command::EvictSlots {
+            start_slot,
+            end_slot,
+        } => AdminRequest::EvictSlots {
+            start_slot,
+            end_slot: end_slot.unwrap_or(start_slot),
+        },
+    };
+
+    let response = send_admin_request(&cli.socket, &request)?;
+    if let AdminResponse::Error { message } = response {
+        anyhow::bail!(message);
+    }
+    println!("{}", serde_json::to_string_pretty(&response)?);
+    Ok(())
+}
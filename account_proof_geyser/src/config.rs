@@ -4,6 +4,8 @@ use std::{fs, io};
 
 use serde::Deserialize;
 
+use crate::types::DEFAULT_MAX_WATCHED_ACCOUNTS;
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct Config {
@@ -11,6 +13,99 @@ pub struct Config {
     pub account_list: Vec<String>,
     pub bind_address: SocketAddr,
     pub vote_accounts: Vec<String>,
+    /// Programs whose accounts are always proven when modified, e.g. the `copy` program so that
+    /// every per-signer or sharded copy PDA is covered
+    #[serde(default)]
+    pub copy_programs: Vec<String>,
+    /// Number of recent updates kept for clients that resume from a slot. 0 disables replay.
+    #[serde(default = "default_replay_buffer_size")]
+    pub replay_buffer_size: usize,
+    /// Optional file that backs the replay buffer so it survives validator restarts
+    #[serde(default)]
+    pub replay_buffer_path: Option<String>,
+    /// Serve the proof stream over TLS with this certificate and key. Plaintext when absent.
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    /// API keys accepted during the stream handshake. Every client is accepted when empty.
+    #[serde(default)]
+    pub api_keys: Vec<ApiKeyConfig>,
+    /// Concurrent subscriptions allowed per API key, or per IP address when no keys are configured
+    #[serde(default = "default_max_subscriptions_per_client")]
+    pub max_subscriptions_per_client: usize,
+    /// Most accounts subscribers may have registered with `watch_accounts` at once, across every
+    /// connection
+    #[serde(default = "default_max_watched_accounts")]
+    pub max_watched_accounts: usize,
+    /// Persist every emitted update to an on-disk archive
+    #[serde(default)]
+    pub archive: Option<ArchiveConfig>,
+    /// Serve Prometheus metrics and a health check over HTTP
+    #[serde(default)]
+    pub metrics: Option<MetricsConfig>,
+    /// Unix socket for the local admin interface (see the `account-proof-admin` binary)
+    #[serde(default)]
+    pub admin_socket_path: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// Address for the `/metrics` and `/health` endpoints
+    pub bind_address: SocketAddr,
+    /// `/health` fails once the latest emitted update is more than this many slots behind the
+    /// latest confirmed slot
+    #[serde(default = "default_max_emission_lag_slots")]
+    pub max_emission_lag_slots: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArchiveConfig {
+    /// Directory of the archive database
+    pub path: String,
+    /// Number of most recent slots to keep. Everything is kept when absent.
+    #[serde(default)]
+    pub retention_slots: Option<u64>,
+    /// Also store the full `(Pubkey, Hash)` leaf set of every slot's accounts delta tree
+    #[serde(default)]
+    pub store_leaves: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM file with the server certificate chain
+    pub cert_path: String,
+    /// PEM file with the server private key (PKCS#8, RSA or SEC1)
+    pub key_path: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKeyConfig {
+    /// Name of the client, used to track its subscriptions and in logs
+    pub name: String,
+    /// Secret the client presents as its bearer token
+    pub key: String,
+    /// Overrides `max_subscriptions_per_client` for this client
+    #[serde(default)]
+    pub max_subscriptions: Option<usize>,
+}
+
+fn default_replay_buffer_size() -> usize {
+    512
+}
+
+fn default_max_subscriptions_per_client() -> usize {
+    8
+}
+
+fn default_max_watched_accounts() -> usize {
+    DEFAULT_MAX_WATCHED_ACCOUNTS
+}
+
+fn default_max_emission_lag_slots() -> u64 {
+    32
 }
 
 #[derive(Debug)]
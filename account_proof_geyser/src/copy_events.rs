@@ -0,0 +1,252 @@
+use std::str::FromStr;
+
+use anchor_lang::{AccountDeserialize, AnchorDeserialize, Discriminator};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use copy::{digest_accumulator, CopyAccount, CopyEvent};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::types::{AccountInfo, CopyPreimage, CopyPreimages};
+
+/// Prefix of the log lines that carry Anchor events
+pub const PROGRAM_DATA: &str = "Program data: ";
+
+/// Decode the `CopyEvent`s that one of `copy_programs` logged in the log messages of a
+/// transaction, in the order they were emitted. Other log lines and events are skipped, as are
+/// events logged by any other program of the transaction, which could otherwise forge them.
+pub fn decode_copy_events<S: AsRef<str>>(logs: &[S], copy_programs: &[Pubkey]) -> Vec<CopyEvent> {
+    // programs being executed, innermost last
+    let mut invoked: Vec<Pubkey> = vec![];
+    let mut events = vec![];
+    for log in logs {
+        let log = log.as_ref();
+        if let Some(data) = log.strip_prefix(PROGRAM_DATA) {
+            if !matches!(invoked.last(), Some(program) if copy_programs.contains(program)) {
+                continue;
+            }
+            if let Some(event) = decode_copy_event(data) {
+                events.push(event);
+            }
+        } else if let Some(invoke) = log.strip_prefix("Program ") {
+            // only the runtime logs a program id there, program logs start with `Program log:`
+            let mut words = invoke.split_whitespace();
+            let Some(Ok(program)) = words.next().map(Pubkey::from_str) else {
+                continue;
+            };
+            match words.next() {
+                Some("invoke") => invoked.push(program),
+                Some("success") | Some("failed:") => {
+                    invoked.pop();
+                }
+                _ => {}
+            }
+        }
+    }
+    events
+}
+
+fn decode_copy_event(data: &str) -> Option<CopyEvent> {
+    let data = STANDARD.decode(data).ok()?;
+    let (discriminator, mut event) = (data.get(..8)?, data.get(8..)?);
+    if discriminator != CopyEvent::discriminator() {
+        return None;
+    }
+    CopyEvent::deserialize(&mut event).ok()
+}
+
+/// Fold `hashes` onto `digest` in order, the way the copy program accumulates them. Without a
+/// digest the first hash starts it, as for the first copy of a slot.
+pub fn accumulate_digest<I: IntoIterator<Item = [u8; 32]>>(
+    digest: Option<[u8; 32]>,
+    hashes: I,
+) -> Option<[u8; 32]> {
+    hashes.into_iter().fold(digest, |digest, hash| {
+        Some(match digest {
+            None => hash,
+            Some(digest) => digest_accumulator(&digest, &hash),
+        })
+    })
+}
+
+/// Recompute the digest of `account` from its entries and the copies rolled over before them
+pub fn entries_digest(account: &CopyAccount) -> Option<[u8; 32]> {
+    let rolled_over = (account.rolled_over > 0).then_some(account.rolled_over_digest);
+    accumulate_digest(rolled_over, account.entries.iter().map(|entry| entry.hash))
+}
+
+/// Collect the hashes accumulated into `copy_account` during `slot` from the copy events of the
+/// slot's transactions, keyed by their index in the block. Fails when an event is missing, e.g.
+/// because the logs of a transaction were truncated.
+pub fn assemble_copy_preimages(
+    transactions: &[(usize, Vec<CopyEvent>)],
+    copy_account: &Pubkey,
+    slot: u64,
+) -> anyhow::Result<CopyPreimages> {
+    let mut transactions: Vec<&(usize, Vec<CopyEvent>)> = transactions.iter().collect();
+    transactions.sort_by_key(|(index, _)| *index);
+
+    let mut preimages = vec![];
+    for event in transactions
+        .into_iter()
+        .flat_map(|(_, events)| events)
+        .filter(|event| event.copy_account == *copy_account && event.slot == slot)
+    {
+        if event.position as usize != preimages.len() {
+            anyhow::bail!(
+                "copy event {} for {} in slot {} is missing",
+                preimages.len(),
+                copy_account,
+                slot
+            );
+        }
+        preimages.push(CopyPreimage::from(event));
+    }
+    if preimages.is_empty() {
+        anyhow::bail!("no copy events for {} in slot {}", copy_account, slot);
+    }
+    Ok(CopyPreimages {
+        copy_account: *copy_account,
+        preimages,
+    })
+}
+
+/// Check that `preimages` fold into the digest of the copy account `account` as of `slot`
+pub fn verify_copy_preimages(
+    preimages: &CopyPreimages,
+    account: &AccountInfo,
+    slot: u64,
+) -> anyhow::Result<()> {
+    if account.pubkey != preimages.copy_account {
+        anyhow::bail!(
+            "preimages are for {} but the account is {}",
+            preimages.copy_account,
+            account.pubkey
+        );
+    }
+    let copy_account = CopyAccount::try_deserialize(&mut account.data.as_slice())?;
+    check_copy_digest(preimages, &copy_account, slot)
+}
+
+/// Check that `preimages` fold into the digest `copy_account` holds for `slot`
+pub fn check_copy_digest(
+    preimages: &CopyPreimages,
+    copy_account: &CopyAccount,
+    slot: u64,
+) -> anyhow::Result<()> {
+    if copy_account.slot != slot {
+        anyhow::bail!(
+            "copy account holds the digest of slot {} instead of {}",
+            copy_account.slot,
+            slot
+        );
+    }
+    if preimages.digest() != Some(copy_account.digest) {
+        anyhow::bail!("preimages don't match the digest of the copy account");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use anchor_lang::{AccountSerialize, Event};
+
+    use super::*;
+
+    fn copies(copy_account: Pubkey, slot: u64, count: usize) -> (AccountInfo, Vec<CopyEvent>) {
+        let mut account = CopyAccount::default();
+        let events = (0..count)
+            .map(|i| {
+                let position = account
+                    .accumulate_hash(&Pubkey::new_unique(), &[i as u8; 32], None, slot)
+                    .unwrap();
+                account.copy_event(copy_account, position)
+            })
+            .collect();
+        let mut data = vec![];
+        account.try_serialize(&mut data).unwrap();
+        let account = AccountInfo {
+            pubkey: copy_account,
+            owner: copy::id(),
+            data,
+            slot,
+            ..AccountInfo::default()
+        };
+        (account, events)
+    }
+
+    fn log(event: &CopyEvent) -> String {
+        format!("{}{}", PROGRAM_DATA, STANDARD.encode(event.data()))
+    }
+
+    #[test]
+    fn test_decode_only_events_logged_by_the_copy_program() {
+        let (_, events) = copies(Pubkey::new_unique(), 5, 2);
+        let (_, forged) = copies(Pubkey::new_unique(), 5, 1);
+        let caller = Pubkey::new_unique();
+        let logs = vec![
+            format!("Program {} invoke [1]", caller),
+            "Program log: Instruction: Increment".to_string(),
+            // logged by the caller itself
+            log(&forged[0]),
+            format!("Program {} invoke [2]", copy::id()),
+            log(&events[0]),
+            "Program data: not base64".to_string(),
+            format!("Program {} consumed 9000 of 190000 compute units", copy::id()),
+            format!("Program {} success", copy::id()),
+            // a program can't pass its own logs off as the runtime's
+            format!("Program log: {} invoke [2]", copy::id()),
+            log(&forged[0]),
+            format!("Program {} success", caller),
+            format!("Program {} invoke [1]", copy::id()),
+            log(&events[1]),
+            format!("Program {} success", copy::id()),
+        ];
+        assert_eq!(decode_copy_events(&logs, &[copy::id()]), events);
+        assert!(decode_copy_events(&logs, &[caller, Pubkey::new_unique()]).contains(&forged[0]));
+        assert!(decode_copy_events(&logs, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_entries_digest_after_rolling_over() {
+        for count in [1, 3, copy::MAX_COPY_ENTRIES + 5] {
+            let copy_account = Pubkey::new_unique();
+            let (account, events) = copies(copy_account, 5, count);
+            let copy = CopyAccount::try_deserialize(&mut account.data.as_slice()).unwrap();
+            assert_eq!(copy.entries.len(), count.min(copy::MAX_COPY_ENTRIES));
+            assert_eq!(entries_digest(&copy), Some(copy.digest));
+
+            // every event is still needed to open the digest
+            let preimages = assemble_copy_preimages(&[(0, events)], &copy_account, 5).unwrap();
+            assert_eq!(preimages.preimages.len(), count);
+            check_copy_digest(&preimages, &copy, 5).unwrap();
+        }
+        assert_eq!(entries_digest(&CopyAccount::default()), None);
+    }
+
+    #[test]
+    fn test_assemble_copy_preimages() {
+        let copy_account = Pubkey::new_unique();
+        let (account, events) = copies(copy_account, 5, 3);
+        let (_, other) = copies(Pubkey::new_unique(), 5, 1);
+
+        // transactions are notified out of order
+        let transactions = vec![
+            (7, vec![events[2]]),
+            (2, vec![events[0], other[0], events[1]]),
+        ];
+        let preimages = assemble_copy_preimages(&transactions, &copy_account, 5).unwrap();
+        assert_eq!(preimages.preimages.len(), 3);
+        assert_eq!(preimages.preimages[0].source, events[0].source);
+        verify_copy_preimages(&preimages, &account, 5).unwrap();
+        assert!(verify_copy_preimages(&preimages, &account, 6).is_err());
+
+        // the events of one transaction were lost
+        assert!(assemble_copy_preimages(&transactions[..1], &copy_account, 5).is_err());
+        assert!(assemble_copy_preimages(&transactions, &copy_account, 6).is_err());
+
+        // a prefix of the preimages doesn't open the digest
+        let mut partial = preimages.clone();
+        partial.preimages.pop();
+        assert!(verify_copy_preimages(&partial, &account, 5).is_err());
+    }
+}
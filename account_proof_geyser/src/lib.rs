@@ -1,14 +1,25 @@
+pub mod admin;
+pub mod archive;
+pub mod auth;
 pub mod config;
+pub mod copy_events;
+pub mod metrics;
+pub mod poh;
+pub mod replay;
+pub mod server;
+pub mod tls;
 pub mod types;
 pub mod utils;
+pub mod wire;
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::Debug;
 use std::str::FromStr;
 use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread;
 
-use borsh::BorshSerialize;
+use copy::CopyEvent;
 use crossbeam_channel::{unbounded, Sender};
 use log::error;
 use solana_geyser_plugin_interface::geyser_plugin_interface::{
@@ -18,24 +29,35 @@ use solana_geyser_plugin_interface::geyser_plugin_interface::{
 use solana_sdk::clock::Slot;
 use solana_sdk::hash::{hashv, Hash};
 use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
 use solana_sdk::vote::instruction::VoteInstruction;
 use solana_sdk::sysvar::slot_hashes::SlotHashes;
-use tokio::io::AsyncWriteExt;
-use tokio::net::TcpListener;
 use tokio::sync::broadcast;
 
+use crate::admin::{execute_admin_request, run_admin_server};
+use crate::archive::ProofArchive;
 use crate::config::Config;
+use crate::copy_events::{assemble_copy_preimages, decode_copy_events, verify_copy_preimages};
+use crate::metrics::{run_metrics_server, Metrics};
+use crate::poh::assemble_transaction_proofs;
+use crate::replay::ReplayBuffer;
+use crate::server::ProofServer;
 use crate::types::{
-    AccountHashAccumulator, AccountInfo, BankHashProof, BlockInfo, GeyserMessage, SlotInfo,
-    TransactionInfo, TransactionSigAccumulator, VoteAccumulator, Update, VoteInfo, SlotHashProofAccumulator
+    AccountHashAccumulator, AccountInfo, Accumulators, BankHashProof, BlockInfo, ConfirmedSlotError,
+    CopyInfo, EntryInfo, GeyserMessage, MonitoredAccounts, SlotEntries, SlotInfo, TransactionInfo,
+    TransactionSigAccumulator,
+    VoteAccumulator, Update, VoteInfo, VoteProof, SlotHashProofAccumulator
 };
 use crate::utils::{
-    assemble_account_delta_inclusion_proof, calculate_root_and_proofs,
-    hash_solana_account,
+    assemble_account_delta_inclusion_proof, assemble_account_delta_non_inclusion_proof,
+    calculate_root_and_proofs, get_non_inclusion_neighbours, hash_solana_account,
 };
 
 pub const SLOT_HASH_ACCOUNT: &str = "SysvarS1otHashes111111111111111111111111111";
 
+/// How many slots back the blockhash of a parent is kept for, to start the PoH chain of its child
+const RECENT_BLOCKHASH_SLOTS: u64 = 512;
+
 fn handle_confirmed_slot(
     slot: u64,
     block_accumulator: &mut HashMap<u64, BlockInfo>,
@@ -43,30 +65,46 @@ fn handle_confirmed_slot(
     processed_transaction_accumulator: &mut TransactionSigAccumulator,
     processed_vote_accumulator: &mut VoteAccumulator,
     pending_updates: &mut HashMap<Hash, Update>,
-    pubkeys_for_proofs: &[Pubkey],
-) -> anyhow::Result<Update> {
+    monitored_accounts: &MonitoredAccounts,
+) -> anyhow::Result<(Update, Vec<(Pubkey, Hash)>)> {
     // Bail if required information is not present
     let Some(block) = block_accumulator.get(&slot) else {
-        anyhow::bail!("block not available");
+        return Err(ConfirmedSlotError::BlockMissing.into());
     };
     let Some(num_sigs) = processed_transaction_accumulator.get(&slot) else {
-        anyhow::bail!("list of txns not available");
+        return Err(ConfirmedSlotError::TransactionsMissing.into());
     };
     let Some(account_hashes_data) = processed_slot_account_accumulator.get(&slot) else {
-        anyhow::bail!("account hashes not available");
+        return Err(ConfirmedSlotError::AccountHashesMissing.into());
     };
 
-    let mut filtered_pubkeys: Vec<Pubkey> = pubkeys_for_proofs
+    let proven_accounts = monitored_accounts.proven_accounts();
+    let mut filtered_pubkeys: Vec<Pubkey> = proven_accounts
         .iter()
         .filter(|pubkey| account_hashes_data.contains_key(pubkey))
         .cloned()
         .collect();
 
+    // Copy PDAs can't all be listed up front (one per signer or shard), so prove every account
+    // owned by a copy program that was modified in the slot
+    let mut copy_pdas: Vec<Pubkey> = account_hashes_data
+        .iter()
+        .filter(|(_, (_, _, account))| monitored_accounts.copy_programs.contains(&account.owner))
+        .map(|(pubkey, _)| *pubkey)
+        .filter(|pubkey| !filtered_pubkeys.contains(pubkey))
+        .collect();
+    copy_pdas.sort_unstable();
+    filtered_pubkeys.extend(copy_pdas);
+
     // Store SlotHash proofs for every Confirmed Slot
 
     let slothash_pubkey = Pubkey::from_str(&SLOT_HASH_ACCOUNT).unwrap();
-    let slothash_account_data = account_hashes_data.get(&slothash_pubkey).unwrap().2.data.clone();
-    let slothashes: SlotHashes = bincode::deserialize(&slothash_account_data).unwrap();
+    let Some(slothash_account) = account_hashes_data.get(&slothash_pubkey) else {
+        return Err(ConfirmedSlotError::SlotHashesMissing.into());
+    };
+    let slothash_account_data = slothash_account.2.data.clone();
+    let slothashes: SlotHashes = bincode::deserialize(&slothash_account_data)
+        .map_err(|_| ConfirmedSlotError::InvalidSlotHashes)?;
     filtered_pubkeys.push(slothash_pubkey);
 
 
@@ -75,22 +113,43 @@ fn handle_confirmed_slot(
         block_accumulator.remove(&slot);
         processed_slot_account_accumulator.remove(&slot);
         processed_transaction_accumulator.remove(&slot);
-        anyhow::bail!("monitored account not modified for slot: {}",&slot);
+        return Err(ConfirmedSlotError::MonitoredAccountsNotModified(slot).into());
     }
 
     // Extract necessary information for calculating Bankhash
     let num_sigs = num_sigs.clone();
-    let parent_bankhash = Hash::from_str(&block.parent_bankhash).unwrap();
-    let blockhash = Hash::from_str(&block.blockhash).unwrap();
+    let parent_bankhash = Hash::from_str(&block.parent_bankhash)
+        .map_err(|_| ConfirmedSlotError::InvalidBlockHashes)?;
+    let blockhash =
+        Hash::from_str(&block.blockhash).map_err(|_| ConfirmedSlotError::InvalidBlockHashes)?;
     let mut account_hashes: Vec<(Pubkey, Hash)> = account_hashes_data
         .iter()
         .map(|(k, (_, v, _))| (k.clone(), v.clone()))
         .collect();
 
 
+    // Monitored accounts that weren't modified get a non-inclusion proof made of their neighbouring leaves
+    account_hashes.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    let non_inclusion: Vec<(Pubkey, Option<Pubkey>, Option<Pubkey>)> = proven_accounts
+        .iter()
+        .filter(|pubkey| !account_hashes_data.contains_key(pubkey))
+        .map(|pubkey| {
+            let (left, right) = get_non_inclusion_neighbours(&account_hashes, pubkey);
+            (*pubkey, left, right)
+        })
+        .collect();
+    let mut proof_pubkeys = filtered_pubkeys.clone();
+    for (_, left, right) in &non_inclusion {
+        for neighbour in [left, right].into_iter().flatten() {
+            if !proof_pubkeys.contains(neighbour) {
+                proof_pubkeys.push(*neighbour);
+            }
+        }
+    }
+
     // Calculate Account Delta Hash (Merkle Root) and Merkle proofs for pubkeys
     let (accounts_delta_hash, account_proofs) =
-        calculate_root_and_proofs(&mut account_hashes, &pubkeys_for_proofs);
+        calculate_root_and_proofs(&mut account_hashes, &proof_pubkeys);
 
     // Step 5: Calculate BankHash based on accounts_delta_hash and information extracted in Step 2
     let bank_hash = hashv(&[
@@ -104,51 +163,137 @@ fn handle_confirmed_slot(
     let proofs = assemble_account_delta_inclusion_proof(
         &account_hashes_data,
         &account_proofs,
-        &pubkeys_for_proofs,
+        &filtered_pubkeys,
+    )?;
+    let non_inclusion_proofs = assemble_account_delta_non_inclusion_proof(
+        &account_hashes_data,
+        &account_proofs,
+        &non_inclusion,
     )?;
 
-    // Step 7: Clean up data after proofs are generated
+    // Step 7: Attach the votes for this bank hash from the monitored vote accounts.
+    // Votes for `slot` can only land in later slots, so votes held for `slot` and earlier are done with.
+    let votes: Vec<VoteProof> = processed_vote_accumulator
+        .values()
+        .flat_map(|votes| votes.values())
+        .filter(|vote| vote.vote_for_slot == slot && vote.vote_for_hash == bank_hash)
+        .filter(|vote| {
+            vote.vote_account()
+                .map_or(false, |vote_account| monitored_accounts.vote_accounts.contains(&vote_account))
+        })
+        .map(VoteProof::from)
+        .collect();
+    processed_vote_accumulator.retain(|vote_slot, _| *vote_slot > slot);
+
+    // Step 8: Clean up data after proofs are generated
     block_accumulator.remove(&slot);
     processed_slot_account_accumulator.remove(&slot);
     processed_transaction_accumulator.remove(&slot);
 
-    Ok(Update {
-        slot,
-        root: bank_hash,
-        proof: BankHashProof {
-            proofs,
-            num_sigs,
-            account_delta_root: accounts_delta_hash,
-            parent_bankhash,
-            blockhash,
+    // account_hashes is sorted by pubkey, so these are the leaves of the accounts delta tree in order
+    Ok((
+        Update {
+            slot,
+            root: bank_hash,
+            proof: BankHashProof {
+                proofs,
+                non_inclusion_proofs,
+                num_sigs,
+                account_delta_root: accounts_delta_hash,
+                parent_bankhash,
+                blockhash,
+            },
+            votes,
+            copy_preimages: vec![],
+            transaction_proofs: vec![],
         },
-    })
+        account_hashes,
+    ))
 }
 
 
-fn handle_processed_slot(
-    slot: u64,
-    raw_slot_account_accumulator: &mut AccountHashAccumulator,
-    processed_slot_account_accumulator: &mut AccountHashAccumulator,
-    raw_transaction_accumulator: &mut TransactionSigAccumulator,
-    processed_transaction_accumulator: &mut TransactionSigAccumulator,
-    raw_vote_accumulator: &mut VoteAccumulator,
-    processed_vote_accumulator: &mut VoteAccumulator,
-) -> anyhow::Result<()> {
+/// Attach what was accumulated into each proven copy PDA during the slot, so that its digest can
+/// be opened. `transactions` are the copy events of the slot by transaction index.
+fn attach_copy_preimages(
+    update: &mut Update,
+    transactions: &[(usize, Vec<CopyEvent>)],
+    copy_programs: &[Pubkey],
+) {
+    for proof in &update.proof.proofs {
+        let account = &proof.1 .0.account;
+        if !copy_programs.contains(&account.owner) {
+            continue;
+        }
+        let preimages = assemble_copy_preimages(transactions, &proof.0, update.slot).and_then(
+            |preimages| verify_copy_preimages(&preimages, account, update.slot).map(|_| preimages),
+        );
+        match preimages {
+            Ok(preimages) => update.copy_preimages.push(preimages),
+            Err(e) => error!("No copy preimages for {} in slot {}: {:?}", proof.0, update.slot, e),
+        }
+    }
+}
+
+/// Prove the inclusion in the block of the slot's transactions that reference a proven account.
+/// The PoH chain of the slot starts at `parent_blockhash`.
+fn attach_transaction_proofs(
+    update: &mut Update,
+    slot_entries: &SlotEntries,
+    parent_blockhash: Option<Hash>,
+) {
+    // Every update proves SlotHashes, which transactions may reference without writing to it
+    let slothash_pubkey = Pubkey::from_str(SLOT_HASH_ACCOUNT).unwrap();
+    let proven: HashSet<Pubkey> = update
+        .proof
+        .proofs
+        .iter()
+        .map(|proof| proof.0)
+        .filter(|pubkey| *pubkey != slothash_pubkey)
+        .collect();
+    let signatures: Vec<Signature> = slot_entries
+        .transactions
+        .iter()
+        .filter(|transaction| transaction.account_keys.iter().any(|key| proven.contains(key)))
+        .filter_map(|transaction| transaction.signatures.first().copied())
+        .collect();
+    if signatures.is_empty() {
+        return;
+    }
+    let Some(parent_blockhash) = parent_blockhash else {
+        error!("No transaction proofs for slot {}: the parent blockhash is unknown", update.slot);
+        return;
+    };
+    match assemble_transaction_proofs(slot_entries, parent_blockhash, update.proof.blockhash, &signatures) {
+        Ok(proofs) => update.transaction_proofs = proofs,
+        Err(e) => error!("No transaction proofs for slot {}: {:?}", update.slot, e),
+    }
+}
+
+fn handle_processed_slot(slot: u64, accumulators: &mut Accumulators) -> anyhow::Result<()> {
+    transfer_slot(
+        slot,
+        &mut accumulators.raw_slot_account_accumulator,
+        &mut accumulators.processed_slot_account_accumulator,
+    );
+    transfer_slot(
+        slot,
+        &mut accumulators.raw_transaction_accumulator,
+        &mut accumulators.processed_transaction_accumulator,
+    );
     transfer_slot(
         slot,
-        raw_slot_account_accumulator,
-        processed_slot_account_accumulator,
+        &mut accumulators.raw_vote_accumulator,
+        &mut accumulators.processed_vote_accumulator,
     );
     transfer_slot(
         slot,
-        raw_transaction_accumulator,
-        processed_transaction_accumulator,
+        &mut accumulators.raw_copy_accumulator,
+        &mut accumulators.processed_copy_accumulator,
     );
     transfer_slot(
         slot,
-        raw_vote_accumulator,
-        processed_vote_accumulator,
+        &mut accumulators.raw_entry_accumulator,
+        &mut accumulators.processed_entry_accumulator,
     );
     Ok(())
 }
@@ -159,25 +304,49 @@ fn transfer_slot<V>(slot: u64, raw: &mut HashMap<u64, V>, processed: &mut HashMa
     }
 }
 
+/// Delivers every generated update to the archive, the replay buffer and the live subscribers
+struct UpdatePublisher {
+    tx: broadcast::Sender<Update>,
+    replay_buffer: Arc<Mutex<ReplayBuffer>>,
+    archive: Option<ProofArchive>,
+    metrics: Arc<Metrics>,
+}
+
+impl UpdatePublisher {
+    fn publish(&self, update: Update, leaves: &[(Pubkey, Hash)]) {
+        let slot = update.slot;
+        if let Some(archive) = &self.archive {
+            if let Err(e) = archive.insert(&update, leaves) {
+                error!("Failed to archive the update {}: {:?}", slot, e);
+            }
+        }
+        if let Err(e) = self.replay_buffer.lock().unwrap().push(update.clone()) {
+            error!("Failed to buffer the update for replay {}: {:?}", slot, e);
+        }
+        self.metrics.latest_emitted_slot.set(slot as i64);
+        if let Err(e) = self.tx.send(update) {
+            error!("No subscribers to receive the update {}: {:?}", slot, e);
+        }
+        self.metrics.broadcast_backlog.set(self.tx.len() as i64);
+    }
+}
+
 fn process_messages(
     geyser_receiver: crossbeam::channel::Receiver<GeyserMessage>,
-    tx: broadcast::Sender<Update>,
-    pubkeys_for_proofs: Vec<Pubkey>,
+    publisher: UpdatePublisher,
+    mut monitored_accounts: MonitoredAccounts,
 ) {
-    let mut raw_slot_account_accumulator: AccountHashAccumulator = HashMap::new();
-    let mut processed_slot_account_accumulator: AccountHashAccumulator = HashMap::new();
-
-    let mut raw_transaction_accumulator: TransactionSigAccumulator = HashMap::new();
-    let mut processed_transaction_accumulator: TransactionSigAccumulator = HashMap::new();
+    let metrics = publisher.metrics.clone();
 
-    let mut raw_vote_accumulator: VoteAccumulator = HashMap::new();
-    let mut processed_vote_accumulator: VoteAccumulator = HashMap::new();
+    let mut accumulators = Accumulators::default();
 
     let mut slothash_accumulator: SlotHashProofAccumulator = HashMap::new();
 
     let mut pending_updates: HashMap<Hash,Update> = HashMap::new();
 
-    let mut block_accumulator: HashMap<u64, BlockInfo> = HashMap::new();
+    // Blockhashes of recent blocks by slot: the PoH chain of a block starts at its parent's
+    let mut recent_blockhashes: BTreeMap<u64, Hash> = BTreeMap::new();
+
     loop {
         match geyser_receiver.recv() {
             // Handle account update
@@ -196,7 +365,7 @@ fn process_messages(
                 let write_version = acc.write_version;
                 let slot = acc.slot;
 
-                let slot_entry = raw_slot_account_accumulator
+                let slot_entry = accumulators.raw_slot_account_accumulator
                     .entry(slot)
                     .or_insert_with(HashMap::new);
 
@@ -208,25 +377,46 @@ fn process_messages(
                     *account_entry = (write_version, Hash::from(account_hash), acc);
                 }
             }
-            // Handle transaction message. We only require the number of signatures for the purpose of calculating the BankHash
+            // Handle transaction message. The number of signatures goes into the BankHash, the
+            // signatures themselves into the PoH entries of the block
             Ok(GeyserMessage::TransactionMessage(txn)) => {
                 let slot_num = txn.slot;
-                *raw_transaction_accumulator.entry(slot_num).or_insert(0) += txn.num_sigs;
+                *accumulators.raw_transaction_accumulator.entry(slot_num).or_insert(0) += txn.signatures.len() as u64;
+                accumulators.raw_entry_accumulator.entry(slot_num)
+                    .or_default()
+                    .transactions
+                    .push(txn);
+            }
+            Ok(GeyserMessage::EntryMessage(entry)) => {
+                accumulators.raw_entry_accumulator.entry(entry.slot)
+                    .or_default()
+                    .entries
+                    .push(entry);
             }
             Ok(GeyserMessage::VoteMessage(vote_info)) => {
                 let slot_num = vote_info.slot;
                 let sig = vote_info.signature;
-                raw_vote_accumulator.entry(slot_num)
+                accumulators.raw_vote_accumulator.entry(slot_num)
                     .or_insert(HashMap::new())
                     .insert(sig.clone(), vote_info);
             }
+            Ok(GeyserMessage::CopyMessage(copy_info)) => {
+                accumulators.raw_copy_accumulator.entry(copy_info.slot)
+                    .or_default()
+                    .push((copy_info.index, copy_info.events));
+            }
             // Handle Block updates
             Ok(GeyserMessage::BlockMessage(block)) => {
                 let slot = block.slot;
-                block_accumulator.insert(
+                if let Ok(blockhash) = Hash::from_str(&block.blockhash) {
+                    recent_blockhashes.insert(slot, blockhash);
+                }
+                recent_blockhashes.retain(|recent_slot, _| recent_slot + RECENT_BLOCKHASH_SLOTS >= slot);
+                accumulators.block_accumulator.insert(
                     slot,
                     BlockInfo {
                         slot,
+                        parent_slot: block.parent_slot,
                         parent_bankhash: block.parent_bankhash,
                         blockhash: block.blockhash,
                         executed_transaction_count: block.executed_transaction_count,
@@ -236,54 +426,92 @@ fn process_messages(
             // Handle `processed` and `confirmed` slot messages.
             // `handle_processed_slot` moves from "working" hashmaps to "processed" hashmaps
             // `handle_confirmed_slot` gets the necessary proofs when a slot is "confirmed"
-            Ok(GeyserMessage::SlotMessage(slot_info)) => match slot_info.status {
-                SlotStatus::Processed => {
-                    // handle a slot being processed.
-                    // move data from raw -> processed
-                    if let Err(e) = handle_processed_slot(
-                        slot_info.slot,
-                        &mut raw_slot_account_accumulator,
-                        &mut processed_slot_account_accumulator,
-                        &mut raw_transaction_accumulator,
-                        &mut processed_transaction_accumulator,
-                        &mut raw_vote_accumulator,
-                        &mut processed_vote_accumulator,
-                    ) {
-                        error!(
-                            "Error when handling processed slot {}: {:?}",
-                            slot_info.slot, e
-                        );
+            Ok(GeyserMessage::SlotMessage(slot_info)) => {
+                match slot_info.status {
+                    SlotStatus::Processed => {
+                        metrics.latest_processed_slot.set(slot_info.slot as i64);
+                        // handle a slot being processed.
+                        // move data from raw -> processed
+                        if let Err(e) = handle_processed_slot(slot_info.slot, &mut accumulators) {
+                            error!(
+                                "Error when handling processed slot {}: {:?}",
+                                slot_info.slot, e
+                            );
+                        }
                     }
-                }
-                SlotStatus::Confirmed => {
-                    // handle a slot being confirmed
-                    // use latest information in "processed" hashmaps and generate required proofs
-                    // cleanup the processed hashmaps
-
-                    match handle_confirmed_slot(
-                        slot_info.slot,
-                        &mut block_accumulator,
-                        &mut processed_slot_account_accumulator,
-                        &mut processed_transaction_accumulator,
-                        &mut processed_vote_accumulator,
-                        &mut pending_updates,
-                        &pubkeys_for_proofs,
-                    ) {
-                        Ok(update) => {
-                            if let Err(e) = tx.send(update) {
-                                error!(
-                                    "No subscribers to receive the update {}: {:?}",
-                                    slot_info.slot, e
-                                );
+                    SlotStatus::Confirmed => {
+                        // handle a slot being confirmed
+                        // use latest information in "processed" hashmaps and generate required proofs
+                        // cleanup the processed hashmaps
+                        metrics.latest_confirmed_slot.set(slot_info.slot as i64);
+
+                        let timer = metrics.proof_generation_seconds.start_timer();
+                        let copy_transactions = accumulators
+                            .processed_copy_accumulator
+                            .remove(&slot_info.slot)
+                            .unwrap_or_default();
+                        let slot_entries = accumulators
+                            .processed_entry_accumulator
+                            .remove(&slot_info.slot)
+                            .unwrap_or_default();
+                        let parent_blockhash = accumulators
+                            .block_accumulator
+                            .get(&slot_info.slot)
+                            .and_then(|block| recent_blockhashes.get(&block.parent_slot))
+                            .copied();
+                        let result = handle_confirmed_slot(
+                            slot_info.slot,
+                            &mut accumulators.block_accumulator,
+                            &mut accumulators.processed_slot_account_accumulator,
+                            &mut accumulators.processed_transaction_accumulator,
+                            &mut accumulators.processed_vote_accumulator,
+                            &mut pending_updates,
+                            &monitored_accounts,
+                        )
+                        .map(|(mut update, leaves)| {
+                            attach_copy_preimages(
+                                &mut update,
+                                &copy_transactions,
+                                &monitored_accounts.copy_programs,
+                            );
+                            attach_transaction_proofs(&mut update, &slot_entries, parent_blockhash);
+                            (update, leaves)
+                        });
+                        timer.observe_duration();
+
+                        match result {
+                            Ok((update, leaves)) => publisher.publish(update, &leaves),
+                            Err(err) => {
+                                let reason = err
+                                    .downcast_ref::<ConfirmedSlotError>()
+                                    .map_or("other", ConfirmedSlotError::reason);
+                                metrics
+                                    .confirmed_slot_errors
+                                    .with_label_values(&[reason])
+                                    .inc();
+                                error!("{:?}", err);
                             }
                         }
-                        Err(err) => {
-                            error!("{:?}", err);
-                        }
                     }
+                    _ => {}
                 }
-                _ => {}
-            },
+
+                for (name, slots) in accumulators.sizes() {
+                    metrics
+                        .accumulator_slots
+                        .with_label_values(&[name])
+                        .set(slots as i64);
+                }
+                metrics
+                    .geyser_channel_backlog
+                    .set(geyser_receiver.len() as i64);
+            }
+            Ok(GeyserMessage::AdminMessage(command)) => {
+                let response =
+                    execute_admin_request(command.request, &mut accumulators, &mut monitored_accounts);
+                // The admin connection may have gone away in the meantime
+                let _ = command.reply.send(response);
+            }
             _ => {}
         }
     }
@@ -296,6 +524,8 @@ const STARTUP_PROCESSED_RECEIVED: u8 = 1 << 1;
 pub struct PluginInner {
     startup_status: AtomicU8,
     geyser_sender: Sender<GeyserMessage>,
+    /// Transactions invoking these programs have their copy events decoded
+    copy_programs: Vec<Pubkey>,
 }
 
 impl PluginInner {
@@ -328,6 +558,18 @@ impl Plugin {
     }
 }
 
+/// Parse the pubkeys of the config entry `key`, naming the entry and the value that isn't one
+fn parse_pubkeys<T: FromIterator<Pubkey>>(key: &str, values: &[String]) -> PluginResult<T> {
+    values
+        .iter()
+        .map(|value| {
+            Pubkey::from_str(value).map_err(|e| GeyserPluginError::ConfigFileReadError {
+                msg: format!("invalid pubkey {:?} in {}: {}", value, key, e),
+            })
+        })
+        .collect()
+}
+
 impl GeyserPlugin for Plugin {
     fn name(&self) -> &'static str {
         "AccountProofGeyserPlugin"
@@ -338,50 +580,83 @@ impl GeyserPlugin for Plugin {
             .map_err(|e| GeyserPluginError::ConfigFileReadError { msg: e.to_string() })?;
         solana_logger::setup_with_default("error");
         let (geyser_sender, geyser_receiver) = unbounded();
-        let pubkeys_for_proofs: Vec<Pubkey> = config
-            .account_list
-            .iter()
-            .map(|x| Pubkey::from_str(x).unwrap())
-            .collect();
-
+        let monitored_accounts = MonitoredAccounts {
+            proof_accounts: parse_pubkeys("account_list", &config.account_list)?,
+            vote_accounts: parse_pubkeys("vote_accounts", &config.vote_accounts)?,
+            copy_programs: parse_pubkeys("copy_programs", &config.copy_programs)?,
+            watched_accounts: HashMap::new(),
+            max_watched_accounts: config.max_watched_accounts,
+        };
+
+        let replay_buffer = match &config.replay_buffer_path {
+            Some(path) => ReplayBuffer::with_log(config.replay_buffer_size, path)
+                .map_err(|e| GeyserPluginError::Custom(Box::new(e)))?,
+            None => ReplayBuffer::new(config.replay_buffer_size),
+        };
+        let replay_buffer = Arc::new(Mutex::new(replay_buffer));
+        let archive = match &config.archive {
+            Some(archive_config) => Some(
+                ProofArchive::from_config(archive_config)
+                    .map_err(|e| GeyserPluginError::Custom(e.into()))?,
+            ),
+            None => None,
+        };
+
+        let metrics = Arc::new(Metrics::new());
         let (tx, _rx) = broadcast::channel(32);
-
-        let tx_process_messages = tx.clone();
+        let server = ProofServer::from_config(
+            &config,
+            tx.clone(),
+            replay_buffer.clone(),
+            archive.clone(),
+            metrics.clone(),
+            geyser_sender.clone(),
+        )
+        .map_err(|e| GeyserPluginError::Custom(Box::new(e)))?;
+
+        let publisher = UpdatePublisher {
+            tx,
+            replay_buffer,
+            archive,
+            metrics: metrics.clone(),
+        };
+        let copy_programs = monitored_accounts.copy_programs.clone();
         thread::spawn(move || {
-            process_messages(geyser_receiver, tx_process_messages, pubkeys_for_proofs);
+            process_messages(geyser_receiver, publisher, monitored_accounts);
         });
 
+        let admin_sender = geyser_sender.clone();
         thread::spawn(move || {
             let runtime = tokio::runtime::Runtime::new().unwrap();
-            runtime.block_on(async {
-                let listener = TcpListener::bind(&config.bind_address).await.unwrap();
-                loop {
-                    let (mut socket, _) = match listener.accept().await {
-                        Ok(connection) => connection,
-                        Err(e) => {
-                            error!("Failed to accept connection: {:?}", e);
-                            continue;
-                        }
-                    };
-                    let mut rx = tx.subscribe();
-                    tokio::spawn(async move {
-                        loop {
-                            match rx.recv().await {
-                                Ok(update) => {
-                                    let data = update.try_to_vec().unwrap();
-                                    let _ = socket.write_all(&data).await;
-                                }
-                                Err(_) => {}
-                            }
-                        }
-                    });
-                }
-            });
+            if let Some(socket_path) = config.admin_socket_path {
+                runtime.spawn(async move {
+                    if let Err(e) = run_admin_server(socket_path, admin_sender).await {
+                        error!("Admin server stopped: {:?}", e);
+                    }
+                });
+            }
+            if let Some(metrics_config) = config.metrics {
+                runtime.spawn(async move {
+                    if let Err(e) = run_metrics_server(
+                        metrics_config.bind_address,
+                        metrics,
+                        metrics_config.max_emission_lag_slots,
+                    )
+                    .await
+                    {
+                        error!("Metrics server stopped: {:?}", e);
+                    }
+                });
+            }
+            if let Err(e) = runtime.block_on(server.run(config.bind_address)) {
+                error!("Proof server stopped: {:?}", e);
+            }
         });
 
         self.inner = Some(PluginInner {
             startup_status: AtomicU8::new(0),
             geyser_sender,
+            copy_programs,
         });
 
         Ok(())
@@ -493,17 +768,57 @@ impl GeyserPlugin for Plugin {
                 }
 
             }
+
+            // Failed transactions still log the events emitted before the failure, but their copies were rolled back
+            let meta = transaction.transaction_status_meta;
+            if meta.status.is_ok()
+                && transaction
+                    .transaction
+                    .message()
+                    .account_keys()
+                    .iter()
+                    .any(|key| inner.copy_programs.contains(key))
+            {
+                let events = meta
+                    .log_messages
+                    .as_deref()
+                    .map(|logs| decode_copy_events(logs, &inner.copy_programs))
+                    .unwrap_or_default();
+                if !events.is_empty() {
+                    inner.send_message(GeyserMessage::CopyMessage(CopyInfo {
+                        slot,
+                        index: transaction.index,
+                        events,
+                    }));
+                }
+            }
             let message = GeyserMessage::TransactionMessage(TransactionInfo {
                 slot,
-                num_sigs: transaction.transaction.signatures().len() as u64,
+                index: transaction.index,
+                signatures: transaction.transaction.signatures().to_vec(),
+                account_keys: transaction.transaction.message().account_keys().iter().copied().collect(),
             });
             inner.send_message(message);
             Ok(())
         })
     }
 
-    fn notify_entry(&self, _entry: ReplicaEntryInfoVersions) -> PluginResult<()> {
-        Ok(())
+    fn notify_entry(&self, entry: ReplicaEntryInfoVersions) -> PluginResult<()> {
+        self.with_inner(|inner| {
+            let ReplicaEntryInfoVersions::V0_0_1(entry) = entry;
+            let Ok(hash) = <[u8; 32]>::try_from(entry.hash) else {
+                error!("Entry {} of slot {} has a malformed hash", entry.index, entry.slot);
+                return Ok(());
+            };
+            inner.send_message(GeyserMessage::EntryMessage(EntryInfo {
+                slot: entry.slot,
+                index: entry.index,
+                num_hashes: entry.num_hashes,
+                hash: Hash::new_from_array(hash),
+                executed_transaction_count: entry.executed_transaction_count,
+            }));
+            Ok(())
+        })
     }
 
     fn notify_block_metadata(&self, blockinfo: ReplicaBlockInfoVersions<'_>) -> PluginResult<()> {
@@ -530,7 +845,7 @@ impl GeyserPlugin for Plugin {
     }
 
     fn entry_notifications_enabled(&self) -> bool {
-        false
+        true
     }
 }
 
@@ -543,3 +858,110 @@ pub unsafe extern "C" fn _create_plugin() -> *mut dyn GeyserPlugin {
     let plugin: Box<dyn GeyserPlugin> = Box::new(plugin);
     Box::into_raw(plugin)
 }
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::slot_hashes::SlotHash;
+
+    use super::*;
+    use crate::types::AccountHashMap;
+    use crate::utils::{verify_leaves_against_bankhash, verify_non_inclusion_against_bankhash};
+
+    fn insert_account(account_hashes: &mut AccountHashMap, account: AccountInfo) {
+        let hash = Hash::from(hash_solana_account(
+            account.lamports,
+            account.owner.as_ref(),
+            account.executable,
+            account.rent_epoch,
+            &account.data,
+            account.pubkey.as_ref(),
+        ));
+        account_hashes.insert(account.pubkey, (account.write_version, hash, account));
+    }
+
+    #[test]
+    fn test_confirmed_slot_proves_modified_accounts_and_slot_hashes() {
+        let slot = 10;
+        let modified = Pubkey::new_from_array([1; 32]);
+        let unmodified = Pubkey::new_from_array([2; 32]);
+        let other = Pubkey::new_from_array([3; 32]);
+        let slothash_pubkey = Pubkey::from_str(SLOT_HASH_ACCOUNT).unwrap();
+
+        let mut account_hashes = AccountHashMap::new();
+        for (pubkey, lamports) in [(modified, 1), (other, 2)] {
+            insert_account(&mut account_hashes, AccountInfo { pubkey, lamports, slot, ..AccountInfo::default() });
+        }
+        let slot_hashes: Vec<SlotHash> = vec![(slot - 1, Hash::new_unique())];
+        insert_account(
+            &mut account_hashes,
+            AccountInfo {
+                pubkey: slothash_pubkey,
+                lamports: 1,
+                data: bincode::serialize(&SlotHashes::new(&slot_hashes)).unwrap(),
+                slot,
+                ..AccountInfo::default()
+            },
+        );
+
+        let mut block_accumulator = HashMap::from([(
+            slot,
+            BlockInfo {
+                slot,
+                parent_slot: slot - 1,
+                parent_bankhash: Hash::new_unique().to_string(),
+                blockhash: Hash::new_unique().to_string(),
+                executed_transaction_count: 1,
+            },
+        )]);
+        let mut account_accumulator: AccountHashAccumulator = HashMap::from([(slot, account_hashes)]);
+        let mut transaction_accumulator: TransactionSigAccumulator = HashMap::from([(slot, 1)]);
+        let mut vote_accumulator: VoteAccumulator = HashMap::new();
+        let monitored_accounts = MonitoredAccounts {
+            proof_accounts: vec![modified, unmodified],
+            ..MonitoredAccounts::default()
+        };
+
+        let (update, _) = handle_confirmed_slot(
+            slot,
+            &mut block_accumulator,
+            &mut account_accumulator,
+            &mut transaction_accumulator,
+            &mut vote_accumulator,
+            &mut HashMap::new(),
+            &monitored_accounts,
+        )
+        .unwrap();
+
+        // Only the monitored account that was written gets an inclusion proof, along with the
+        // SlotHashes sysvar that is written every slot
+        let proven: Vec<Pubkey> = update.proof.proofs.iter().map(|proof| proof.0).collect();
+        assert_eq!(proven, vec![modified, slothash_pubkey]);
+        let non_included: Vec<Pubkey> =
+            update.proof.non_inclusion_proofs.iter().map(|proof| proof.pubkey).collect();
+        assert_eq!(non_included, vec![unmodified]);
+
+        let proof = &update.proof;
+        for account_proof in &proof.proofs {
+            verify_leaves_against_bankhash(
+                account_proof,
+                update.root,
+                proof.num_sigs,
+                proof.account_delta_root,
+                proof.parent_bankhash,
+                proof.blockhash,
+            )
+            .unwrap();
+        }
+        for non_inclusion_proof in &proof.non_inclusion_proofs {
+            verify_non_inclusion_against_bankhash(
+                non_inclusion_proof,
+                update.root,
+                proof.num_sigs,
+                proof.account_delta_root,
+                proof.parent_bankhash,
+                proof.blockhash,
+            )
+            .unwrap();
+        }
+    }
+}
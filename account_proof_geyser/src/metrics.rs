@@ -0,0 +1,229 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+
+/// Counters and gauges describing what `process_messages` and the proof server are doing.
+/// Served in the Prometheus text format on `/metrics`.
+pub struct Metrics {
+    registry: Registry,
+    pub latest_processed_slot: IntGauge,
+    pub latest_confirmed_slot: IntGauge,
+    pub latest_emitted_slot: IntGauge,
+    /// Number of slots held in each accumulator, labelled by accumulator name
+    pub accumulator_slots: IntGaugeVec,
+    pub proof_generation_seconds: Histogram,
+    /// `handle_confirmed_slot` failures, labelled by reason
+    pub confirmed_slot_errors: IntCounterVec,
+    pub connected_subscribers: IntGauge,
+    /// Messages from the validator waiting to be processed
+    pub geyser_channel_backlog: IntGauge,
+    /// Updates waiting to be read by the slowest subscriber
+    pub broadcast_backlog: IntGauge,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new_custom(Some("account_proof".to_string()), None).unwrap();
+
+        let latest_processed_slot =
+            IntGauge::new("latest_processed_slot", "Latest slot seen as processed").unwrap();
+        let latest_confirmed_slot =
+            IntGauge::new("latest_confirmed_slot", "Latest slot seen as confirmed").unwrap();
+        let latest_emitted_slot = IntGauge::new(
+            "latest_emitted_slot",
+            "Latest slot for which an update was emitted",
+        )
+        .unwrap();
+        let accumulator_slots = IntGaugeVec::new(
+            Opts::new("accumulator_slots", "Number of slots held in each accumulator"),
+            &["accumulator"],
+        )
+        .unwrap();
+        let proof_generation_seconds = Histogram::with_opts(
+            HistogramOpts::new(
+                "proof_generation_seconds",
+                "Time spent generating the proofs for a confirmed slot",
+            )
+            .buckets(vec![
+                0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+            ]),
+        )
+        .unwrap();
+        let confirmed_slot_errors = IntCounterVec::new(
+            Opts::new(
+                "confirmed_slot_errors_total",
+                "Confirmed slots for which no update could be generated",
+            ),
+            &["reason"],
+        )
+        .unwrap();
+        let connected_subscribers =
+            IntGauge::new("connected_subscribers", "Clients subscribed to the proof stream")
+                .unwrap();
+        let geyser_channel_backlog = IntGauge::new(
+            "geyser_channel_backlog",
+            "Validator notifications waiting to be processed",
+        )
+        .unwrap();
+        let broadcast_backlog = IntGauge::new(
+            "broadcast_backlog",
+            "Updates not yet received by the slowest subscriber",
+        )
+        .unwrap();
+
+        registry.register(Box::new(latest_processed_slot.clone())).unwrap();
+        registry.register(Box::new(latest_confirmed_slot.clone())).unwrap();
+        registry.register(Box::new(latest_emitted_slot.clone())).unwrap();
+        registry.register(Box::new(accumulator_slots.clone())).unwrap();
+        registry.register(Box::new(proof_generation_seconds.clone())).unwrap();
+        registry.register(Box::new(confirmed_slot_errors.clone())).unwrap();
+        registry.register(Box::new(connected_subscribers.clone())).unwrap();
+        registry.register(Box::new(geyser_channel_backlog.clone())).unwrap();
+        registry.register(Box::new(broadcast_backlog.clone())).unwrap();
+
+        Metrics {
+            registry,
+            latest_processed_slot,
+            latest_confirmed_slot,
+            latest_emitted_slot,
+            accumulator_slots,
+            proof_generation_seconds,
+            confirmed_slot_errors,
+            connected_subscribers,
+            geyser_channel_backlog,
+            broadcast_backlog,
+        }
+    }
+
+    /// Render every metric in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut buffer = vec![];
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    /// Fails when the latest emitted update is more than `max_emission_lag` slots behind the latest
+    /// confirmed slot
+    pub fn health(&self, max_emission_lag: u64) -> Result<(), String> {
+        let confirmed = self.latest_confirmed_slot.get();
+        let emitted = self.latest_emitted_slot.get();
+        let lag = confirmed.saturating_sub(emitted);
+        if lag > max_emission_lag as i64 {
+            return Err(format!(
+                "emitted slot {} is {} slots behind confirmed slot {}",
+                emitted, lag, confirmed
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+async fn handle_request(
+    request: Request<Body>,
+    metrics: Arc<Metrics>,
+    max_emission_lag: u64,
+) -> Result<Response<Body>, Infallible> {
+    let response = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/metrics") => Response::builder()
+            .header("Content-Type", TextEncoder::new().format_type())
+            .body(Body::from(metrics.render())),
+        (&Method::GET, "/health") => match metrics.health(max_emission_lag) {
+            Ok(()) => Response::builder().body(Body::from("ok")),
+            Err(reason) => Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .body(Body::from(reason)),
+        },
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty()),
+    };
+    Ok(response.unwrap())
+}
+
+/// Serve `/metrics` and `/health` over HTTP on `bind_address`
+pub async fn run_metrics_server(
+    bind_address: SocketAddr,
+    metrics: Arc<Metrics>,
+    max_emission_lag: u64,
+) -> hyper::Result<()> {
+    let make_service = make_service_fn(move |_| {
+        let metrics = metrics.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                handle_request(request, metrics.clone(), max_emission_lag)
+            }))
+        }
+    });
+    Server::try_bind(&bind_address)?.serve(make_service).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_contains_metrics() {
+        let metrics = Metrics::new();
+        metrics.latest_confirmed_slot.set(42);
+        metrics
+            .confirmed_slot_errors
+            .with_label_values(&["block_missing"])
+            .inc();
+        metrics
+            .accumulator_slots
+            .with_label_values(&["raw_accounts"])
+            .set(3);
+
+        let rendered = metrics.render();
+        assert!(rendered.contains("account_proof_latest_confirmed_slot 42"));
+        assert!(rendered.contains("account_proof_confirmed_slot_errors_total{reason=\"block_missing\"} 1"));
+        assert!(rendered.contains("account_proof_accumulator_slots{accumulator=\"raw_accounts\"} 3"));
+    }
+
+    #[test]
+    fn test_health_fails_when_emission_lags() {
+        let metrics = Metrics::new();
+        assert!(metrics.health(10).is_ok());
+
+        metrics.latest_confirmed_slot.set(100);
+        metrics.latest_emitted_slot.set(95);
+        assert!(metrics.health(10).is_ok());
+
+        metrics.latest_emitted_slot.set(80);
+        assert!(metrics.health(10).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_health_endpoint() {
+        let metrics = Arc::new(Metrics::new());
+        metrics.latest_confirmed_slot.set(100);
+
+        let request = Request::get("/health").body(Body::empty()).unwrap();
+        let response = handle_request(request, metrics.clone(), 10).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        metrics.latest_emitted_slot.set(100);
+        let request = Request::get("/health").body(Body::empty()).unwrap();
+        let response = handle_request(request, metrics.clone(), 10).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let request = Request::get("/metrics").body(Body::empty()).unwrap();
+        let response = handle_request(request, metrics, 10).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
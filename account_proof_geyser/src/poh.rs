@@ -0,0 +1,298 @@
+use std::collections::HashSet;
+
+use solana_sdk::hash::{hash, hashv, Hash};
+use solana_sdk::signature::Signature;
+
+use crate::types::{PohEntry, SlotEntries, TransactionProof};
+
+// Prefixes of the merkle tree the signatures of an entry are hashed into (solana-merkle-tree)
+const LEAF_PREFIX: &[u8] = &[0];
+const INTERMEDIATE_PREFIX: &[u8] = &[1];
+
+/// Hash of an entry from the hash of the previous one, as in `solana_entry::entry::next_hash`:
+/// `num_hashes - 1` hashes, then either the transactions root is mixed in or a tick is hashed
+pub fn next_entry_hash(start_hash: &Hash, num_hashes: u64, transactions_root: Option<Hash>) -> Hash {
+    if num_hashes == 0 && transactions_root.is_none() {
+        return *start_hash;
+    }
+    let mut poh = *start_hash;
+    for _ in 1..num_hashes {
+        poh = hash(poh.as_ref());
+    }
+    match transactions_root {
+        Some(root) => hashv(&[poh.as_ref(), root.as_ref()]),
+        None => hash(poh.as_ref()),
+    }
+}
+
+/// Every level of the merkle tree over `signatures`, leaves first. A node without a right
+/// sibling is hashed with itself.
+fn signature_levels(signatures: &[Signature]) -> Vec<Vec<Hash>> {
+    let mut levels = vec![signatures
+        .iter()
+        .map(|signature| hashv(&[LEAF_PREFIX, signature.as_ref()]))
+        .collect::<Vec<Hash>>()];
+    while levels[levels.len() - 1].len() > 1 {
+        let level = levels[levels.len() - 1]
+            .chunks(2)
+            .map(|pair| {
+                let right = pair.get(1).unwrap_or(&pair[0]);
+                hashv(&[INTERMEDIATE_PREFIX, pair[0].as_ref(), right.as_ref()])
+            })
+            .collect();
+        levels.push(level);
+    }
+    levels
+}
+
+/// Root of the signatures of an entry's transactions, mixed into PoH by the entry
+pub fn hash_signatures(signatures: &[Signature]) -> Hash {
+    signature_levels(signatures)
+        .last()
+        .and_then(|level| level.first().copied())
+        .unwrap_or_default()
+}
+
+/// Root of the signature tree from the leaf of `signature` at `index` and its siblings
+pub fn signature_root(signature: &[u8], index: u64, siblings: &[Hash]) -> Option<Hash> {
+    if siblings.len() < 64 && index >> siblings.len() != 0 {
+        return None;
+    }
+    let mut node = hashv(&[LEAF_PREFIX, signature]);
+    for (level, sibling) in siblings.iter().enumerate() {
+        node = if index >> level & 1 == 0 {
+            hashv(&[INTERMEDIATE_PREFIX, node.as_ref(), sibling.as_ref()])
+        } else {
+            hashv(&[INTERMEDIATE_PREFIX, sibling.as_ref(), node.as_ref()])
+        };
+    }
+    Some(node)
+}
+
+/// Build inclusion proofs for `signatures` out of the entries and transactions notified for a
+/// slot. The entry hashes come from the validator, so the PoH chain is not recomputed: only the
+/// signature roots are, and the hashes of the entries holding one of `signatures`. The notified
+/// transactions must add up to the entries and the last entry must end at `blockhash`, so that a
+/// missing transaction or entry is noticed. The PoH chain of the slot starts at `start_hash`, the
+/// parent's blockhash.
+pub fn assemble_transaction_proofs(
+    slot_entries: &SlotEntries,
+    start_hash: Hash,
+    blockhash: Hash,
+    signatures: &[Signature],
+) -> anyhow::Result<Vec<TransactionProof>> {
+    let mut entries: Vec<_> = slot_entries.entries.iter().collect();
+    entries.sort_by_key(|entry| entry.index);
+    let mut transactions: Vec<_> = slot_entries.transactions.iter().collect();
+    transactions.sort_by_key(|transaction| transaction.index);
+    for (index, entry) in entries.iter().enumerate() {
+        if entry.index != index {
+            anyhow::bail!("entry {} is missing", index);
+        }
+    }
+    for (index, transaction) in transactions.iter().enumerate() {
+        if transaction.index != index {
+            anyhow::bail!("transaction {} is missing", index);
+        }
+    }
+    let executed: u64 = entries.iter().map(|entry| entry.executed_transaction_count).sum();
+    if executed as usize != transactions.len() {
+        anyhow::bail!(
+            "entries hold {} transactions but {} were notified",
+            executed,
+            transactions.len()
+        );
+    }
+    let last_hash = entries.last().map_or(start_hash, |entry| entry.hash);
+    if last_hash != blockhash {
+        anyhow::bail!("entries end at {} instead of the blockhash {}", last_hash, blockhash);
+    }
+
+    // The signatures of each entry and the hash of the entry before it
+    let mut entry_signatures: Vec<Vec<Signature>> = vec![];
+    let mut start_hashes = vec![];
+    let mut later_entries = vec![];
+    let mut previous_hash = start_hash;
+    let mut next_transaction = 0;
+    for entry in &entries {
+        let count = entry.executed_transaction_count as usize;
+        let signatures: Vec<Signature> = transactions[next_transaction..next_transaction + count]
+            .iter()
+            .flat_map(|transaction| transaction.signatures.iter().copied())
+            .collect();
+        next_transaction += count;
+
+        start_hashes.push(previous_hash);
+        previous_hash = entry.hash;
+        later_entries.push(PohEntry {
+            num_hashes: entry.num_hashes,
+            transactions_root: (count > 0).then(|| hash_signatures(&signatures)),
+        });
+        entry_signatures.push(signatures);
+    }
+
+    // Entries whose hash was checked against their transactions
+    let mut checked = HashSet::new();
+    signatures
+        .iter()
+        .map(|signature| {
+            let (entry_index, signature_index) = entry_signatures
+                .iter()
+                .enumerate()
+                .find_map(|(entry_index, signatures)| {
+                    let position = signatures.iter().position(|s| s == signature)?;
+                    Some((entry_index, position))
+                })
+                .ok_or_else(|| anyhow::anyhow!("transaction {} is not in the block", signature))?;
+            let entry = &later_entries[entry_index];
+            if checked.insert(entry_index)
+                && next_entry_hash(&start_hashes[entry_index], entry.num_hashes, entry.transactions_root)
+                    != entries[entry_index].hash
+            {
+                anyhow::bail!("entry {} does not hash to {}", entry_index, entries[entry_index].hash);
+            }
+            let signature_siblings = signature_levels(&entry_signatures[entry_index])
+                .iter()
+                .take_while(|level| level.len() > 1)
+                .enumerate()
+                .map(|(level, nodes)| {
+                    let position = signature_index >> level;
+                    let sibling = position ^ 1;
+                    nodes.get(sibling).copied().unwrap_or(nodes[position])
+                })
+                .collect();
+            Ok(TransactionProof {
+                signature: signature.as_ref().to_vec(),
+                entry_index: entry_index as u64,
+                start_hash: start_hashes[entry_index],
+                num_hashes: entry.num_hashes,
+                signature_index: signature_index as u64,
+                signature_siblings,
+                later_entries: later_entries[entry_index + 1..].to_vec(),
+            })
+        })
+        .collect()
+}
+
+/// Check that the transaction signed with `proof.signature` is in an entry whose PoH chain ends
+/// at `blockhash`
+pub fn verify_transaction_proof(proof: &TransactionProof, blockhash: Hash) -> anyhow::Result<()> {
+    Signature::try_from(proof.signature.as_slice())?;
+    let Some(transactions_root) = signature_root(
+        &proof.signature,
+        proof.signature_index,
+        &proof.signature_siblings,
+    ) else {
+        anyhow::bail!(
+            "signature index {} does not fit {} levels",
+            proof.signature_index,
+            proof.signature_siblings.len()
+        );
+    };
+    let mut poh = next_entry_hash(&proof.start_hash, proof.num_hashes, Some(transactions_root));
+    for entry in &proof.later_entries {
+        poh = next_entry_hash(&poh, entry.num_hashes, entry.transactions_root);
+    }
+    if poh != blockhash {
+        anyhow::bail!("PoH chain ends at {} instead of the blockhash {}", poh, blockhash);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::pubkey::Pubkey;
+
+    use super::*;
+    use crate::types::{EntryInfo, TransactionInfo};
+
+    fn transaction(index: usize, signatures: usize) -> TransactionInfo {
+        TransactionInfo {
+            slot: 5,
+            index,
+            signatures: (0..signatures).map(|_| Signature::new_unique()).collect(),
+            account_keys: vec![Pubkey::new_unique()],
+        }
+    }
+
+    /// Entries holding `counts` transactions each, hashed on from `start_hash`
+    fn block(start_hash: Hash, counts: &[u64]) -> (SlotEntries, Hash) {
+        let mut slot_entries = SlotEntries::default();
+        let mut poh = start_hash;
+        for (index, count) in counts.iter().enumerate() {
+            let first = slot_entries.transactions.len();
+            for i in 0..*count as usize {
+                slot_entries.transactions.push(transaction(first + i, 1 + i % 2));
+            }
+            let signatures: Vec<Signature> = slot_entries.transactions[first..]
+                .iter()
+                .flat_map(|transaction| transaction.signatures.clone())
+                .collect();
+            let transactions_root = (*count > 0).then(|| hash_signatures(&signatures));
+            poh = next_entry_hash(&poh, 3, transactions_root);
+            slot_entries.entries.push(EntryInfo {
+                slot: 5,
+                index,
+                num_hashes: 3,
+                hash: poh,
+                executed_transaction_count: *count,
+            });
+        }
+        (slot_entries, poh)
+    }
+
+    #[test]
+    fn test_signature_tree_matches_odd_levels() {
+        let signatures: Vec<Signature> = (0..5).map(|_| Signature::new_unique()).collect();
+        let levels = signature_levels(&signatures);
+        assert_eq!(levels.iter().map(Vec::len).collect::<Vec<_>>(), vec![5, 3, 2, 1]);
+        // the fifth leaf is paired with itself on the way up
+        let leaf = levels[0][4];
+        let node = hashv(&[INTERMEDIATE_PREFIX, leaf.as_ref(), leaf.as_ref()]);
+        let node = hashv(&[INTERMEDIATE_PREFIX, node.as_ref(), node.as_ref()]);
+        let root = hashv(&[INTERMEDIATE_PREFIX, levels[2][0].as_ref(), node.as_ref()]);
+        assert_eq!(hash_signatures(&signatures), root);
+        assert_eq!(hash_signatures(&signatures[..1]), hashv(&[LEAF_PREFIX, signatures[0].as_ref()]));
+    }
+
+    #[test]
+    fn test_prove_and_verify_transactions() {
+        let start_hash = Hash::new_unique();
+        let (mut slot_entries, blockhash) = block(start_hash, &[0, 3, 0, 5, 0]);
+        // notified out of order
+        slot_entries.transactions.reverse();
+        let last = slot_entries.transactions[0].signatures[0];
+        let first = slot_entries.transactions[7].signatures[0];
+
+        let proofs =
+            assemble_transaction_proofs(&slot_entries, start_hash, blockhash, &[first, last])
+                .unwrap();
+        assert_eq!(proofs[0].entry_index, 1);
+        assert_eq!(proofs[1].entry_index, 3);
+        assert_eq!(proofs[1].later_entries, vec![PohEntry { num_hashes: 3, transactions_root: None }]);
+        for proof in &proofs {
+            verify_transaction_proof(proof, blockhash).unwrap();
+            assert!(verify_transaction_proof(proof, Hash::new_unique()).is_err());
+        }
+
+        let mut tampered = proofs[1].clone();
+        tampered.signature = Signature::new_unique().as_ref().to_vec();
+        assert!(verify_transaction_proof(&tampered, blockhash).is_err());
+        let mut tampered = proofs[1].clone();
+        tampered.signature_siblings[0] = Hash::new_unique();
+        assert!(verify_transaction_proof(&tampered, blockhash).is_err());
+
+        assert!(assemble_transaction_proofs(&slot_entries, start_hash, blockhash, &[Signature::new_unique()]).is_err());
+        assert!(assemble_transaction_proofs(&slot_entries, start_hash, Hash::new_unique(), &[]).is_err());
+
+        // only the entries holding a proven transaction are hashed
+        let mut wrong_entry = slot_entries.clone();
+        wrong_entry.entries[1].num_hashes = 4;
+        assert!(assemble_transaction_proofs(&wrong_entry, start_hash, blockhash, &[last]).is_ok());
+        assert!(assemble_transaction_proofs(&wrong_entry, start_hash, blockhash, &[first]).is_err());
+
+        // a transaction was not notified
+        slot_entries.transactions.remove(3);
+        assert!(assemble_transaction_proofs(&slot_entries, start_hash, blockhash, &[]).is_err());
+    }
+}
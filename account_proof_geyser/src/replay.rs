@@ -0,0 +1,255 @@
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::types::Update;
+use crate::wire::{decode_frame, encode_frame};
+
+/// Ring buffer of the most recent confirmed `Update`s so that reconnecting clients can resume
+/// from the last slot they saw instead of only receiving future updates.
+///
+/// When backed by a log file every update is appended to disk as a wire frame, and the buffer is
+/// reloaded from the log on startup. The log is compacted once it holds twice the capacity.
+#[derive(Debug)]
+pub struct ReplayBuffer {
+    capacity: usize,
+    updates: VecDeque<Update>,
+    log: Option<ReplayLog>,
+}
+
+#[derive(Debug)]
+struct ReplayLog {
+    path: PathBuf,
+    file: File,
+    entries: usize,
+}
+
+impl ReplayBuffer {
+    /// In-memory buffer holding up to `capacity` updates. A capacity of 0 disables replay.
+    pub fn new(capacity: usize) -> Self {
+        ReplayBuffer {
+            capacity,
+            updates: VecDeque::with_capacity(capacity),
+            log: None,
+        }
+    }
+
+    /// Buffer backed by the log file at `path`, which is created if it doesn't exist yet
+    pub fn with_log<P: AsRef<Path>>(capacity: usize, path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut buffer = ReplayBuffer::new(capacity);
+
+        let mut entries = 0;
+        if path.exists() {
+            let mut bytes = Vec::new();
+            File::open(&path)?.read_to_end(&mut bytes)?;
+            let mut offset = 0;
+            // A truncated frame at the tail means we crashed mid-write, so everything before it is kept
+            while let Some((update, len)) = decode_frame::<Update>(&bytes[offset..])? {
+                buffer.push_in_memory(update);
+                offset += len;
+                entries += 1;
+            }
+            if offset < bytes.len() {
+                OpenOptions::new()
+                    .write(true)
+                    .open(&path)?
+                    .set_len(offset as u64)?;
+            }
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        buffer.log = Some(ReplayLog {
+            path,
+            file,
+            entries,
+        });
+        Ok(buffer)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.updates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.updates.is_empty()
+    }
+
+    /// Slot of the oldest update still available for replay
+    pub fn oldest_slot(&self) -> Option<u64> {
+        self.updates.front().map(|u| u.slot)
+    }
+
+    /// Slot of the most recent update
+    pub fn latest_slot(&self) -> Option<u64> {
+        self.updates.back().map(|u| u.slot)
+    }
+
+    pub fn push(&mut self, update: Update) -> io::Result<()> {
+        if self.capacity == 0 {
+            return Ok(());
+        }
+        if let Some(log) = self.log.as_mut() {
+            log.file.write_all(&encode_frame(&update)?)?;
+            log.entries += 1;
+        }
+        self.push_in_memory(update);
+
+        let needs_compaction = self
+            .log
+            .as_ref()
+            .map_or(false, |log| log.entries > 2 * self.capacity);
+        if needs_compaction {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    /// All buffered updates for slots strictly greater than `slot`, oldest first
+    pub fn updates_after(&self, slot: u64) -> Vec<Update> {
+        self.updates
+            .iter()
+            .filter(|u| u.slot > slot)
+            .cloned()
+            .collect()
+    }
+
+    fn push_in_memory(&mut self, update: Update) {
+        if self.capacity == 0 {
+            return;
+        }
+        while self.updates.len() >= self.capacity {
+            self.updates.pop_front();
+        }
+        self.updates.push_back(update);
+    }
+
+    /// Rewrite the log so that it only contains the updates currently held in memory
+    fn compact(&mut self) -> io::Result<()> {
+        let Some(log) = self.log.as_mut() else {
+            return Ok(());
+        };
+        let tmp_path = log.path.with_extension("compact");
+        {
+            let mut tmp = File::create(&tmp_path)?;
+            for update in &self.updates {
+                tmp.write_all(&encode_frame(update)?)?;
+            }
+            tmp.sync_all()?;
+        }
+        fs::rename(&tmp_path, &log.path)?;
+        log.file = OpenOptions::new().append(true).open(&log.path)?;
+        log.entries = self.updates.len();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::hash::Hash;
+
+    use super::*;
+    use crate::types::BankHashProof;
+
+    fn update_for_slot(slot: u64) -> Update {
+        Update {
+            slot,
+            root: Hash::new_unique(),
+            proof: BankHashProof {
+                proofs: vec![],
+                non_inclusion_proofs: vec![],
+                num_sigs: slot,
+                account_delta_root: Hash::new_unique(),
+                parent_bankhash: Hash::new_unique(),
+                blockhash: Hash::new_unique(),
+            },
+            votes: vec![],
+            copy_preimages: vec![],
+            transaction_proofs: vec![],
+        }
+    }
+
+    fn temp_log_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "replay_{}_{}_{}.log",
+            name,
+            std::process::id(),
+            rand::random::<u64>()
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_ring_buffer_evicts_oldest() {
+        let mut buffer = ReplayBuffer::new(3);
+        for slot in 10..15 {
+            buffer.push(update_for_slot(slot)).unwrap();
+        }
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.oldest_slot(), Some(12));
+        assert_eq!(buffer.latest_slot(), Some(14));
+
+        let slots: Vec<u64> = buffer.updates_after(12).iter().map(|u| u.slot).collect();
+        assert_eq!(slots, vec![13, 14]);
+        assert!(buffer.updates_after(14).is_empty());
+    }
+
+    #[test]
+    fn test_zero_capacity_disables_replay() {
+        let mut buffer = ReplayBuffer::new(0);
+        buffer.push(update_for_slot(1)).unwrap();
+        assert!(buffer.is_empty());
+        assert!(buffer.updates_after(0).is_empty());
+    }
+
+    #[test]
+    fn test_log_survives_restart_and_compacts() {
+        let path = temp_log_path("restart");
+        {
+            let mut buffer = ReplayBuffer::with_log(4, &path).unwrap();
+            for slot in 0..11 {
+                buffer.push(update_for_slot(slot)).unwrap();
+            }
+        }
+
+        let reloaded = ReplayBuffer::with_log(4, &path).unwrap();
+        let slots: Vec<u64> = reloaded.updates_after(0).iter().map(|u| u.slot).collect();
+        assert_eq!(slots, vec![7, 8, 9, 10]);
+        // compaction kept the log bounded
+        assert!(reloaded.log.as_ref().unwrap().entries <= 8);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_truncated_log_tail_is_ignored() {
+        let path = temp_log_path("truncated");
+        {
+            let mut buffer = ReplayBuffer::with_log(8, &path).unwrap();
+            buffer.push(update_for_slot(1)).unwrap();
+            buffer.push(update_for_slot(2)).unwrap();
+        }
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        let frame = encode_frame(&update_for_slot(3)).unwrap();
+        file.write_all(&frame[..frame.len() / 2]).unwrap();
+
+        let mut reloaded = ReplayBuffer::with_log(8, &path).unwrap();
+        assert_eq!(reloaded.len(), 2);
+        assert_eq!(reloaded.latest_slot(), Some(2));
+
+        // appending after the partial frame was dropped keeps the log readable
+        reloaded.push(update_for_slot(4)).unwrap();
+        drop(reloaded);
+        let reloaded = ReplayBuffer::with_log(8, &path).unwrap();
+        assert_eq!(reloaded.len(), 3);
+        assert_eq!(reloaded.latest_slot(), Some(4));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,698 @@
+use std::collections::HashSet;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crossbeam_channel::Sender;
+use log::error;
+use prometheus::IntGauge;
+use solana_sdk::pubkey::Pubkey;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::timeout;
+use tokio_rustls::TlsAcceptor;
+
+use crate::admin::{forward_request, AdminCommand, AdminRequest, AdminResponse};
+use crate::archive::ProofArchive;
+use crate::auth::AccessControl;
+use crate::config::Config;
+use crate::metrics::Metrics;
+use crate::replay::ReplayBuffer;
+use crate::tls::load_tls_acceptor;
+use crate::types::{GeyserMessage, Update};
+use crate::wire::{read_frame, write_frame, StreamRequest, StreamResponse};
+
+/// How long a freshly connected client has to complete the TLS and stream handshakes
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Most archived updates held in memory at once while replaying a resume from the archive
+pub const ARCHIVE_REPLAY_CHUNK: usize = 64;
+
+/// Most accounts a single subscriber may register in its handshake
+pub const MAX_WATCH_ACCOUNTS: usize = 64;
+
+/// State shared by every connection of the proof server
+pub struct StreamContext {
+    pub replay_buffer: Arc<Mutex<ReplayBuffer>>,
+    /// Serves resumes that reach further back than the replay buffer
+    pub archive: Option<ProofArchive>,
+    pub access_control: AccessControl,
+    pub metrics: Arc<Metrics>,
+    /// Hands accounts registered by subscribers to `process_messages`. Registration is refused
+    /// without it.
+    pub geyser_sender: Option<Sender<GeyserMessage>>,
+}
+
+/// Counts a connection in the `connected_subscribers` gauge for as long as it is held
+struct ConnectedSubscriber(IntGauge);
+
+impl ConnectedSubscriber {
+    fn new(gauge: &IntGauge) -> Self {
+        gauge.inc();
+        ConnectedSubscriber(gauge.clone())
+    }
+}
+
+impl Drop for ConnectedSubscriber {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+/// Keeps the accounts registered by a subscriber proven for as long as it is held
+struct WatchedAccounts {
+    pubkeys: Vec<String>,
+    geyser_sender: Sender<GeyserMessage>,
+}
+
+impl WatchedAccounts {
+    async fn register(
+        pubkeys: &[Pubkey],
+        geyser_sender: &Option<Sender<GeyserMessage>>,
+    ) -> Result<Self, String> {
+        if pubkeys.len() > MAX_WATCH_ACCOUNTS {
+            return Err(format!(
+                "cannot register more than {} accounts",
+                MAX_WATCH_ACCOUNTS
+            ));
+        }
+        let Some(geyser_sender) = geyser_sender else {
+            return Err("account registration is not available".to_string());
+        };
+        let pubkeys: Vec<String> = pubkeys.iter().map(|pubkey| pubkey.to_string()).collect();
+        let request = AdminRequest::WatchAccounts {
+            pubkeys: pubkeys.clone(),
+        };
+        match forward_request(request, geyser_sender).await {
+            AdminResponse::Error { message } => Err(message),
+            _ => Ok(WatchedAccounts {
+                pubkeys,
+                geyser_sender: geyser_sender.clone(),
+            }),
+        }
+    }
+}
+
+impl Drop for WatchedAccounts {
+    fn drop(&mut self) {
+        // Nobody waits for the response
+        let (reply, _) = tokio::sync::mpsc::unbounded_channel();
+        let request = AdminRequest::UnwatchAccounts {
+            pubkeys: std::mem::take(&mut self.pubkeys),
+        };
+        let _ = self
+            .geyser_sender
+            .send(GeyserMessage::AdminMessage(AdminCommand { request, reply }));
+    }
+}
+
+/// Streams confirmed updates to every connected client, optionally over TLS and behind API keys
+pub struct ProofServer {
+    tx: broadcast::Sender<Update>,
+    context: Arc<StreamContext>,
+    tls_acceptor: Option<TlsAcceptor>,
+}
+
+impl ProofServer {
+    pub fn new(
+        tx: broadcast::Sender<Update>,
+        context: StreamContext,
+        tls_acceptor: Option<TlsAcceptor>,
+    ) -> Self {
+        ProofServer {
+            tx,
+            context: Arc::new(context),
+            tls_acceptor,
+        }
+    }
+
+    pub fn from_config(
+        config: &Config,
+        tx: broadcast::Sender<Update>,
+        replay_buffer: Arc<Mutex<ReplayBuffer>>,
+        archive: Option<ProofArchive>,
+        metrics: Arc<Metrics>,
+        geyser_sender: Sender<GeyserMessage>,
+    ) -> io::Result<Self> {
+        let tls_acceptor = match &config.tls {
+            Some(tls) => Some(load_tls_acceptor(tls)?),
+            None => None,
+        };
+        let context = StreamContext {
+            replay_buffer,
+            archive,
+            access_control: AccessControl::from_config(config),
+            metrics,
+            geyser_sender: Some(geyser_sender),
+        };
+        Ok(Self::new(tx, context, tls_acceptor))
+    }
+
+    pub async fn run(self, bind_address: SocketAddr) -> io::Result<()> {
+        let listener = TcpListener::bind(bind_address).await?;
+        self.serve(listener).await
+    }
+
+    pub async fn serve(self, listener: TcpListener) -> io::Result<()> {
+        loop {
+            let (socket, peer) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    error!("Failed to accept connection: {:?}", e);
+                    continue;
+                }
+            };
+            // Subscribe before the handshake so that nothing published while we replay is missed
+            let rx = self.tx.subscribe();
+            let context = self.context.clone();
+            let tls_acceptor = self.tls_acceptor.clone();
+            tokio::spawn(async move {
+                let result = match tls_acceptor {
+                    Some(acceptor) => match timeout(HANDSHAKE_TIMEOUT, acceptor.accept(socket)).await {
+                        Ok(Ok(stream)) => handle_connection(stream, peer.ip(), rx, &context).await,
+                        Ok(Err(e)) => Err(e),
+                        Err(_) => Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            "TLS handshake timed out",
+                        )),
+                    },
+                    None => handle_connection(socket, peer.ip(), rx, &context).await,
+                };
+                if let Err(e) = result {
+                    error!("Closing connection to {}: {:?}", peer, e);
+                }
+            });
+        }
+    }
+}
+
+/// Read the client's handshake and check its credentials, replay archived and buffered updates if
+/// it asked to resume, then forward live updates. A client that lags behind the broadcast channel
+/// is disconnected so it can resume from its last slot.
+pub async fn handle_connection<S>(
+    mut socket: S,
+    peer: IpAddr,
+    mut rx: broadcast::Receiver<Update>,
+    context: &StreamContext,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let request: StreamRequest = match timeout(HANDSHAKE_TIMEOUT, read_frame(&mut socket)).await {
+        Ok(Ok(Some(request))) => request,
+        Ok(Ok(None)) => return Ok(()),
+        Ok(Err(e)) => return Err(e),
+        Err(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "client did not send a stream request",
+            ))
+        }
+    };
+
+    // Held until the connection closes so that it counts against the client's limit
+    let _subscription = match context.access_control.admit(request.auth_token.as_deref(), peer) {
+        Ok(subscription) => subscription,
+        Err(e) => {
+            write_frame(&mut socket, &StreamResponse::Rejected(e.to_string())).await?;
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, e));
+        }
+    };
+    // Registered before accepting so that the next confirmed update already proves the accounts.
+    // Each registered account adds proving work, so only clients with an API key may register.
+    let _watched = if request.watch_accounts.is_empty() {
+        None
+    } else if !context.access_control.requires_token() {
+        let reason = "account registration requires an API key".to_string();
+        write_frame(&mut socket, &StreamResponse::Rejected(reason.clone())).await?;
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, reason));
+    } else {
+        match WatchedAccounts::register(&request.watch_accounts, &context.geyser_sender).await {
+            Ok(watched) => Some(watched),
+            Err(reason) => {
+                write_frame(&mut socket, &StreamResponse::Rejected(reason.clone())).await?;
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, reason));
+            }
+        }
+    };
+    write_frame(&mut socket, &StreamResponse::Accepted).await?;
+    let _subscriber = ConnectedSubscriber::new(&context.metrics.connected_subscribers);
+
+    let mut replayed = HashSet::new();
+    let mut last_archived = None;
+    if let Some(resume_from_slot) = request.resume_from_slot {
+        let (oldest_buffered, backlog) = {
+            let replay_buffer = context.replay_buffer.lock().unwrap();
+            (
+                replay_buffer.oldest_slot(),
+                replay_buffer.updates_after(resume_from_slot),
+            )
+        };
+        // Slots that already fell out of the replay buffer are read from the archive. They are
+        // read a chunk at a time, so that a resume from far back doesn't load the whole archive,
+        // and off the runtime since sled reads block.
+        let first_missing = resume_from_slot.saturating_add(1);
+        let last_missing = match oldest_buffered {
+            Some(oldest) => oldest.checked_sub(1),
+            None => Some(u64::MAX),
+        };
+        if let (Some(archive), Some(last_missing)) = (&context.archive, last_missing) {
+            let mut start = first_missing;
+            while start <= last_missing {
+                let archive = archive.clone();
+                let chunk = tokio::task::spawn_blocking(move || {
+                    archive.updates_in_range(start..=last_missing, ARCHIVE_REPLAY_CHUNK)
+                })
+                .await
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+                let Some(last) = chunk.last().map(|update| update.slot) else {
+                    break;
+                };
+                let complete = chunk.len() < ARCHIVE_REPLAY_CHUNK;
+                for update in chunk {
+                    write_frame(&mut socket, &update).await?;
+                }
+                last_archived = Some(last);
+                match last.checked_add(1) {
+                    Some(next) if !complete => start = next,
+                    _ => break,
+                }
+            }
+        }
+
+        for update in backlog {
+            replayed.insert(update.slot);
+            write_frame(&mut socket, &update).await?;
+        }
+    }
+
+    loop {
+        match rx.recv().await {
+            Ok(update) => {
+                // Updates published between subscribing and taking the backlog were already replayed
+                if replayed.remove(&update.slot)
+                    || matches!(last_archived, Some(last) if update.slot <= last)
+                {
+                    continue;
+                }
+                write_frame(&mut socket, &update).await?;
+            }
+            Err(RecvError::Lagged(skipped)) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("subscriber lagged behind by {} updates", skipped),
+                ));
+            }
+            Err(RecvError::Closed) => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use solana_sdk::hash::Hash;
+    use tokio::net::TcpStream;
+    use tokio_rustls::rustls::{self, Certificate, RootCertStore, ServerName};
+    use tokio_rustls::TlsConnector;
+
+    use super::*;
+    use crate::admin::execute_admin_request;
+    use crate::config::{ApiKeyConfig, TlsConfig};
+    use crate::types::{Accumulators, BankHashProof, MonitoredAccounts};
+
+    const PEER: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    fn update_for_slot(slot: u64) -> Update {
+        Update {
+            slot,
+            root: Hash::new_unique(),
+            proof: BankHashProof {
+                proofs: vec![],
+                non_inclusion_proofs: vec![],
+                num_sigs: 0,
+                account_delta_root: Hash::new_unique(),
+                parent_bankhash: Hash::new_unique(),
+                blockhash: Hash::new_unique(),
+            },
+            votes: vec![],
+            copy_preimages: vec![],
+            transaction_proofs: vec![],
+        }
+    }
+
+    fn api_keys() -> Vec<ApiKeyConfig> {
+        vec![ApiKeyConfig {
+            name: "partner".to_string(),
+            key: "partner-secret".to_string(),
+            max_subscriptions: Some(1),
+        }]
+    }
+
+    /// Write a self-signed certificate for `localhost` to temporary files
+    fn self_signed_tls_config() -> (TlsConfig, Vec<u8>) {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let dir = std::env::temp_dir().join(format!(
+            "proof_server_tls_{}_{}",
+            std::process::id(),
+            rand::random::<u64>()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cert_path = dir.join("cert.pem");
+        let key_path = dir.join("key.pem");
+        std::fs::write(&cert_path, cert.serialize_pem().unwrap()).unwrap();
+        std::fs::write(&key_path, cert.serialize_private_key_pem()).unwrap();
+        (
+            TlsConfig {
+                cert_path: cert_path.to_string_lossy().to_string(),
+                key_path: key_path.to_string_lossy().to_string(),
+            },
+            cert.serialize_der().unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_resume_replays_then_streams_live() {
+        let (tx, _) = broadcast::channel(16);
+        let mut replay_buffer = ReplayBuffer::new(8);
+        for slot in 1..=5 {
+            replay_buffer.push(update_for_slot(slot)).unwrap();
+        }
+        let replay_buffer = Arc::new(Mutex::new(replay_buffer));
+
+        let (mut client, server) = tokio::io::duplex(1 << 20);
+        let rx = tx.subscribe();
+        // slot 5 was published after the client subscribed but is also in the backlog
+        tx.send(update_for_slot(5)).unwrap();
+        tokio::spawn(async move {
+            let context = StreamContext {
+                replay_buffer,
+                archive: None,
+                access_control: AccessControl::new(&[], 1),
+                metrics: Arc::new(Metrics::new()),
+                geyser_sender: None,
+            };
+            handle_connection(server, PEER, rx, &context).await
+        });
+
+        write_frame(
+            &mut client,
+            &StreamRequest {
+                resume_from_slot: Some(2),
+                auth_token: None,
+                watch_accounts: vec![],
+            },
+        )
+        .await
+        .unwrap();
+        let response: StreamResponse = read_frame(&mut client).await.unwrap().unwrap();
+        assert_eq!(response, StreamResponse::Accepted);
+        tx.send(update_for_slot(6)).unwrap();
+
+        let mut slots = vec![];
+        for _ in 0..4 {
+            let update: Update = read_frame(&mut client).await.unwrap().unwrap();
+            slots.push(update.slot);
+        }
+        assert_eq!(slots, vec![3, 4, 5, 6]);
+    }
+
+    #[tokio::test]
+    async fn test_resume_reads_archive_beyond_replay_buffer() {
+        let archive_path = std::env::temp_dir().join(format!(
+            "proof_server_archive_{}_{}",
+            std::process::id(),
+            rand::random::<u64>()
+        ));
+        let archive = ProofArchive::open(&archive_path, None, false).unwrap();
+        let mut replay_buffer = ReplayBuffer::new(2);
+        for slot in 1..=6 {
+            let update = update_for_slot(slot);
+            archive.insert(&update, &[]).unwrap();
+            replay_buffer.push(update).unwrap();
+        }
+
+        let (tx, _) = broadcast::channel(16);
+        let (mut client, server) = tokio::io::duplex(1 << 20);
+        let rx = tx.subscribe();
+        let context = StreamContext {
+            replay_buffer: Arc::new(Mutex::new(replay_buffer)),
+            archive: Some(archive),
+            access_control: AccessControl::new(&[], 1),
+            metrics: Arc::new(Metrics::new()),
+            geyser_sender: None,
+        };
+        tokio::spawn(async move { handle_connection(server, PEER, rx, &context).await });
+
+        write_frame(
+            &mut client,
+            &StreamRequest {
+                resume_from_slot: Some(1),
+                auth_token: None,
+                watch_accounts: vec![],
+            },
+        )
+        .await
+        .unwrap();
+        let response: StreamResponse = read_frame(&mut client).await.unwrap().unwrap();
+        assert_eq!(response, StreamResponse::Accepted);
+
+        let mut slots = vec![];
+        for _ in 0..5 {
+            let update: Update = read_frame(&mut client).await.unwrap().unwrap();
+            slots.push(update.slot);
+        }
+        // 2..=4 come from the archive, 5 and 6 from the replay buffer
+        assert_eq!(slots, vec![2, 3, 4, 5, 6]);
+        let _ = std::fs::remove_dir_all(archive_path);
+    }
+
+    #[tokio::test]
+    async fn test_resume_replays_the_archive_in_chunks() {
+        let archive_path = std::env::temp_dir().join(format!(
+            "proof_server_archive_{}_{}",
+            std::process::id(),
+            rand::random::<u64>()
+        ));
+        let archive = ProofArchive::open(&archive_path, None, false).unwrap();
+        let archived = 2 * ARCHIVE_REPLAY_CHUNK as u64 + 5;
+        for slot in 1..=archived {
+            archive.insert(&update_for_slot(slot), &[]).unwrap();
+        }
+
+        // without a replay buffer everything after the resume slot comes from the archive
+        let (tx, _) = broadcast::channel(16);
+        let (mut client, server) = tokio::io::duplex(1 << 20);
+        let rx = tx.subscribe();
+        let context = StreamContext {
+            replay_buffer: Arc::new(Mutex::new(ReplayBuffer::new(0))),
+            archive: Some(archive),
+            access_control: AccessControl::new(&[], 1),
+            metrics: Arc::new(Metrics::new()),
+            geyser_sender: None,
+        };
+        tokio::spawn(async move { handle_connection(server, PEER, rx, &context).await });
+
+        write_frame(
+            &mut client,
+            &StreamRequest {
+                resume_from_slot: Some(0),
+                auth_token: None,
+                watch_accounts: vec![],
+            },
+        )
+        .await
+        .unwrap();
+        let response: StreamResponse = read_frame(&mut client).await.unwrap().unwrap();
+        assert_eq!(response, StreamResponse::Accepted);
+
+        let mut slots = vec![];
+        for _ in 1..=archived {
+            let update: Update = read_frame(&mut client).await.unwrap().unwrap();
+            slots.push(update.slot);
+        }
+        assert_eq!(slots, (1..=archived).collect::<Vec<_>>());
+
+        // live updates for slots that were already replayed are skipped
+        tx.send(update_for_slot(archived)).unwrap();
+        tx.send(update_for_slot(archived + 1)).unwrap();
+        let update: Update = read_frame(&mut client).await.unwrap().unwrap();
+        assert_eq!(update.slot, archived + 1);
+        let _ = std::fs::remove_dir_all(archive_path);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_token_is_rejected() {
+        let (tx, _) = broadcast::channel(16);
+        let replay_buffer = Arc::new(Mutex::new(ReplayBuffer::new(0)));
+        let (mut client, server) = tokio::io::duplex(1 << 16);
+        let rx = tx.subscribe();
+        let connection = tokio::spawn(async move {
+            let context = StreamContext {
+                replay_buffer,
+                archive: None,
+                access_control: AccessControl::new(&api_keys(), 1),
+                metrics: Arc::new(Metrics::new()),
+                geyser_sender: None,
+            };
+            handle_connection(server, PEER, rx, &context).await
+        });
+
+        write_frame(
+            &mut client,
+            &StreamRequest {
+                resume_from_slot: None,
+                auth_token: Some("wrong".to_string()),
+                watch_accounts: vec![],
+            },
+        )
+        .await
+        .unwrap();
+        let response: StreamResponse = read_frame(&mut client).await.unwrap().unwrap();
+        assert_eq!(response, StreamResponse::Rejected("invalid API key".to_string()));
+        let err = connection.await.unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn test_watch_accounts_for_the_connection() {
+        let partner = ApiKeyConfig {
+            max_subscriptions: None,
+            ..api_keys().remove(0)
+        };
+        let context = move |api_keys: &[ApiKeyConfig], geyser_sender| StreamContext {
+            replay_buffer: Arc::new(Mutex::new(ReplayBuffer::new(0))),
+            archive: None,
+            access_control: AccessControl::new(api_keys, 2),
+            metrics: Arc::new(Metrics::new()),
+            geyser_sender,
+        };
+        let watched = Pubkey::new_unique();
+        let request = StreamRequest {
+            resume_from_slot: None,
+            auth_token: Some(partner.key.clone()),
+            watch_accounts: vec![watched],
+        };
+        let (tx, _) = broadcast::channel(16);
+
+        // without API keys anyone could register, so registration is refused
+        let (unused_sender, _) = crossbeam_channel::unbounded();
+        let (mut client, server) = tokio::io::duplex(1 << 16);
+        let rx = tx.subscribe();
+        tokio::spawn(async move {
+            handle_connection(server, PEER, rx, &context(&[], Some(unused_sender))).await
+        });
+        write_frame(&mut client, &request).await.unwrap();
+        let response: StreamResponse = read_frame(&mut client).await.unwrap().unwrap();
+        assert_eq!(
+            response,
+            StreamResponse::Rejected("account registration requires an API key".to_string())
+        );
+
+        // without a way to reach the plugin's accounts, registration is refused
+        let (mut client, server) = tokio::io::duplex(1 << 16);
+        let rx = tx.subscribe();
+        let keys = vec![partner.clone()];
+        tokio::spawn(async move { handle_connection(server, PEER, rx, &context(&keys, None)).await });
+        write_frame(&mut client, &request).await.unwrap();
+        let response: StreamResponse = read_frame(&mut client).await.unwrap().unwrap();
+        assert!(matches!(response, StreamResponse::Rejected(_)));
+
+        // stands in for process_messages, reporting every request it applies
+        let (geyser_sender, geyser_receiver) = crossbeam_channel::unbounded();
+        let (applied_tx, mut applied) = tokio::sync::mpsc::unbounded_channel();
+        std::thread::spawn(move || {
+            let mut accumulators = Accumulators::default();
+            let mut monitored = MonitoredAccounts::default();
+            while let Ok(GeyserMessage::AdminMessage(command)) = geyser_receiver.recv() {
+                let response =
+                    execute_admin_request(command.request.clone(), &mut accumulators, &mut monitored);
+                let _ = applied_tx.send((command.request, monitored.proven_accounts()));
+                let _ = command.reply.send(response);
+            }
+        });
+
+        let (mut client, server) = tokio::io::duplex(1 << 16);
+        let rx = tx.subscribe();
+        let keys = vec![partner];
+        let connection = tokio::spawn(async move {
+            handle_connection(server, PEER, rx, &context(&keys, Some(geyser_sender))).await
+        });
+        write_frame(&mut client, &request).await.unwrap();
+        let response: StreamResponse = read_frame(&mut client).await.unwrap().unwrap();
+        assert_eq!(response, StreamResponse::Accepted);
+        let (registered, proven) = applied.recv().await.unwrap();
+        assert!(matches!(registered, AdminRequest::WatchAccounts { .. }));
+        assert_eq!(proven, vec![watched]);
+
+        // the accounts are released when the connection ends
+        drop(tx);
+        connection.await.unwrap().unwrap();
+        let (released, proven) = applied.recv().await.unwrap();
+        assert!(matches!(released, AdminRequest::UnwatchAccounts { .. }));
+        assert!(proven.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_tls_with_token_and_subscription_limit() {
+        let (tls_config, cert_der) = self_signed_tls_config();
+        let (tx, _) = broadcast::channel(16);
+        let context = StreamContext {
+            replay_buffer: Arc::new(Mutex::new(ReplayBuffer::new(0))),
+            archive: None,
+            access_control: AccessControl::new(&api_keys(), 8),
+            metrics: Arc::new(Metrics::new()),
+            geyser_sender: None,
+        };
+        let server = ProofServer::new(
+            tx.clone(),
+            context,
+            Some(load_tls_acceptor(&tls_config).unwrap()),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(server.serve(listener));
+
+        let mut roots = RootCertStore::empty();
+        roots.add(&Certificate(cert_der)).unwrap();
+        let connector = TlsConnector::from(Arc::new(
+            rustls::ClientConfig::builder()
+                .with_safe_defaults()
+                .with_root_certificates(roots)
+                .with_no_client_auth(),
+        ));
+        let request = StreamRequest {
+            resume_from_slot: None,
+            auth_token: Some("partner-secret".to_string()),
+            watch_accounts: vec![],
+        };
+
+        let tcp = TcpStream::connect(addr).await.unwrap();
+        let mut first = connector
+            .connect(ServerName::try_from("localhost").unwrap(), tcp)
+            .await
+            .unwrap();
+        write_frame(&mut first, &request).await.unwrap();
+        let response: StreamResponse = read_frame(&mut first).await.unwrap().unwrap();
+        assert_eq!(response, StreamResponse::Accepted);
+
+        // the partner key only allows a single concurrent subscription
+        let tcp = TcpStream::connect(addr).await.unwrap();
+        let mut second = connector
+            .connect(ServerName::try_from("localhost").unwrap(), tcp)
+            .await
+            .unwrap();
+        write_frame(&mut second, &request).await.unwrap();
+        let response: StreamResponse = read_frame(&mut second).await.unwrap().unwrap();
+        assert!(matches!(response, StreamResponse::Rejected(_)));
+
+        tx.send(update_for_slot(42)).unwrap();
+        let update: Update = read_frame(&mut first).await.unwrap().unwrap();
+        assert_eq!(update.slot, 42);
+    }
+}
@@ -0,0 +1,49 @@
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+use tokio_rustls::rustls::{Certificate, PrivateKey, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+use crate::config::TlsConfig;
+
+/// Build a TLS acceptor from the PEM certificate chain and private key referenced by `tls`
+pub fn load_tls_acceptor(tls: &TlsConfig) -> io::Result<TlsAcceptor> {
+    let certs = load_certs(&tls.cert_path)?;
+    let key = load_private_key(&tls.key_path)?;
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+pub fn load_certs<P: AsRef<Path>>(path: P) -> io::Result<Vec<Certificate>> {
+    let mut reader = BufReader::new(File::open(path.as_ref())?);
+    let certs = rustls_pemfile::certs(&mut reader)?;
+    if certs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("no certificates found in {}", path.as_ref().display()),
+        ));
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+pub fn load_private_key<P: AsRef<Path>>(path: P) -> io::Result<PrivateKey> {
+    let mut reader = BufReader::new(File::open(path.as_ref())?);
+    for item in rustls_pemfile::read_all(&mut reader)? {
+        match item {
+            rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::ECKey(key) => return Ok(PrivateKey(key)),
+            _ => {}
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("no private key found in {}", path.as_ref().display()),
+    ))
+}
@@ -1,60 +1,206 @@
 use std::collections::HashMap;
 
 use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
 use solana_geyser_plugin_interface::geyser_plugin_interface::{ReplicaBlockInfoV2, SlotStatus};
-use solana_sdk::hash::Hash;
+use solana_sdk::hash::{hashv, Hash};
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::Signature;
 use solana_sdk::message::legacy::Message;
 
+use copy::CopyEvent;
+
+use crate::admin::AdminCommand;
+use crate::copy_events::accumulate_digest;
+
 pub type AccountHashAccumulator = HashMap<u64, AccountHashMap>;
 pub type TransactionSigAccumulator = HashMap<u64, u64>;
 pub type SlotHashProofAccumulator = HashMap<u64, (Hash, BankHashProof)>;
 pub type VoteAccumulator = HashMap<u64, VoteHashMap>;
 pub type VoteHashMap = HashMap<Signature,VoteInfo>;
 pub type AccountHashMap = HashMap<Pubkey, (u64, Hash, AccountInfo)>;
+/// Copy events of each slot, with the index in the block of the transaction that emitted them
+pub type CopyEventAccumulator = HashMap<u64, Vec<(usize, Vec<CopyEvent>)>>;
+pub type EntryAccumulator = HashMap<u64, SlotEntries>;
 
-#[derive(Clone, Debug, BorshSerialize, BorshDeserialize)]
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
 pub struct Proof {
     pub path: Vec<usize>, // Position in the chunk (between 0 and 15) for each level.
     pub siblings: Vec<Vec<Hash>>, // Sibling hashes at each level.
 }
 
-#[derive(Clone, Debug, BorshSerialize, BorshDeserialize)]
+/// Everything a slot's bank hash is computed from
+#[derive(Clone, Debug, PartialEq, Eq, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
 pub struct BankHashComponents {
-    parent_bankhash: Hash,
-    accounts_delta_hash: Hash,
-    num_sigs: u64,
-    current_blockhash: Hash
+    pub parent_bankhash: Hash,
+    pub accounts_delta_hash: Hash,
+    pub num_sigs: u64,
+    pub current_blockhash: Hash
+}
+
+impl BankHashComponents {
+    pub fn bank_hash(&self) -> Hash {
+        hashv(&[
+            self.parent_bankhash.as_ref(),
+            self.accounts_delta_hash.as_ref(),
+            &self.num_sigs.to_le_bytes(),
+            self.current_blockhash.as_ref(),
+        ])
+    }
+}
+
+impl From<&BankHashProof> for BankHashComponents {
+    fn from(proof: &BankHashProof) -> Self {
+        BankHashComponents {
+            parent_bankhash: proof.parent_bankhash,
+            accounts_delta_hash: proof.account_delta_root,
+            num_sigs: proof.num_sigs,
+            current_blockhash: proof.blockhash,
+        }
+    }
 }
 
-#[derive(Clone, Debug, BorshSerialize, BorshDeserialize)]
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
 pub struct Data {
     pub pubkey: Pubkey,
     pub hash: Hash,
     pub account: AccountInfo,
 }
 
-#[derive(Clone, Debug, BorshSerialize, BorshDeserialize)]
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
 pub struct AccountDeltaProof(pub Pubkey, pub (Data, Proof));
 
-#[derive(Clone, Debug, BorshSerialize, BorshDeserialize)]
+/// Proof that `pubkey` was not modified in a slot, made of the leaves of the accounts delta tree
+/// on either side of where it would sit. Each neighbour carries its account so that its leaf hash
+/// is bound to its pubkey.
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+pub struct AccountDeltaNonInclusionProof {
+    pub pubkey: Pubkey,
+    /// The leaf with the largest pubkey below `pubkey`. Absent when `pubkey` sorts before every leaf.
+    pub left: Option<AccountDeltaProof>,
+    /// The leaf with the smallest pubkey above `pubkey`. Absent when `pubkey` sorts after every leaf.
+    pub right: Option<AccountDeltaProof>,
+}
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
 pub struct BankHashProof {
     pub proofs: Vec<AccountDeltaProof>,
+    /// For the monitored accounts that were not modified in the slot
+    pub non_inclusion_proofs: Vec<AccountDeltaNonInclusionProof>,
     pub num_sigs: u64,
     pub account_delta_root: Hash,
     pub parent_bankhash: Hash,
     pub blockhash: Hash,
 }
 
-#[derive(Clone, Debug, BorshSerialize, BorshDeserialize)]
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
 pub struct Update {
     pub slot: u64,
     pub root: Hash,
     pub proof: BankHashProof,
+    /// Votes for `root` by the configured `vote_accounts` seen by the time `slot` was confirmed
+    pub votes: Vec<VoteProof>,
+    /// What was accumulated into each proven copy PDA during `slot`
+    pub copy_preimages: Vec<CopyPreimages>,
+    /// Inclusion in the block of the transactions that reference a proven account
+    pub transaction_proofs: Vec<TransactionProof>,
+}
+
+/// A PoH entry after the one holding a proven transaction, enough to carry the hash chain forward
+#[derive(Clone, Debug, PartialEq, Eq, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+pub struct PohEntry {
+    pub num_hashes: u64,
+    /// Root of the signatures of the entry's transactions, `None` for ticks
+    pub transactions_root: Option<Hash>,
+}
+
+/// Proof that a transaction is in an entry of the block: hashing on from the entry, the PoH chain
+/// ends at the blockhash, which the bank hash commits to
+#[derive(Clone, Debug, PartialEq, Eq, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+pub struct TransactionProof {
+    /// The first signature of the transaction, which identifies it
+    pub signature: Vec<u8>,
+    /// Index in the block of the entry holding the transaction
+    pub entry_index: u64,
+    /// Hash of the previous entry, or the parent's blockhash for the first entry
+    pub start_hash: Hash,
+    pub num_hashes: u64,
+    /// Position of the signature among the signatures of every transaction in the entry
+    pub signature_index: u64,
+    /// Sibling hashes from the signature's leaf up to the root mixed into the entry
+    pub signature_siblings: Vec<Hash>,
+    /// The entries after it, the last of which hashes to the blockhash
+    pub later_entries: Vec<PohEntry>,
+}
+
+/// A hash accumulated into a copy PDA, as emitted by the `copy` program
+#[derive(Clone, Debug, PartialEq, Eq, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+pub struct CopyPreimage {
+    pub source: Pubkey,
+    pub hash: Hash,
+    /// `(offset, len)` of the account data that was hashed, when it wasn't the whole account
+    pub range: Option<(u64, u64)>,
+    /// Version of the hashing scheme of `hash`
+    pub version: u8,
+}
+
+/// Every hash accumulated into `copy_account` during the slot, in order, so that its digest can be
+/// recomputed without the other accounts that were copied
+#[derive(Clone, Debug, PartialEq, Eq, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+pub struct CopyPreimages {
+    pub copy_account: Pubkey,
+    pub preimages: Vec<CopyPreimage>,
+}
+
+impl CopyPreimages {
+    /// The digest the copy program folds the preimages into
+    pub fn digest(&self) -> Option<[u8; 32]> {
+        accumulate_digest(None, self.preimages.iter().map(|preimage| preimage.hash.to_bytes()))
+    }
+}
+
+impl From<&CopyEvent> for CopyPreimage {
+    fn from(event: &CopyEvent) -> Self {
+        CopyPreimage {
+            source: event.source,
+            hash: Hash::new_from_array(event.hash),
+            range: event.range.map(|range| (range.offset, range.len)),
+            version: event.version,
+        }
+    }
+}
+
+/// A signed vote transaction, kept so that the bank hash it votes for can be checked offline
+#[derive(Clone, Debug, PartialEq, Eq, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+pub struct VoteProof {
+    /// Signature of the first account key (the fee payer) over `message`
+    pub signature: Vec<u8>,
+    /// The serialized legacy transaction message
+    pub message: Vec<u8>,
+}
+
+/// What a `VoteProof` attests to once its signature has been checked
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerifiedVote {
+    /// The authorized voter of `vote_account` that signed the vote instruction
+    pub authority: Pubkey,
+    pub vote_account: Pubkey,
+    /// The latest slot voted on
+    pub slot: u64,
+    /// The bank hash of `slot`
+    pub bank_hash: Hash,
+}
+
+impl From<&VoteInfo> for VoteProof {
+    fn from(vote: &VoteInfo) -> Self {
+        VoteProof {
+            signature: vote.signature.as_ref().to_vec(),
+            message: vote.message.serialize(),
+        }
+    }
 }
 
-#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
 pub struct AccountInfo {
     /// The Pubkey for the account
     pub pubkey: Pubkey,
@@ -103,9 +249,38 @@ impl Default for AccountInfo {
 #[derive(Debug, Clone)]
 pub struct TransactionInfo {
     pub slot: u64,
-    pub num_sigs: u64,
+    /// Index of the transaction in the block
+    pub index: usize,
+    pub signatures: Vec<Signature>,
+    pub account_keys: Vec<Pubkey>,
 }
 
+#[derive(Debug, Clone)]
+pub struct EntryInfo {
+    pub slot: u64,
+    /// Index of the entry in the block
+    pub index: usize,
+    pub num_hashes: u64,
+    pub hash: Hash,
+    pub executed_transaction_count: u64,
+}
+
+/// The PoH entries of a slot and the transactions they hold, as notified
+#[derive(Debug, Clone, Default)]
+pub struct SlotEntries {
+    pub entries: Vec<EntryInfo>,
+    pub transactions: Vec<TransactionInfo>,
+}
+
+
+/// Copy events emitted by a successful transaction
+#[derive(Debug, Clone)]
+pub struct CopyInfo {
+    pub slot: u64,
+    /// Index of the transaction in the block
+    pub index: usize,
+    pub events: Vec<CopyEvent>,
+}
 
 #[derive(Debug, Clone)]
 pub struct VoteInfo {
@@ -116,9 +291,19 @@ pub struct VoteInfo {
     pub message: Message
 }
 
+impl VoteInfo {
+    /// The vote account the vote instruction is applied to
+    pub fn vote_account(&self) -> Option<Pubkey> {
+        let instruction = self.message.instructions.first()?;
+        let index = *instruction.accounts.first()?;
+        self.message.account_keys.get(index as usize).copied()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct BlockInfo {
     pub slot: u64,
+    pub parent_slot: u64,
     pub parent_bankhash: String,
     pub blockhash: String,
     pub executed_transaction_count: u64,
@@ -128,6 +313,7 @@ impl<'a> From<&'a ReplicaBlockInfoV2<'a>> for BlockInfo {
     fn from(block: &'a ReplicaBlockInfoV2<'a>) -> Self {
         Self {
             slot: block.slot,
+            parent_slot: block.parent_slot,
             parent_bankhash: block.parent_blockhash.to_string(),
             blockhash: block.blockhash.to_string(),
             executed_transaction_count: block.executed_transaction_count,
@@ -141,11 +327,179 @@ pub struct SlotInfo {
     pub status: SlotStatus,
 }
 
+/// Reasons for which no update can be generated for a confirmed slot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfirmedSlotError {
+    BlockMissing,
+    TransactionsMissing,
+    AccountHashesMissing,
+    SlotHashesMissing,
+    /// The SlotHashes account data doesn't deserialize
+    InvalidSlotHashes,
+    /// The block's parent bank hash or blockhash isn't a valid hash
+    InvalidBlockHashes,
+    MonitoredAccountsNotModified(u64),
+}
+
+impl ConfirmedSlotError {
+    /// Short label used when counting failures in metrics
+    pub fn reason(&self) -> &'static str {
+        match self {
+            ConfirmedSlotError::BlockMissing => "block_missing",
+            ConfirmedSlotError::TransactionsMissing => "transactions_missing",
+            ConfirmedSlotError::AccountHashesMissing => "account_hashes_missing",
+            ConfirmedSlotError::SlotHashesMissing => "slot_hashes_missing",
+            ConfirmedSlotError::InvalidSlotHashes => "invalid_slot_hashes",
+            ConfirmedSlotError::InvalidBlockHashes => "invalid_block_hashes",
+            ConfirmedSlotError::MonitoredAccountsNotModified(_) => "accounts_not_modified",
+        }
+    }
+}
+
+impl std::fmt::Display for ConfirmedSlotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConfirmedSlotError::BlockMissing => write!(f, "block not available"),
+            ConfirmedSlotError::TransactionsMissing => write!(f, "list of txns not available"),
+            ConfirmedSlotError::AccountHashesMissing => write!(f, "account hashes not available"),
+            ConfirmedSlotError::SlotHashesMissing => write!(f, "SlotHashes account not modified"),
+            ConfirmedSlotError::InvalidSlotHashes => write!(f, "SlotHashes account data is invalid"),
+            ConfirmedSlotError::InvalidBlockHashes => write!(f, "block hashes are invalid"),
+            ConfirmedSlotError::MonitoredAccountsNotModified(slot) => {
+                write!(f, "monitored account not modified for slot: {}", slot)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfirmedSlotError {}
+
 #[derive(Debug, Clone)]
 pub enum GeyserMessage {
     AccountMessage(AccountInfo),
     BlockMessage(BlockInfo),
     TransactionMessage(TransactionInfo),
     SlotMessage(SlotInfo),
-    VoteMessage(VoteInfo)
+    VoteMessage(VoteInfo),
+    CopyMessage(CopyInfo),
+    EntryMessage(EntryInfo),
+    AdminMessage(AdminCommand),
+}
+
+/// Per-slot state held by `process_messages`. Entries move from the raw to the processed
+/// accumulators when a slot is processed and are removed once the slot is confirmed.
+#[derive(Debug, Default)]
+pub struct Accumulators {
+    pub raw_slot_account_accumulator: AccountHashAccumulator,
+    pub processed_slot_account_accumulator: AccountHashAccumulator,
+    pub raw_transaction_accumulator: TransactionSigAccumulator,
+    pub processed_transaction_accumulator: TransactionSigAccumulator,
+    pub raw_vote_accumulator: VoteAccumulator,
+    pub processed_vote_accumulator: VoteAccumulator,
+    pub raw_copy_accumulator: CopyEventAccumulator,
+    pub processed_copy_accumulator: CopyEventAccumulator,
+    pub raw_entry_accumulator: EntryAccumulator,
+    pub processed_entry_accumulator: EntryAccumulator,
+    pub block_accumulator: HashMap<u64, BlockInfo>,
+}
+
+impl Accumulators {
+    /// Number of slots held in each accumulator
+    pub fn sizes(&self) -> [(&'static str, usize); 11] {
+        [
+            ("raw_accounts", self.raw_slot_account_accumulator.len()),
+            ("processed_accounts", self.processed_slot_account_accumulator.len()),
+            ("raw_transactions", self.raw_transaction_accumulator.len()),
+            ("processed_transactions", self.processed_transaction_accumulator.len()),
+            ("raw_votes", self.raw_vote_accumulator.len()),
+            ("processed_votes", self.processed_vote_accumulator.len()),
+            ("raw_copies", self.raw_copy_accumulator.len()),
+            ("processed_copies", self.processed_copy_accumulator.len()),
+            ("raw_entries", self.raw_entry_accumulator.len()),
+            ("processed_entries", self.processed_entry_accumulator.len()),
+            ("blocks", self.block_accumulator.len()),
+        ]
+    }
+
+    /// Every slot with state in at least one accumulator, in ascending order
+    pub fn slots(&self) -> Vec<u64> {
+        let mut slots: Vec<u64> = self
+            .raw_slot_account_accumulator
+            .keys()
+            .chain(self.processed_slot_account_accumulator.keys())
+            .chain(self.raw_transaction_accumulator.keys())
+            .chain(self.processed_transaction_accumulator.keys())
+            .chain(self.raw_vote_accumulator.keys())
+            .chain(self.processed_vote_accumulator.keys())
+            .chain(self.raw_copy_accumulator.keys())
+            .chain(self.processed_copy_accumulator.keys())
+            .chain(self.raw_entry_accumulator.keys())
+            .chain(self.processed_entry_accumulator.keys())
+            .chain(self.block_accumulator.keys())
+            .copied()
+            .collect();
+        slots.sort_unstable();
+        slots.dedup();
+        slots
+    }
+
+    /// Drop everything held for `slot`. Returns whether anything was removed.
+    pub fn evict(&mut self, slot: u64) -> bool {
+        let mut removed = self.raw_slot_account_accumulator.remove(&slot).is_some();
+        removed |= self.processed_slot_account_accumulator.remove(&slot).is_some();
+        removed |= self.raw_transaction_accumulator.remove(&slot).is_some();
+        removed |= self.processed_transaction_accumulator.remove(&slot).is_some();
+        removed |= self.raw_vote_accumulator.remove(&slot).is_some();
+        removed |= self.processed_vote_accumulator.remove(&slot).is_some();
+        removed |= self.raw_copy_accumulator.remove(&slot).is_some();
+        removed |= self.processed_copy_accumulator.remove(&slot).is_some();
+        removed |= self.raw_entry_accumulator.remove(&slot).is_some();
+        removed |= self.processed_entry_accumulator.remove(&slot).is_some();
+        removed |= self.block_accumulator.remove(&slot).is_some();
+        removed
+    }
+}
+
+/// Default for `max_watched_accounts`
+pub const DEFAULT_MAX_WATCHED_ACCOUNTS: usize = 1024;
+
+/// Accounts for which proofs are generated, and the vote accounts whose votes are tracked.
+/// Initialised from `account_list`, `vote_accounts` and `copy_programs`. The first two are
+/// editable through the admin socket.
+#[derive(Debug, Clone)]
+pub struct MonitoredAccounts {
+    pub proof_accounts: Vec<Pubkey>,
+    pub vote_accounts: Vec<Pubkey>,
+    /// Every modified account owned by one of these programs is proven
+    pub copy_programs: Vec<Pubkey>,
+    /// Accounts registered by stream subscribers, with the number of subscriptions watching each
+    pub watched_accounts: HashMap<Pubkey, usize>,
+    /// Registrations that would take `watched_accounts` past this many accounts are refused
+    pub max_watched_accounts: usize,
+}
+
+impl Default for MonitoredAccounts {
+    fn default() -> Self {
+        MonitoredAccounts {
+            proof_accounts: vec![],
+            vote_accounts: vec![],
+            copy_programs: vec![],
+            watched_accounts: HashMap::new(),
+            max_watched_accounts: DEFAULT_MAX_WATCHED_ACCOUNTS,
+        }
+    }
+}
+
+impl MonitoredAccounts {
+    /// `proof_accounts` followed by the watched accounts missing from it, in pubkey order
+    pub fn proven_accounts(&self) -> Vec<Pubkey> {
+        let mut watched: Vec<Pubkey> = self
+            .watched_accounts
+            .keys()
+            .filter(|pubkey| !self.proof_accounts.contains(pubkey))
+            .copied()
+            .collect();
+        watched.sort_unstable();
+        self.proof_accounts.iter().copied().chain(watched).collect()
+    }
 }
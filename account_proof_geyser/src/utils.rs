@@ -5,9 +5,15 @@ use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use rayon::prelude::*;
 use solana_runtime::accounts_hash::{AccountsHasher, MERKLE_FANOUT};
 use solana_sdk::hash::{Hash, Hasher, hashv};
+use solana_sdk::message::legacy::Message;
 use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::vote::instruction::VoteInstruction;
 
-use crate::types::{AccountDeltaProof, AccountHashMap, Data, Proof};
+use crate::types::{
+    AccountDeltaNonInclusionProof, AccountDeltaProof, AccountHashMap, Data, Proof, VerifiedVote,
+    VoteProof,
+};
 
 
 
@@ -239,6 +245,29 @@ pub fn is_first(proof: &Proof) -> bool {
     proof.path.iter().all(|&position| position == 0)
 }
 
+/// The leaf is the last one of every chunk on its path, i.e. the rightmost leaf of the tree
+pub fn is_last(proof: &Proof) -> bool {
+    proof.path.len() == proof.siblings.len()
+        && proof
+            .path
+            .iter()
+            .zip(&proof.siblings)
+            .all(|(&position, siblings)| position == siblings.len())
+}
+
+/// Position of the leaf among all the leaves of the tree, recovered from its path
+pub fn leaf_index(proof: &Proof) -> Option<u64> {
+    proof
+        .path
+        .iter()
+        .rev()
+        .try_fold(0u64, |index, &position| {
+            index
+                .checked_mul(MERKLE_FANOUT as u64)?
+                .checked_add(position as u64)
+        })
+}
+
 pub fn get_proof_pubkeys_required(
     pubkey_hash_vec: &mut [(Pubkey, Hash)],
     leaves_for_proof: &[Pubkey],
@@ -330,6 +359,48 @@ pub fn assemble_account_delta_inclusion_proof(
     Ok(proofs)
 }
 
+/// Pubkeys of the leaves on either side of `pubkey`, which must not be one of the leaves.
+/// `pubkey_hash_vec` must be sorted by pubkey.
+pub fn get_non_inclusion_neighbours(
+    pubkey_hash_vec: &[(Pubkey, Hash)],
+    pubkey: &Pubkey,
+) -> (Option<Pubkey>, Option<Pubkey>) {
+    let position = match pubkey_hash_vec.binary_search_by(|(k, _)| k.cmp(pubkey)) {
+        Ok(pos) => pos,
+        Err(pos) => pos,
+    };
+    let left = position.checked_sub(1).map(|pos| pubkey_hash_vec[pos].0);
+    let right = pubkey_hash_vec.get(position).map(|(k, _)| *k);
+    (left, right)
+}
+
+pub fn assemble_account_delta_non_inclusion_proof(
+    account_data_hashes: &AccountHashMap,
+    account_proofs: &[(Pubkey, Proof)],
+    non_inclusion: &[(Pubkey, Option<Pubkey>, Option<Pubkey>)],
+) -> anyhow::Result<Vec<AccountDeltaNonInclusionProof>> {
+    let mut proofs = vec![];
+    for (pubkey, left, right) in non_inclusion {
+        let neighbour_proof = |neighbour: &Option<Pubkey>| -> anyhow::Result<Option<AccountDeltaProof>> {
+            match neighbour {
+                Some(neighbour) => Ok(assemble_account_delta_inclusion_proof(
+                    account_data_hashes,
+                    account_proofs,
+                    &[*neighbour],
+                )?.pop()),
+                None => Ok(None),
+            }
+        };
+        proofs.push(AccountDeltaNonInclusionProof {
+            pubkey: *pubkey,
+            left: neighbour_proof(left)?,
+            right: neighbour_proof(right)?,
+        });
+    }
+
+    Ok(proofs)
+}
+
 pub fn verify_leaves_against_bankhash(account_proof: &AccountDeltaProof,
                                       bankhash: Hash,
                                       num_sigs: u64,
@@ -366,6 +437,133 @@ pub fn verify_leaves_against_bankhash(account_proof: &AccountDeltaProof,
     Ok(())
 }
 
+/// Check that `non_inclusion_proof.pubkey` was not modified in the slot with `bankhash`: both
+/// neighbours must be proven leaves on either side of the pubkey and next to each other in the
+/// tree, or the single neighbour must be the first or last leaf.
+/// Deleted accounts hash to a constant that doesn't commit to their pubkey, so they can't be
+/// used as neighbours.
+pub fn verify_non_inclusion_against_bankhash(non_inclusion_proof: &AccountDeltaNonInclusionProof,
+                                             bankhash: Hash,
+                                             num_sigs: u64,
+                                             account_delta_root: Hash,
+                                             parent_bankhash: Hash,
+                                             blockhash: Hash) -> anyhow::Result<()> {
+    let pubkey = non_inclusion_proof.pubkey;
+    for neighbour in [&non_inclusion_proof.left, &non_inclusion_proof.right].into_iter().flatten() {
+        if neighbour.1.0.account.lamports == 0 {
+            anyhow::bail!("neighbour {} is a deleted account and doesn't commit to its pubkey", neighbour.0);
+        }
+        verify_leaves_against_bankhash(neighbour,
+                                       bankhash,
+                                       num_sigs,
+                                       account_delta_root,
+                                       parent_bankhash,
+                                       blockhash)?;
+    }
+
+    match (&non_inclusion_proof.left, &non_inclusion_proof.right) {
+        (Some(left), Some(right)) => {
+            if !(left.0 < pubkey && pubkey < right.0) {
+                anyhow::bail!("pubkey is not between its neighbours");
+            }
+            let left_proof = &left.1.1;
+            let right_proof = &right.1.1;
+            if left_proof.path.len() != right_proof.path.len() {
+                anyhow::bail!("neighbours are at different depths");
+            }
+            match (leaf_index(left_proof), leaf_index(right_proof)) {
+                (Some(l), Some(r)) if l.checked_add(1) == Some(r) => {}
+                _ => anyhow::bail!("neighbours are not adjacent leaves"),
+            }
+        }
+        (None, Some(right)) => {
+            if pubkey >= right.0 {
+                anyhow::bail!("pubkey is not below the first leaf");
+            }
+            if !is_first(&right.1.1) {
+                anyhow::bail!("right neighbour is not the first leaf");
+            }
+        }
+        (Some(left), None) => {
+            if pubkey <= left.0 {
+                anyhow::bail!("pubkey is not above the last leaf");
+            }
+            if !is_last(&left.1.1) {
+                anyhow::bail!("left neighbour is not the last leaf");
+            }
+        }
+        (None, None) => anyhow::bail!("non-inclusion proof has no neighbours"),
+    }
+    Ok(())
+}
+
+/// Check the signature on a vote transaction and decode the vote account, the authorized voter
+/// that signed it and the slot and bank hash it votes for
+pub fn verify_vote(vote: &VoteProof) -> anyhow::Result<VerifiedVote> {
+    let message: Message = bincode::deserialize(&vote.message)?;
+    let signature = Signature::try_from(vote.signature.as_slice())?;
+
+    let Some(instruction) = message.instructions.first() else {
+        anyhow::bail!("vote message has no instructions");
+    };
+    if message.program_id(0) != Some(&solana_sdk::vote::program::id()) {
+        anyhow::bail!("first instruction is not a vote instruction");
+    }
+    let account = |position: usize| {
+        instruction
+            .accounts
+            .get(position)
+            .map(|index| *index as usize)
+            .filter(|index| *index < message.account_keys.len())
+    };
+
+    // the authorized voter is the last account of every instruction that votes on a slot
+    let (slot, bank_hash, authority_position) =
+        match bincode::deserialize::<VoteInstruction>(&instruction.data)? {
+            VoteInstruction::Vote(vote) | VoteInstruction::VoteSwitch(vote, _) => {
+                let Some(slot) = vote.slots.last() else {
+                    anyhow::bail!("vote has no slots");
+                };
+                (*slot, vote.hash, 3)
+            }
+            VoteInstruction::UpdateVoteState(update)
+            | VoteInstruction::UpdateVoteStateSwitch(update, _)
+            | VoteInstruction::CompactUpdateVoteState(update)
+            | VoteInstruction::CompactUpdateVoteStateSwitch(update, _) => {
+                let Some(lockout) = update.lockouts.back() else {
+                    anyhow::bail!("vote state update has no lockouts");
+                };
+                (lockout.slot(), update.hash, 1)
+            }
+            _ => anyhow::bail!("instruction does not vote on a slot"),
+        };
+
+    let Some(vote_account) = account(0) else {
+        anyhow::bail!("vote instruction has no vote account");
+    };
+    let Some(authority) = account(authority_position) else {
+        anyhow::bail!("vote instruction has no authorized voter");
+    };
+    if !message.is_signer(authority) {
+        anyhow::bail!("authorized voter did not sign the vote transaction");
+    }
+    // the proof only carries the first signature of the transaction
+    if authority != 0 {
+        anyhow::bail!("vote transaction was paid for by another account than the authorized voter");
+    }
+    let authority = message.account_keys[authority];
+    if !signature.verify(authority.as_ref(), &message.serialize()) {
+        anyhow::bail!("vote signature verification failure");
+    }
+
+    Ok(VerifiedVote {
+        authority,
+        vote_account: message.account_keys[vote_account],
+        slot,
+        bank_hash,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use std::convert::TryFrom;
@@ -525,4 +723,139 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_verify_vote() {
+        use solana_sdk::signature::{Keypair, Signer};
+        use solana_sdk::vote::instruction::compact_update_vote_state;
+        use solana_sdk::vote::state::VoteStateUpdate;
+
+        let identity = Keypair::new();
+        let vote_account = Pubkey::new_unique();
+        let bank_hash = generate_random_hash();
+        let mut update = VoteStateUpdate::from(vec![(40, 2), (42, 1)]);
+        update.hash = bank_hash;
+        let instruction = compact_update_vote_state(&vote_account, &identity.pubkey(), update);
+        let message = Message::new(&[instruction], Some(&identity.pubkey()));
+        let signature = identity.sign_message(&message.serialize());
+
+        let mut vote = VoteProof {
+            signature: signature.as_ref().to_vec(),
+            message: message.serialize(),
+        };
+        let verified = verify_vote(&vote).unwrap();
+        assert_eq!(verified.authority, identity.pubkey());
+        assert_eq!(verified.vote_account, vote_account);
+        assert_eq!(verified.slot, 42);
+        assert_eq!(verified.bank_hash, bank_hash);
+
+        vote.signature = Keypair::new().sign_message(&vote.message).as_ref().to_vec();
+        assert!(verify_vote(&vote).is_err());
+
+        // a vote paid for by another account can't be attributed to the fee payer
+        let payer = Keypair::new();
+        let mut update = VoteStateUpdate::from(vec![(42, 1)]);
+        update.hash = bank_hash;
+        let instruction = compact_update_vote_state(&vote_account, &identity.pubkey(), update);
+        let message = Message::new(&[instruction], Some(&payer.pubkey()));
+        let vote = VoteProof {
+            signature: payer.sign_message(&message.serialize()).as_ref().to_vec(),
+            message: message.serialize(),
+        };
+        assert!(verify_vote(&vote).is_err());
+    }
+
+    #[test]
+    fn test_non_inclusion_proof() {
+        use crate::types::AccountInfo;
+
+        // leaves at odd first bytes, so that even ones fall between two known neighbours
+        let leaf_pubkey = |index: u8| Pubkey::new_from_array([2 * index + 1; 32]);
+        let mut account_data_hashes = AccountHashMap::new();
+        for lamports in 1..=40u64 {
+            let account = AccountInfo {
+                pubkey: leaf_pubkey(lamports as u8 - 1),
+                lamports,
+                ..AccountInfo::default()
+            };
+            let hash = Hash::from(hash_solana_account(
+                account.lamports,
+                account.owner.as_ref(),
+                account.executable,
+                account.rent_epoch,
+                &account.data,
+                account.pubkey.as_ref(),
+            ));
+            account_data_hashes.insert(account.pubkey, (1, hash, account));
+        }
+        let mut pubkey_hash_vec: Vec<(Pubkey, Hash)> = account_data_hashes
+            .iter()
+            .map(|(k, (_, h, _))| (*k, *h))
+            .collect();
+        pubkey_hash_vec.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+        // one missing pubkey below every leaf, one above every leaf and one between two leaves
+        // that sit in different chunks
+        let below = Pubkey::new_from_array([0; 32]);
+        let above = Pubkey::new_from_array([255; 32]);
+        let between = Pubkey::new_from_array([32; 32]);
+        assert_eq!((pubkey_hash_vec[15].0, pubkey_hash_vec[16].0), (leaf_pubkey(15), leaf_pubkey(16)));
+        assert!(pubkey_hash_vec[15].0 < between && between < pubkey_hash_vec[16].0);
+
+        let non_inclusion: Vec<_> = [below, above, between]
+            .iter()
+            .map(|pubkey| {
+                let (left, right) = get_non_inclusion_neighbours(&pubkey_hash_vec, pubkey);
+                (*pubkey, left, right)
+            })
+            .collect();
+        let neighbours: Vec<Pubkey> = non_inclusion
+            .iter()
+            .flat_map(|(_, left, right)| [*left, *right])
+            .flatten()
+            .collect();
+        let (root, account_proofs) = calculate_root_and_proofs(&mut pubkey_hash_vec, &neighbours);
+        let proofs = assemble_account_delta_non_inclusion_proof(
+            &account_data_hashes,
+            &account_proofs,
+            &non_inclusion,
+        )
+        .unwrap();
+
+        let parent_bankhash = generate_random_hash();
+        let blockhash = generate_random_hash();
+        let bankhash = hashv(&[
+            parent_bankhash.as_ref(),
+            root.as_ref(),
+            &0u64.to_le_bytes(),
+            blockhash.as_ref(),
+        ]);
+        let verify = |proof: &AccountDeltaNonInclusionProof| {
+            verify_non_inclusion_against_bankhash(proof, bankhash, 0, root, parent_bankhash, blockhash)
+        };
+
+        assert!(proofs[0].left.is_none() && proofs[1].right.is_none());
+        for proof in &proofs {
+            verify(proof).unwrap();
+        }
+
+        // a pubkey that is in the tree can't be proven absent with neighbours that aren't adjacent
+        let mut skipping = proofs[2].clone();
+        skipping.pubkey = proofs[2].right.as_ref().unwrap().0;
+        skipping.right = proofs[1].left.clone();
+        assert!(verify(&skipping).is_err());
+
+        // the first and last leaves can't stand in for inner neighbours
+        let mut not_first = proofs[2].clone();
+        not_first.left = None;
+        assert!(verify(&not_first).is_err());
+        let mut not_last = proofs[2].clone();
+        not_last.right = None;
+        assert!(verify(&not_last).is_err());
+
+        // tampering with a neighbour breaks the proof
+        let mut tampered = proofs[2].clone();
+        tampered.left.as_mut().unwrap().1 .0.account.lamports += 1;
+        assert!(verify(&tampered).is_err());
+    }
 }
@@ -0,0 +1,97 @@
+use std::io;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_sdk::pubkey::Pubkey;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Upper bound on a single frame. Updates carry full account data, so this is generous,
+/// but it stops a corrupt length prefix from allocating unbounded memory.
+pub const MAX_FRAME_LEN: usize = 64 * 1024 * 1024;
+
+/// Handshake sent by a client right after connecting to the proof server
+#[derive(Clone, Debug, Default, BorshSerialize, BorshDeserialize)]
+pub struct StreamRequest {
+    /// Replay every buffered update for a slot after this one before switching to live updates.
+    /// `None` only streams live updates.
+    pub resume_from_slot: Option<u64>,
+    /// Bearer token, required when the server is configured with API keys
+    pub auth_token: Option<String>,
+    /// Accounts to prove on top of the plugin's `account_list` for as long as the connection is open
+    pub watch_accounts: Vec<Pubkey>,
+}
+
+/// Server's answer to a `StreamRequest`. Updates only follow an `Accepted` response.
+#[derive(Clone, Debug, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub enum StreamResponse {
+    Accepted,
+    Rejected(String),
+}
+
+/// Serialize a message as a frame: a little endian u32 length followed by the borsh encoding
+pub fn encode_frame<T: BorshSerialize>(message: &T) -> io::Result<Vec<u8>> {
+    let payload = message.try_to_vec()?;
+    if payload.len() > MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("frame of {} bytes exceeds the maximum frame length", payload.len()),
+        ));
+    }
+    let mut frame = Vec::with_capacity(4 + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&payload);
+    Ok(frame)
+}
+
+/// Split the first complete frame off the front of `buf`.
+/// Returns `None` if `buf` doesn't hold a complete frame yet.
+pub fn decode_frame<T: BorshDeserialize>(buf: &[u8]) -> io::Result<Option<(T, usize)>> {
+    if buf.len() < 4 {
+        return Ok(None);
+    }
+    let len = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame length {} exceeds the maximum frame length", len),
+        ));
+    }
+    if buf.len() < 4 + len {
+        return Ok(None);
+    }
+    let message = T::try_from_slice(&buf[4..4 + len])?;
+    Ok(Some((message, 4 + len)))
+}
+
+pub async fn write_frame<W, T>(writer: &mut W, message: &T) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+    T: BorshSerialize,
+{
+    let frame = encode_frame(message)?;
+    writer.write_all(&frame).await?;
+    writer.flush().await
+}
+
+/// Read the next frame from `reader`. Returns `None` if the stream was closed cleanly between frames.
+pub async fn read_frame<R, T>(reader: &mut R) -> io::Result<Option<T>>
+where
+    R: AsyncRead + Unpin,
+    T: BorshDeserialize,
+{
+    let mut len_buf = [0u8; 4];
+    match reader.read_exact(&mut len_buf).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_le_bytes(len_buf) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame length {} exceeds the maximum frame length", len),
+        ));
+    }
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+    T::try_from_slice(&payload).map(Some)
+}
@@ -0,0 +1,358 @@
+use std::fmt;
+
+use account_proof_geyser::types::{AccountDeltaProof, BankHashComponents, Update};
+use account_proof_geyser::utils::verify_leaves_against_bankhash;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_sdk::hash::Hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::slot_hashes::SlotHashes;
+use solana_sdk::sysvar;
+
+/// Ties the bank hash of an older slot to a later slot through the SlotHashes sysvar as of the
+/// later slot. SlotHashes only holds the last 512 slots, so a link can't reach further back.
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct SlotHashesLink {
+    pub slot: u64,
+    pub bank_hash: BankHashComponents,
+    /// Inclusion of the SlotHashes sysvar in the accounts delta of `slot`. The sysvar is
+    /// rewritten every slot, so it is always there.
+    pub slot_hashes_proof: AccountDeltaProof,
+}
+
+impl SlotHashesLink {
+    /// Take the SlotHashes proof out of an update
+    pub fn from_update(update: &Update) -> Option<Self> {
+        let slot_hashes_proof = update
+            .proof
+            .proofs
+            .iter()
+            .find(|proof| proof.0 == sysvar::slot_hashes::id())?;
+        Some(SlotHashesLink {
+            slot: update.slot,
+            bank_hash: BankHashComponents::from(&update.proof),
+            slot_hashes_proof: slot_hashes_proof.clone(),
+        })
+    }
+
+    /// The SlotHashes recorded as of `slot`, once its proof has been checked against the bank hash
+    fn slot_hashes(&self) -> Result<SlotHashes, AncestryError> {
+        let pubkey = self.slot_hashes_proof.0;
+        if pubkey != sysvar::slot_hashes::id() {
+            return Err(AncestryError::NotSlotHashes {
+                slot: self.slot,
+                pubkey,
+            });
+        }
+        verify_leaves_against_bankhash(
+            &self.slot_hashes_proof,
+            self.bank_hash.bank_hash(),
+            self.bank_hash.num_sigs,
+            self.bank_hash.accounts_delta_hash,
+            self.bank_hash.parent_bankhash,
+            self.bank_hash.current_blockhash,
+        )
+        .map_err(|e| AncestryError::InvalidSlotHashesProof {
+            slot: self.slot,
+            reason: e.to_string(),
+        })?;
+        bincode::deserialize(&self.slot_hashes_proof.1 .0.account.data)
+            .map_err(|_| AncestryError::InvalidSlotHashes { slot: self.slot })
+    }
+}
+
+/// One step from a slot to a later one that commits to its bank hash
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub enum AncestryLink {
+    /// The next slot of the chain, whose parent bank hash is the current bank hash
+    Parent {
+        slot: u64,
+        bank_hash: BankHashComponents,
+    },
+    /// A later slot whose SlotHashes records the current bank hash
+    SlotHashes(Box<SlotHashesLink>),
+}
+
+impl AncestryLink {
+    pub fn slot(&self) -> u64 {
+        match self {
+            AncestryLink::Parent { slot, .. } => *slot,
+            AncestryLink::SlotHashes(link) => link.slot,
+        }
+    }
+}
+
+/// Connects the bank hash of a slot to the bank hash of a descendant, so that trust in the
+/// descendant (e.g. votes on it) covers the ancestor. Parent links can reach any distance,
+/// SlotHashes links skip up to 512 slots at once.
+#[derive(Debug, Clone, Default, BorshSerialize, BorshDeserialize)]
+pub struct AncestryProof {
+    pub links: Vec<AncestryLink>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AncestryError {
+    /// The proof has no links
+    Empty,
+    /// Each link must move to a later slot
+    NotDescending { slot: u64, next_slot: u64 },
+    /// The parent bank hash of `slot` isn't the bank hash of the slot before it in the proof
+    ParentMismatch {
+        slot: u64,
+        parent_bankhash: Hash,
+        expected: Hash,
+    },
+    /// The link doesn't prove the SlotHashes sysvar
+    NotSlotHashes { slot: u64, pubkey: Pubkey },
+    /// The SlotHashes proof doesn't open the bank hash of `slot`
+    InvalidSlotHashesProof { slot: u64, reason: String },
+    /// The SlotHashes data of `slot` couldn't be decoded
+    InvalidSlotHashes { slot: u64 },
+    /// The SlotHashes of `link_slot` has no entry for `slot`, or a different bank hash
+    SlotHashesMismatch {
+        slot: u64,
+        link_slot: u64,
+        bank_hash: Hash,
+        found: Option<Hash>,
+    },
+}
+
+impl fmt::Display for AncestryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AncestryError::Empty => write!(f, "ancestry proof has no links"),
+            AncestryError::NotDescending { slot, next_slot } => {
+                write!(f, "slot {} can't link to earlier slot {}", slot, next_slot)
+            }
+            AncestryError::ParentMismatch {
+                slot,
+                parent_bankhash,
+                expected,
+            } => write!(
+                f,
+                "slot {} has parent bank hash {} instead of {}",
+                slot, parent_bankhash, expected
+            ),
+            AncestryError::NotSlotHashes { slot, pubkey } => write!(
+                f,
+                "link to slot {} proves {} instead of the SlotHashes sysvar",
+                slot, pubkey
+            ),
+            AncestryError::InvalidSlotHashesProof { slot, reason } => {
+                write!(f, "SlotHashes proof of slot {} failed: {}", slot, reason)
+            }
+            AncestryError::InvalidSlotHashes { slot } => {
+                write!(f, "SlotHashes of slot {} could not be decoded", slot)
+            }
+            AncestryError::SlotHashesMismatch {
+                slot,
+                link_slot,
+                bank_hash,
+                found,
+            } => match found {
+                Some(found) => write!(
+                    f,
+                    "SlotHashes of slot {} has bank hash {} for slot {} instead of {}",
+                    link_slot, found, slot, bank_hash
+                ),
+                None => write!(
+                    f,
+                    "SlotHashes of slot {} has no entry for slot {}",
+                    link_slot, slot
+                ),
+            },
+        }
+    }
+}
+
+impl std::error::Error for AncestryError {}
+
+/// Follow `proof` from `bank_hash` at `slot` and return the descendant slot and bank hash it ends
+/// at. Trusting the returned bank hash means trusting `bank_hash`.
+pub fn verify_ancestry(
+    slot: u64,
+    bank_hash: Hash,
+    proof: &AncestryProof,
+) -> Result<(u64, Hash), AncestryError> {
+    if proof.links.is_empty() {
+        return Err(AncestryError::Empty);
+    }
+    let (mut slot, mut bank_hash) = (slot, bank_hash);
+    for link in &proof.links {
+        if link.slot() <= slot {
+            return Err(AncestryError::NotDescending {
+                slot,
+                next_slot: link.slot(),
+            });
+        }
+        let next_bank_hash = match link {
+            AncestryLink::Parent {
+                slot: next_slot,
+                bank_hash: components,
+            } => {
+                if components.parent_bankhash != bank_hash {
+                    return Err(AncestryError::ParentMismatch {
+                        slot: *next_slot,
+                        parent_bankhash: components.parent_bankhash,
+                        expected: bank_hash,
+                    });
+                }
+                components.bank_hash()
+            }
+            AncestryLink::SlotHashes(link) => {
+                let found = link.slot_hashes()?.get(&slot).copied();
+                if found != Some(bank_hash) {
+                    return Err(AncestryError::SlotHashesMismatch {
+                        slot,
+                        link_slot: link.slot,
+                        bank_hash,
+                        found,
+                    });
+                }
+                link.bank_hash.bank_hash()
+            }
+        };
+        slot = link.slot();
+        bank_hash = next_bank_hash;
+    }
+    Ok((slot, bank_hash))
+}
+
+/// Build a proof from `bank_hash` at `slot` to the update for `descendant_slot` out of confirmed
+/// updates. Each step jumps to the furthest update whose SlotHashes records the current bank hash,
+/// or else to the update that builds directly on it.
+pub fn generate_ancestry_proof(
+    slot: u64,
+    bank_hash: Hash,
+    updates: &[Update],
+    descendant_slot: u64,
+) -> Option<AncestryProof> {
+    let mut candidates: Vec<&Update> = updates
+        .iter()
+        .filter(|update| update.slot > slot && update.slot <= descendant_slot)
+        .collect();
+    candidates.sort_by_key(|update| update.slot);
+
+    let mut links = vec![];
+    let (mut slot, mut bank_hash) = (slot, bank_hash);
+    while slot != descendant_slot {
+        let slot_hashes_link = candidates
+            .iter()
+            .rev()
+            .filter(|update| update.slot > slot)
+            .filter_map(|update| SlotHashesLink::from_update(update))
+            .find(|link| {
+                let slot_hashes =
+                    bincode::deserialize::<SlotHashes>(&link.slot_hashes_proof.1 .0.account.data);
+                matches!(slot_hashes, Ok(slot_hashes) if slot_hashes.get(&slot) == Some(&bank_hash))
+            });
+        let link = match slot_hashes_link {
+            Some(link) => AncestryLink::SlotHashes(Box::new(link)),
+            None => {
+                let child = candidates.iter().find(|update| {
+                    update.slot > slot && update.proof.parent_bankhash == bank_hash
+                })?;
+                AncestryLink::Parent {
+                    slot: child.slot,
+                    bank_hash: BankHashComponents::from(&child.proof),
+                }
+            }
+        };
+        slot = link.slot();
+        bank_hash = match &link {
+            AncestryLink::Parent { bank_hash, .. } => bank_hash.bank_hash(),
+            AncestryLink::SlotHashes(link) => link.bank_hash.bank_hash(),
+        };
+        links.push(link);
+    }
+    Some(AncestryProof { links })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use account_proof_geyser::types::AccountInfo;
+    use solana_sdk::slot_hashes::SlotHash;
+
+    use super::*;
+    use crate::verify::tests::account_update;
+
+    /// An update for `slot` proving a SlotHashes sysvar holding `slot_hashes`
+    pub(crate) fn slot_hashes_update(slot: u64, slot_hashes: &[SlotHash]) -> Update {
+        account_update(AccountInfo {
+            pubkey: sysvar::slot_hashes::id(),
+            lamports: 1,
+            owner: sysvar::id(),
+            data: bincode::serialize(&SlotHashes::new(slot_hashes)).unwrap(),
+            slot,
+            ..AccountInfo::default()
+        })
+    }
+
+    /// An update for `slot` building on `parent`, with a SlotHashes that doesn't record it
+    fn child_update(slot: u64, parent: &Update) -> Update {
+        let mut update = slot_hashes_update(slot, &[]);
+        update.proof.parent_bankhash = parent.root;
+        update.root = BankHashComponents::from(&update.proof).bank_hash();
+        update
+    }
+
+    #[test]
+    fn test_chain_of_slot_hashes_and_parents() {
+        let ancestor = slot_hashes_update(10, &[]);
+        // 10 -> 600 and 601 -> 1100 through SlotHashes, 600 -> 601 through the parent bank hash,
+        // reaching back further than SlotHashes alone
+        let jump = slot_hashes_update(600, &[(10, ancestor.root)]);
+        let parent = child_update(601, &jump);
+        let descendant = slot_hashes_update(1100, &[(601, parent.root)]);
+        let updates = vec![descendant.clone(), parent.clone(), jump.clone()];
+
+        let proof = generate_ancestry_proof(10, ancestor.root, &updates, 1100).unwrap();
+        let slots: Vec<u64> = proof.links.iter().map(AncestryLink::slot).collect();
+        assert_eq!(slots, vec![600, 601, 1100]);
+        assert_eq!(
+            verify_ancestry(10, ancestor.root, &proof),
+            Ok((1100, descendant.root))
+        );
+        assert!(matches!(
+            verify_ancestry(10, Hash::new_unique(), &proof),
+            Err(AncestryError::SlotHashesMismatch { slot: 10, .. })
+        ));
+
+        assert!(matches!(
+            proof.links[1],
+            AncestryLink::Parent { slot: 601, .. }
+        ));
+        let mut tampered = proof.clone();
+        if let AncestryLink::Parent { bank_hash, .. } = &mut tampered.links[1] {
+            bank_hash.parent_bankhash = Hash::new_unique();
+        }
+        assert!(matches!(
+            verify_ancestry(10, ancestor.root, &tampered),
+            Err(AncestryError::ParentMismatch { slot: 601, .. })
+        ));
+
+        assert!(generate_ancestry_proof(10, ancestor.root, &updates[1..], 1100).is_none());
+    }
+
+    #[test]
+    fn test_tampered_slot_hashes_are_rejected() {
+        let ancestor = slot_hashes_update(10, &[]);
+        let later = slot_hashes_update(12, &[(10, ancestor.root)]);
+        let mut proof = generate_ancestry_proof(10, ancestor.root, &[later], 12).unwrap();
+        let AncestryLink::SlotHashes(link) = &mut proof.links[0] else {
+            panic!("expected a SlotHashes link");
+        };
+        link.slot_hashes_proof.1 .0.account.data =
+            bincode::serialize(&SlotHashes::new(&[(10, Hash::new_unique())])).unwrap();
+        assert!(matches!(
+            verify_ancestry(10, ancestor.root, &proof),
+            Err(AncestryError::InvalidSlotHashesProof { slot: 12, .. })
+        ));
+        assert_eq!(
+            verify_ancestry(12, ancestor.root, &proof),
+            Err(AncestryError::NotDescending {
+                slot: 12,
+                next_slot: 12
+            })
+        );
+    }
+}
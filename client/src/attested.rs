@@ -0,0 +1,341 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use account_proof_geyser::types::{
+    AccountDeltaProof, AccountInfo, BankHashComponents, Update, VoteProof,
+};
+use account_proof_geyser::utils::{hash_solana_account, verify_proof};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_sdk::hash::Hash;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::ancestry::{verify_ancestry, AncestryError, AncestryLink, AncestryProof, SlotHashesLink};
+use crate::verify::{trusted_voters, TrustedRoot};
+
+/// What an `AttestedAccountProof` must satisfy to be accepted
+#[derive(Debug, Clone)]
+pub struct TrustPolicy {
+    /// What the attested bank hash is checked against
+    pub root: TrustedRoot,
+    /// Furthest back, in slots, the attested slot may be from the slot of the account. `0` only
+    /// accepts proofs whose own bank hash is attested.
+    pub max_link_distance: u64,
+}
+
+impl TrustPolicy {
+    /// Accept ancestry proofs of any length
+    pub fn new(root: TrustedRoot) -> Self {
+        TrustPolicy {
+            root,
+            max_link_distance: u64::MAX,
+        }
+    }
+}
+
+/// Everything needed to check the state of an account at a slot against a `TrustPolicy`, without
+/// the `Update` it was taken from
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct AttestedAccountProof {
+    pub slot: u64,
+    /// Inclusion of the account, data included, in the accounts delta of `slot`
+    pub account_proof: AccountDeltaProof,
+    pub bank_hash: BankHashComponents,
+    /// Present when the votes are for a later slot than `slot`
+    pub ancestry: Option<AncestryProof>,
+    /// Votes for the attested slot: `slot`, or the slot `ancestry` ends at
+    pub votes: Vec<VoteProof>,
+}
+
+/// The state of an account, proven against a bank hash that satisfies a `TrustPolicy`
+#[derive(Debug, Clone)]
+pub struct VerifiedAccount {
+    pub slot: u64,
+    pub bank_hash: Hash,
+    pub account: AccountInfo,
+    /// The slot whose bank hash the trust policy vouched for. Later than `slot` when the proof
+    /// was linked through an ancestry proof.
+    pub attested_slot: u64,
+    /// Vote accounts of the trusted validators that voted for the attested bank hash
+    pub voters: HashSet<Pubkey>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerificationError {
+    /// The account in the proof is not the pubkey it is proven for
+    AccountPubkeyMismatch { pubkey: Pubkey },
+    /// The account data doesn't hash to the leaf
+    AccountHashMismatch { pubkey: Pubkey },
+    /// The leaf of `pubkey` is not in the accounts delta of `slot`
+    InvalidMerkleProof { pubkey: Pubkey, slot: u64 },
+    /// The attested slot is further from `slot` than the policy allows
+    InvalidLinkDistance { slot: u64, link_slot: u64 },
+    /// The ancestry proof doesn't connect `slot` to the attested slot
+    Ancestry(AncestryError),
+    /// The attested bank hash isn't the trusted one
+    UntrustedBankHash { slot: u64, bank_hash: Hash },
+    /// Too few trusted validators voted for the attested bank hash
+    InsufficientVotes {
+        slot: u64,
+        bank_hash: Hash,
+        votes: usize,
+        required: usize,
+    },
+}
+
+impl fmt::Display for VerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerificationError::AccountPubkeyMismatch { pubkey } => {
+                write!(f, "proof for {} carries another account", pubkey)
+            }
+            VerificationError::AccountHashMismatch { pubkey } => {
+                write!(f, "account data of {} does not match its hash", pubkey)
+            }
+            VerificationError::InvalidMerkleProof { pubkey, slot } => write!(
+                f,
+                "{} is not in the accounts delta of slot {}",
+                pubkey, slot
+            ),
+            VerificationError::InvalidLinkDistance { slot, link_slot } => write!(
+                f,
+                "slot {} can't be linked from slot {} under the trust policy",
+                slot, link_slot
+            ),
+            VerificationError::Ancestry(e) => write!(f, "{}", e),
+            VerificationError::UntrustedBankHash { slot, bank_hash } => write!(
+                f,
+                "bank hash {} of slot {} is not the trusted bank hash",
+                bank_hash, slot
+            ),
+            VerificationError::InsufficientVotes {
+                slot,
+                bank_hash,
+                votes,
+                required,
+            } => write!(
+                f,
+                "{} trusted validators voted for bank hash {} of slot {}, {} required",
+                votes, bank_hash, slot, required
+            ),
+        }
+    }
+}
+
+impl std::error::Error for VerificationError {}
+
+impl From<AncestryError> for VerificationError {
+    fn from(e: AncestryError) -> Self {
+        VerificationError::Ancestry(e)
+    }
+}
+
+/// Check that `account_proof` opens `accounts_delta_hash` to the account it carries
+fn verify_account_leaf(
+    account_proof: &AccountDeltaProof,
+    accounts_delta_hash: &Hash,
+    slot: u64,
+) -> Result<(), VerificationError> {
+    let pubkey = account_proof.0;
+    let (data, proof) = &account_proof.1;
+    if data.pubkey != pubkey || data.account.pubkey != pubkey {
+        return Err(VerificationError::AccountPubkeyMismatch { pubkey });
+    }
+    let account = &data.account;
+    let hash = hash_solana_account(
+        account.lamports,
+        account.owner.as_ref(),
+        account.executable,
+        account.rent_epoch,
+        &account.data,
+        pubkey.as_ref(),
+    );
+    if data.hash.as_ref() != hash {
+        return Err(VerificationError::AccountHashMismatch { pubkey });
+    }
+    if !verify_proof(&data.hash, proof, accounts_delta_hash) {
+        return Err(VerificationError::InvalidMerkleProof { pubkey, slot });
+    }
+    Ok(())
+}
+
+impl AttestedAccountProof {
+    /// Bundle the proof of `pubkey` in `update` with the votes it carries
+    pub fn from_update(update: &Update, pubkey: &Pubkey) -> Option<Self> {
+        let account_proof = update.proof.proofs.iter().find(|proof| &proof.0 == pubkey)?;
+        Some(AttestedAccountProof {
+            slot: update.slot,
+            account_proof: account_proof.clone(),
+            bank_hash: BankHashComponents::from(&update.proof),
+            ancestry: None,
+            votes: update.votes.clone(),
+        })
+    }
+
+    /// Attest the proof with the votes of a later update instead, linked through its SlotHashes
+    pub fn link_to(self, later: &Update) -> Option<Self> {
+        let ancestry = AncestryProof {
+            links: vec![AncestryLink::SlotHashes(Box::new(SlotHashesLink::from_update(
+                later,
+            )?))],
+        };
+        Some(self.with_ancestry(ancestry, later.votes.clone()))
+    }
+
+    /// Attest the proof with `votes` for the slot that `ancestry` leads to
+    pub fn with_ancestry(mut self, ancestry: AncestryProof, votes: Vec<VoteProof>) -> Self {
+        self.ancestry = Some(ancestry);
+        self.votes = votes;
+        self
+    }
+
+    /// Check the account against its bank hash, link the bank hash to the attested slot and check
+    /// the attested bank hash against `policy`
+    pub fn verify(&self, policy: &TrustPolicy) -> Result<VerifiedAccount, VerificationError> {
+        verify_account_leaf(
+            &self.account_proof,
+            &self.bank_hash.accounts_delta_hash,
+            self.slot,
+        )?;
+        let bank_hash = self.bank_hash.bank_hash();
+
+        let (attested_slot, attested_bank_hash) = match &self.ancestry {
+            Some(ancestry) => verify_ancestry(self.slot, bank_hash, ancestry)?,
+            None => (self.slot, bank_hash),
+        };
+        if attested_slot - self.slot > policy.max_link_distance {
+            return Err(VerificationError::InvalidLinkDistance {
+                slot: self.slot,
+                link_slot: attested_slot,
+            });
+        }
+
+        let voters = match &policy.root {
+            TrustedRoot::BankHash(trusted) => {
+                if *trusted != attested_bank_hash {
+                    return Err(VerificationError::UntrustedBankHash {
+                        slot: attested_slot,
+                        bank_hash: attested_bank_hash,
+                    });
+                }
+                HashSet::new()
+            }
+            TrustedRoot::Validators {
+                validators,
+                min_votes,
+            } => {
+                let voters =
+                    trusted_voters(&self.votes, attested_slot, attested_bank_hash, validators);
+                if voters.len() < *min_votes {
+                    return Err(VerificationError::InsufficientVotes {
+                        slot: attested_slot,
+                        bank_hash: attested_bank_hash,
+                        votes: voters.len(),
+                        required: *min_votes,
+                    });
+                }
+                voters
+            }
+        };
+
+        Ok(VerifiedAccount {
+            slot: self.slot,
+            bank_hash,
+            account: self.account_proof.1 .0.account.clone(),
+            attested_slot,
+            voters,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::signature::Keypair;
+
+    use super::*;
+    use crate::ancestry::tests::slot_hashes_update;
+    use crate::verify::tests::{account_update, validator_set, vote};
+
+    fn account_at(slot: u64) -> AccountInfo {
+        AccountInfo {
+            pubkey: Pubkey::new_unique(),
+            lamports: 5,
+            data: vec![9; 8],
+            slot,
+            ..AccountInfo::default()
+        }
+    }
+
+    #[test]
+    fn test_verify_direct_attestation() {
+        let account = account_at(7);
+        let pubkey = account.pubkey;
+        let mut update = account_update(account);
+        let validators: Vec<Keypair> = (0..3).map(|_| Keypair::new()).collect();
+        update.votes = validators
+            .iter()
+            .map(|validator| vote(validator, 7, update.root))
+            .collect();
+
+        let proof = AttestedAccountProof::from_update(&update, &pubkey).unwrap();
+        let verified = proof
+            .verify(&TrustPolicy::new(TrustedRoot::BankHash(update.root)))
+            .unwrap();
+        assert_eq!(verified.account.pubkey, pubkey);
+        assert_eq!(verified.attested_slot, 7);
+
+        let policy = TrustPolicy::new(TrustedRoot::supermajority_of(validator_set(&validators)));
+        assert_eq!(proof.verify(&policy).unwrap().voters.len(), 3);
+
+        let mut tampered = proof.clone();
+        tampered.account_proof.1 .0.account.lamports += 1;
+        assert_eq!(
+            tampered.verify(&policy).unwrap_err(),
+            VerificationError::AccountHashMismatch { pubkey }
+        );
+        let mut tampered = proof.clone();
+        tampered.bank_hash.num_sigs += 1;
+        assert!(matches!(
+            tampered.verify(&policy),
+            Err(VerificationError::InsufficientVotes { votes: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_verify_through_slot_hashes() {
+        let account = account_at(7);
+        let pubkey = account.pubkey;
+        let update = account_update(account);
+        let later = slot_hashes_update(9, &[(8, Hash::new_unique()), (7, update.root)]);
+        let policy = TrustPolicy::new(TrustedRoot::BankHash(later.root));
+
+        let proof = AttestedAccountProof::from_update(&update, &pubkey).unwrap();
+        assert!(matches!(
+            proof.verify(&policy),
+            Err(VerificationError::UntrustedBankHash { slot: 7, .. })
+        ));
+        let linked = proof.clone().link_to(&later).unwrap();
+        let verified = linked.verify(&policy).unwrap();
+        assert_eq!((verified.slot, verified.attested_slot), (7, 9));
+        assert_eq!(verified.bank_hash, update.root);
+
+        let direct_only = TrustPolicy {
+            max_link_distance: 0,
+            ..policy.clone()
+        };
+        assert!(matches!(
+            linked.verify(&direct_only),
+            Err(VerificationError::InvalidLinkDistance { .. })
+        ));
+
+        // SlotHashes records another bank hash for the slot
+        let forked = slot_hashes_update(9, &[(7, Hash::new_unique())]);
+        let policy = TrustPolicy::new(TrustedRoot::BankHash(forked.root));
+        assert!(matches!(
+            proof.link_to(&forked).unwrap().verify(&policy),
+            Err(VerificationError::Ancestry(AncestryError::SlotHashesMismatch {
+                found: Some(_),
+                ..
+            }))
+        ));
+    }
+}
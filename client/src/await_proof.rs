@@ -0,0 +1,167 @@
+use std::fmt;
+use std::time::Duration;
+
+use solana_sdk::pubkey::Pubkey;
+
+use crate::subscriber::{ProofSubscriber, SubscriberConfig, SubscriberError, VerifiedUpdate};
+
+#[derive(Debug)]
+pub enum AwaitError {
+    /// No confirmed slot modified the account in time
+    Timeout { pubkey: Pubkey, timeout: Duration },
+    /// The plugin refused the registration, the stream failed or a proof didn't verify
+    Subscriber(SubscriberError),
+    /// The proof stream ended before the account was modified
+    StreamEnded,
+}
+
+impl AwaitError {
+    /// Whether writing to the account ourselves, e.g. with a copy transaction, may still get a
+    /// proof for it
+    pub fn can_fall_back(&self) -> bool {
+        matches!(
+            self,
+            AwaitError::Timeout { .. } | AwaitError::Subscriber(SubscriberError::Rejected(_))
+        )
+    }
+}
+
+impl fmt::Display for AwaitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AwaitError::Timeout { pubkey, timeout } => write!(
+                f,
+                "{} was not modified in a confirmed slot within {:?}",
+                pubkey, timeout
+            ),
+            AwaitError::Subscriber(e) => write!(f, "{}", e),
+            AwaitError::StreamEnded => write!(f, "proof stream ended"),
+        }
+    }
+}
+
+impl std::error::Error for AwaitError {}
+
+impl From<SubscriberError> for AwaitError {
+    fn from(e: SubscriberError) -> Self {
+        AwaitError::Subscriber(e)
+    }
+}
+
+/// Register `pubkey` with the plugin and wait for the next confirmed update that modifies it.
+/// Updates proving it unmodified are skipped. The returned update has been verified against its
+/// bank hash, so its `account(pubkey)` is the proven state.
+pub async fn await_account_proof(
+    config: SubscriberConfig,
+    pubkey: Pubkey,
+    timeout: Duration,
+) -> Result<VerifiedUpdate, AwaitError> {
+    let mut subscriber = ProofSubscriber::new(SubscriberConfig {
+        pubkeys: [pubkey].into_iter().collect(),
+        register: true,
+        ..config
+    });
+    let modified = async {
+        while let Some(update) = subscriber.next_update().await {
+            let update = update?;
+            if update.account(&pubkey).is_some() {
+                return Ok(update);
+            }
+        }
+        Err(AwaitError::StreamEnded)
+    };
+    tokio::time::timeout(timeout, modified)
+        .await
+        .unwrap_or(Err(AwaitError::Timeout { pubkey, timeout }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use account_proof_geyser::admin::{execute_admin_request, AdminRequest};
+    use account_proof_geyser::auth::AccessControl;
+    use account_proof_geyser::config::ApiKeyConfig;
+    use account_proof_geyser::metrics::Metrics;
+    use account_proof_geyser::replay::ReplayBuffer;
+    use account_proof_geyser::server::{ProofServer, StreamContext};
+    use account_proof_geyser::types::{Accumulators, GeyserMessage, MonitoredAccounts};
+    use tokio::net::TcpListener;
+    use tokio::sync::broadcast;
+
+    use super::*;
+    use crate::subscriber::tests::signed_update;
+
+    #[tokio::test]
+    async fn test_await_registers_and_returns_modified_state() {
+        let awaited = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+
+        // stands in for the plugin's message loop, remembering what was registered
+        let (geyser_sender, geyser_receiver) = crossbeam_channel::unbounded();
+        let registered = Arc::new(Mutex::new(vec![]));
+        let seen = registered.clone();
+        std::thread::spawn(move || {
+            let mut accumulators = Accumulators::default();
+            let mut monitored = MonitoredAccounts::default();
+            while let Ok(GeyserMessage::AdminMessage(command)) = geyser_receiver.recv() {
+                if let AdminRequest::WatchAccounts { pubkeys } = &command.request {
+                    seen.lock().unwrap().extend(pubkeys.clone());
+                }
+                let response = execute_admin_request(command.request, &mut accumulators, &mut monitored);
+                let _ = command.reply.send(response);
+            }
+        });
+
+        let (tx, _rx) = broadcast::channel(16);
+        let server = ProofServer::new(
+            tx.clone(),
+            StreamContext {
+                replay_buffer: Arc::new(Mutex::new(ReplayBuffer::new(0))),
+                archive: None,
+                // the first connection is only noticed to be closed on the next write
+                access_control: AccessControl::new(
+                    &[ApiKeyConfig {
+                        name: "client".to_string(),
+                        key: "secret".to_string(),
+                        max_subscriptions: None,
+                    }],
+                    2,
+                ),
+                metrics: Arc::new(Metrics::new()),
+                geyser_sender: Some(geyser_sender),
+            },
+            None,
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = listener.local_addr().unwrap().to_string();
+        tokio::spawn(server.serve(listener));
+
+        let config = SubscriberConfig {
+            endpoint,
+            auth_token: Some("secret".to_string()),
+            ..SubscriberConfig::default()
+        };
+        let result = tokio::spawn(await_account_proof(
+            config.clone(),
+            awaited,
+            Duration::from_secs(10),
+        ));
+        // live updates only reach the subscriber once it has connected
+        let mut slot = 0;
+        while !result.is_finished() {
+            slot += 2;
+            let _ = tx.send(signed_update(slot - 1, &[other]));
+            let _ = tx.send(signed_update(slot, &[awaited, other]));
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let update = result.await.unwrap().unwrap();
+        assert_eq!(update.slot % 2, 0);
+        assert_eq!(update.account(&awaited).unwrap().lamports, 1);
+        assert_eq!(*registered.lock().unwrap(), vec![awaited.to_string()]);
+
+        let result = await_account_proof(config, awaited, Duration::from_millis(100)).await;
+        assert!(matches!(result, Err(AwaitError::Timeout { .. })));
+        assert!(result.unwrap_err().can_fall_back());
+    }
+}
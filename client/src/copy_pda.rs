@@ -0,0 +1,124 @@
+use std::fmt;
+use std::str::FromStr;
+
+use copy::{shard_index, CopyScope};
+use solana_sdk::pubkey::Pubkey;
+
+/// How a copy account is picked for each copy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyPdaMode {
+    /// Every copy goes to the single global PDA
+    Global,
+    /// Every signer copies to its own PDA
+    PerSigner,
+    /// Copies are spread over this many shared PDAs by source account
+    Sharded(u8),
+}
+
+impl CopyPdaMode {
+    /// Scope of the copy account used for copying `source`
+    pub fn scope(&self, source: &Pubkey) -> CopyScope {
+        match self {
+            CopyPdaMode::Global => CopyScope::Global,
+            CopyPdaMode::PerSigner => CopyScope::Creator,
+            CopyPdaMode::Sharded(shards) => CopyScope::Shard {
+                index: shard_index(source, *shards),
+            },
+        }
+    }
+
+    /// Every copy account `creator` can write to in this mode
+    pub fn family(&self, creator: &Pubkey, copy_program: &Pubkey) -> Vec<(CopyScope, Pubkey)> {
+        let scopes = match self {
+            CopyPdaMode::Global => vec![CopyScope::Global],
+            CopyPdaMode::PerSigner => vec![CopyScope::Creator],
+            CopyPdaMode::Sharded(shards) => (0..*shards)
+                .map(|index| CopyScope::Shard { index })
+                .collect(),
+        };
+        scopes
+            .into_iter()
+            .map(|scope| (scope, scope.find_copy_address(creator, copy_program).0))
+            .collect()
+    }
+}
+
+impl FromStr for CopyPdaMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "global" => Ok(CopyPdaMode::Global),
+            "signer" => Ok(CopyPdaMode::PerSigner),
+            _ => match s.strip_prefix("sharded:").map(u8::from_str) {
+                Some(Ok(shards)) if shards > 0 => Ok(CopyPdaMode::Sharded(shards)),
+                _ => Err(format!(
+                    "unknown copy PDA mode {} (expected global, signer or sharded:<1-255>)",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+impl fmt::Display for CopyPdaMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CopyPdaMode::Global => write!(f, "global"),
+            CopyPdaMode::PerSigner => write!(f, "signer"),
+            CopyPdaMode::Sharded(shards) => write!(f, "sharded:{}", shards),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use copy::PREFIX;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_mode() {
+        for mode in [
+            CopyPdaMode::Global,
+            CopyPdaMode::PerSigner,
+            CopyPdaMode::Sharded(8),
+        ] {
+            assert_eq!(CopyPdaMode::from_str(&mode.to_string()), Ok(mode));
+        }
+        assert!(CopyPdaMode::from_str("sharded:0").is_err());
+        assert!(CopyPdaMode::from_str("sharded").is_err());
+    }
+
+    #[test]
+    fn test_copy_pda_family() {
+        let program = Pubkey::new_unique();
+        let creator = Pubkey::new_unique();
+        let source = Pubkey::new_unique();
+
+        // the global scope keeps the PDA derived from PREFIX alone
+        let (legacy, _) = Pubkey::find_program_address(&[PREFIX.as_bytes()], &program);
+        assert_eq!(
+            CopyPdaMode::Global.family(&creator, &program),
+            vec![(CopyScope::Global, legacy)]
+        );
+
+        let per_signer = CopyPdaMode::PerSigner.family(&creator, &program);
+        assert_ne!(per_signer[0].1, legacy);
+        assert_ne!(
+            per_signer,
+            CopyPdaMode::PerSigner.family(&Pubkey::new_unique(), &program)
+        );
+
+        let sharded = CopyPdaMode::Sharded(4);
+        let family = sharded.family(&creator, &program);
+        assert_eq!(family.len(), 4);
+        let scope = sharded.scope(&source);
+        assert!(family.iter().any(|(s, _)| *s == scope));
+        // the shard only depends on the source account
+        assert_eq!(
+            scope.find_copy_address(&creator, &program),
+            scope.find_copy_address(&Pubkey::new_unique(), &program)
+        );
+    }
+}
@@ -0,0 +1,515 @@
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::fmt;
+
+use account_proof_geyser::types::{BankHashComponents, Update};
+use solana_sdk::hash::Hash;
+
+use crate::ancestry::{AncestryLink, AncestryProof};
+use crate::attested::VerifiedAccount;
+
+/// How far trust in a bank hash goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trust {
+    /// A checkpoint, or committed to by a trusted header through the chain of parent bank hashes
+    Committed,
+    /// Builds on a trusted header through parent bank hashes. Nothing stops a header from being
+    /// built on any parent, so this only holds as long as no conflicting fork turns up: a header
+    /// that replaced a conflicting one is never trusted this way.
+    Descendant,
+}
+
+/// How an inserted header relates to the ones already stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Insertion {
+    /// The first header in the store
+    First,
+    /// Its parent bank hash is the bank hash of the stored header for `parent_slot`
+    Linked { parent_slot: u64 },
+    /// Its parent isn't stored: headers after `previous_slot` are missing
+    Gap { previous_slot: u64 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderError {
+    /// The update's bank hash isn't the hash of its components
+    InvalidBankHash { slot: u64 },
+    /// The header conflicts with the committed header or checkpoint for `conflicting_slot`: it has
+    /// another bank hash for the same slot, or it skips a header that builds on the same parent
+    Fork {
+        slot: u64,
+        bank_hash: Hash,
+        conflicting_slot: u64,
+    },
+    /// The committed header for `slot` has another bank hash than the one being trusted
+    CheckpointMismatch { slot: u64, stored: Hash, trusted: Hash },
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeaderError::InvalidBankHash { slot } => {
+                write!(f, "bank hash of slot {} does not match its components", slot)
+            }
+            HeaderError::Fork {
+                slot,
+                bank_hash,
+                conflicting_slot,
+            } => write!(
+                f,
+                "bank hash {} of slot {} is on another fork than the header of slot {}",
+                bank_hash, slot, conflicting_slot
+            ),
+            HeaderError::CheckpointMismatch {
+                slot,
+                stored,
+                trusted,
+            } => write!(
+                f,
+                "header of slot {} has bank hash {} but {} is trusted",
+                slot, stored, trusted
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HeaderError {}
+
+#[derive(Debug, Clone)]
+struct Header {
+    bank_hash: Hash,
+    components: BankHashComponents,
+    trust: Option<Trust>,
+    /// Replaced a conflicting header, so building on a trusted parent proves nothing
+    contested: bool,
+}
+
+impl Header {
+    fn is_parent_of(&self, child: &Header) -> bool {
+        child.components.parent_bankhash == self.bank_hash
+    }
+}
+
+/// Bank hashes of confirmed slots, linked into chains through their parent bank hashes. Trust
+/// placed in one bank hash extends backward to every header it commits to and forward to the
+/// headers that build on it.
+#[derive(Debug, Clone, Default)]
+pub struct HeaderStore {
+    headers: BTreeMap<u64, Header>,
+    /// Trusted bank hashes by slot, including those whose header isn't stored yet
+    checkpoints: BTreeMap<u64, Hash>,
+}
+
+impl HeaderStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.headers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+
+    pub fn bank_hash(&self, slot: u64) -> Option<Hash> {
+        self.headers.get(&slot).map(|header| header.bank_hash)
+    }
+
+    /// How far the bank hash of `slot` is trusted, if at all
+    pub fn trust(&self, slot: u64) -> Option<Trust> {
+        self.headers.get(&slot).and_then(|header| header.trust)
+    }
+
+    /// Consecutive stored slots that don't link, i.e. with headers missing between them
+    pub fn gaps(&self) -> Vec<(u64, u64)> {
+        self.headers
+            .iter()
+            .zip(self.headers.iter().skip(1))
+            .filter(|((_, parent), (_, child))| !parent.is_parent_of(child))
+            .map(|((parent_slot, _), (child_slot, _))| (*parent_slot, *child_slot))
+            .collect()
+    }
+
+    /// Store the bank hash of an update
+    pub fn insert_update(&mut self, update: &Update) -> Result<Insertion, HeaderError> {
+        let components = BankHashComponents::from(&update.proof);
+        if components.bank_hash() != update.root {
+            return Err(HeaderError::InvalidBankHash { slot: update.slot });
+        }
+        self.insert(update.slot, components)
+    }
+
+    /// Store the bank hash of `slot`, link it to its stored neighbours and extend their trust to it.
+    /// A header that conflicts with a committed header or a checkpoint is rejected. Conflicting
+    /// headers that aren't committed are evicted along with the headers that build on them, since
+    /// anyone can build a header on a trusted parent, and the new header is stored as contested.
+    pub fn insert(
+        &mut self,
+        slot: u64,
+        components: BankHashComponents,
+    ) -> Result<Insertion, HeaderError> {
+        let mut header = Header {
+            bank_hash: components.bank_hash(),
+            components,
+            trust: None,
+            contested: false,
+        };
+        let fork = |conflicting_slot| HeaderError::Fork {
+            slot,
+            bank_hash: header.bank_hash,
+            conflicting_slot,
+        };
+        if matches!(self.checkpoints.get(&slot), Some(checkpoint) if *checkpoint != header.bank_hash) {
+            return Err(fork(slot));
+        }
+
+        let mut conflicts = vec![];
+        if let Some(stored) = self.headers.get(&slot) {
+            if stored.bank_hash == header.bank_hash {
+                return Ok(self.insertion(slot));
+            }
+            conflicts.push(slot);
+        } else {
+            // Building on an older header than the closest earlier one skips the headers in
+            // between, which are then on another fork
+            if let Some((parent_slot, _)) = self
+                .headers
+                .range(..slot)
+                .rev()
+                .find(|(_, older)| older.is_parent_of(&header))
+            {
+                conflicts.extend(self.headers.range(parent_slot + 1..slot).map(|(slot, _)| *slot));
+            }
+            let previous = self.headers.range(..slot).next_back();
+            let next = self
+                .headers
+                .range((Bound::Excluded(slot), Bound::Unbounded))
+                .next();
+            if let (Some((_, previous)), Some((next_slot, next))) = (previous, next) {
+                // The next header builds on the previous one and skips this slot
+                if previous.is_parent_of(next) {
+                    conflicts.push(*next_slot);
+                }
+            }
+        }
+        if let Some(committed) = conflicts
+            .iter()
+            .find(|slot| self.headers[slot].trust == Some(Trust::Committed))
+        {
+            return Err(fork(*committed));
+        }
+
+        for conflicting_slot in &conflicts {
+            self.evict(*conflicting_slot);
+        }
+        header.contested = !conflicts.is_empty();
+        if self.checkpoints.contains_key(&slot) {
+            header.trust = Some(Trust::Committed);
+        }
+        self.headers.insert(slot, header);
+        self.propagate(slot);
+        Ok(self.insertion(slot))
+    }
+
+    /// Remove the header of `slot`, if still stored, and the headers that build on it
+    fn evict(&mut self, slot: u64) {
+        let mut next = self.headers.contains_key(&slot).then_some(slot);
+        while let Some(slot) = next {
+            next = self.child_slot(slot);
+            self.headers.remove(&slot);
+        }
+    }
+
+    fn insertion(&self, slot: u64) -> Insertion {
+        let header = &self.headers[&slot];
+        match self.headers.range(..slot).next_back() {
+            None => Insertion::First,
+            Some((parent_slot, parent)) if parent.is_parent_of(header) => Insertion::Linked {
+                parent_slot: *parent_slot,
+            },
+            Some((previous_slot, _)) => Insertion::Gap {
+                previous_slot: *previous_slot,
+            },
+        }
+    }
+
+    /// Trust `bank_hash` as the bank hash of `slot`, e.g. a checkpoint from a trusted source. A
+    /// stored header with another bank hash is evicted along with the headers that build on it,
+    /// unless it is committed. The checkpoint is kept, so that the header with the trusted bank
+    /// hash is committed once inserted.
+    pub fn trust_checkpoint(&mut self, slot: u64, bank_hash: Hash) -> Result<(), HeaderError> {
+        if let Some(header) = self.headers.get(&slot) {
+            if header.bank_hash != bank_hash {
+                if header.trust == Some(Trust::Committed) {
+                    return Err(HeaderError::CheckpointMismatch {
+                        slot,
+                        stored: header.bank_hash,
+                        trusted: bank_hash,
+                    });
+                }
+                self.evict(slot);
+            }
+        }
+        self.checkpoints.insert(slot, bank_hash);
+        if let Some(header) = self.headers.get_mut(&slot) {
+            header.trust = Some(Trust::Committed);
+            self.propagate(slot);
+        }
+        Ok(())
+    }
+
+    /// Trust the bank hash an attested account proof was verified against
+    pub fn trust_attested(&mut self, verified: &VerifiedAccount) -> Result<(), HeaderError> {
+        self.trust_checkpoint(verified.slot, verified.bank_hash)
+    }
+
+    /// A proof that the header of `slot` is an ancestor of the header of `descendant_slot`,
+    /// made of the stored headers between them. `None` unless they are linked without gaps.
+    pub fn ancestry_proof(&self, slot: u64, descendant_slot: u64) -> Option<AncestryProof> {
+        if descendant_slot <= slot || !self.headers.contains_key(&slot) {
+            return None;
+        }
+        let mut links = vec![];
+        let mut parent_slot = slot;
+        while parent_slot != descendant_slot {
+            let child_slot = self.child_slot(parent_slot)?;
+            links.push(AncestryLink::Parent {
+                slot: child_slot,
+                bank_hash: self.headers[&child_slot].components.clone(),
+            });
+            parent_slot = child_slot;
+        }
+        Some(AncestryProof { links })
+    }
+
+    /// The closest earlier header, if `slot` builds on it
+    fn parent_slot(&self, slot: u64) -> Option<u64> {
+        let (parent_slot, parent) = self.headers.range(..slot).next_back()?;
+        parent.is_parent_of(&self.headers[&slot]).then_some(*parent_slot)
+    }
+
+    /// The closest later header, if it builds on `slot`
+    fn child_slot(&self, slot: u64) -> Option<u64> {
+        let (child_slot, child) = self
+            .headers
+            .range((Bound::Excluded(slot), Bound::Unbounded))
+            .next()?;
+        self.headers[&slot].is_parent_of(child).then_some(*child_slot)
+    }
+
+    /// Extend trust between `slot` and its linked neighbours, then along the chain in both directions
+    fn propagate(&mut self, slot: u64) {
+        // A committed child commits to its parent
+        if let Some(child_slot) = self.child_slot(slot) {
+            if self.headers[&child_slot].trust == Some(Trust::Committed) {
+                self.headers.get_mut(&slot).unwrap().trust = Some(Trust::Committed);
+            }
+        }
+        if let Some(parent_slot) = self.parent_slot(slot) {
+            let header = &self.headers[&slot];
+            if self.headers[&parent_slot].trust.is_some() && header.trust.is_none() && !header.contested {
+                self.headers.get_mut(&slot).unwrap().trust = Some(Trust::Descendant);
+            }
+        }
+
+        if self.headers[&slot].trust == Some(Trust::Committed) {
+            let mut child_slot = slot;
+            while let Some(parent_slot) = self.parent_slot(child_slot) {
+                self.headers.get_mut(&parent_slot).unwrap().trust = Some(Trust::Committed);
+                child_slot = parent_slot;
+            }
+        }
+        if self.headers[&slot].trust.is_some() {
+            let mut parent_slot = slot;
+            while let Some(child_slot) = self.child_slot(parent_slot) {
+                let child = self.headers.get_mut(&child_slot).unwrap();
+                if child.trust.is_some() || child.contested {
+                    break;
+                }
+                child.trust = Some(Trust::Descendant);
+                parent_slot = child_slot;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use account_proof_geyser::types::AccountInfo;
+
+    use super::*;
+    use crate::ancestry::verify_ancestry;
+    use crate::verify::tests::account_update;
+
+    fn header_on(parent_bankhash: Hash) -> BankHashComponents {
+        BankHashComponents {
+            parent_bankhash,
+            accounts_delta_hash: Hash::new_unique(),
+            num_sigs: 1,
+            current_blockhash: Hash::new_unique(),
+        }
+    }
+
+    /// Headers for `slots`, each building on the one before
+    fn chain(slots: &[u64]) -> Vec<(u64, BankHashComponents)> {
+        let mut parent_bankhash = Hash::new_unique();
+        slots
+            .iter()
+            .map(|slot| {
+                let components = header_on(parent_bankhash);
+                parent_bankhash = components.bank_hash();
+                (*slot, components)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_links_and_gaps() {
+        let headers = chain(&[1, 2, 4, 5]);
+        let mut store = HeaderStore::new();
+        assert_eq!(store.insert(1, headers[0].1.clone()), Ok(Insertion::First));
+        // skipped slots don't break the chain
+        assert_eq!(store.insert(4, headers[2].1.clone()), Ok(Insertion::Gap { previous_slot: 1 }));
+        assert_eq!(store.insert(5, headers[3].1.clone()), Ok(Insertion::Linked { parent_slot: 4 }));
+        assert_eq!(store.gaps(), vec![(1, 4)]);
+        assert_eq!(store.insert(2, headers[1].1.clone()), Ok(Insertion::Linked { parent_slot: 1 }));
+        assert!(store.gaps().is_empty());
+
+        let mut update = account_update(AccountInfo {
+            slot: 6,
+            ..AccountInfo::default()
+        });
+        update.proof.num_sigs += 1;
+        assert_eq!(
+            store.insert_update(&update),
+            Err(HeaderError::InvalidBankHash { slot: 6 })
+        );
+    }
+
+    #[test]
+    fn test_trust_extends_along_the_chain() {
+        let headers = chain(&[1, 2, 3, 4]);
+        let mut store = HeaderStore::new();
+        for (slot, components) in headers.iter().filter(|(slot, _)| *slot != 3) {
+            store.insert(*slot, components.clone()).unwrap();
+        }
+        let checkpoint = headers[1].1.bank_hash();
+        store.trust_checkpoint(2, checkpoint).unwrap();
+        assert_eq!(store.trust(1), Some(Trust::Committed));
+        assert_eq!(store.trust(2), Some(Trust::Committed));
+        assert!(matches!(
+            store.trust_checkpoint(2, Hash::new_unique()),
+            Err(HeaderError::CheckpointMismatch { .. })
+        ));
+        // slot 4 isn't linked until slot 3 fills the gap
+        assert_eq!(store.trust(4), None);
+        store.insert(3, headers[2].1.clone()).unwrap();
+        assert_eq!(store.trust(3), Some(Trust::Descendant));
+        assert_eq!(store.trust(4), Some(Trust::Descendant));
+
+        // a later checkpoint commits to everything before it
+        store.trust_checkpoint(4, headers[3].1.bank_hash()).unwrap();
+        assert_eq!(store.trust(3), Some(Trust::Committed));
+    }
+
+    #[test]
+    fn test_ancestry_proof_from_stored_headers() {
+        let headers = chain(&[1, 2, 4, 5]);
+        let mut store = HeaderStore::new();
+        for (slot, components) in headers.iter().filter(|(slot, _)| *slot != 4) {
+            store.insert(*slot, components.clone()).unwrap();
+        }
+        assert!(store.ancestry_proof(1, 5).is_none());
+        store.insert(4, headers[2].1.clone()).unwrap();
+        let proof = store.ancestry_proof(1, 5).unwrap();
+        assert_eq!(proof.links.len(), 3);
+        assert_eq!(
+            verify_ancestry(1, headers[0].1.bank_hash(), &proof),
+            Ok((5, headers[3].1.bank_hash()))
+        );
+        assert!(store.ancestry_proof(5, 1).is_none());
+        assert!(store.ancestry_proof(1, 3).is_none());
+    }
+
+    #[test]
+    fn test_forks_of_committed_headers_are_rejected() {
+        let headers = chain(&[1, 2, 3]);
+        let mut store = HeaderStore::new();
+        for (slot, components) in &headers {
+            store.insert(*slot, components.clone()).unwrap();
+        }
+        store.trust_checkpoint(3, headers[2].1.bank_hash()).unwrap();
+
+        // another bank hash for a stored slot
+        let other = header_on(headers[1].1.bank_hash());
+        assert!(matches!(
+            store.insert(3, other.clone()),
+            Err(HeaderError::Fork { conflicting_slot: 3, .. })
+        ));
+        // building on slot 2 skips slot 3
+        assert!(matches!(
+            store.insert(4, other),
+            Err(HeaderError::Fork { conflicting_slot: 3, .. })
+        ));
+        // slot 3 builds on slot 1, so nothing can sit between them
+        let mut store = HeaderStore::new();
+        store.insert(1, headers[0].1.clone()).unwrap();
+        let skipping = header_on(headers[0].1.bank_hash());
+        store.insert(3, skipping.clone()).unwrap();
+        store.trust_checkpoint(3, skipping.bank_hash()).unwrap();
+        assert!(matches!(
+            store.insert(2, headers[1].1.clone()),
+            Err(HeaderError::Fork { conflicting_slot: 3, .. })
+        ));
+        assert_eq!(store.len(), 2);
+
+        // a checkpoint for a slot that isn't stored yet rejects other headers for it
+        store.trust_checkpoint(5, Hash::new_unique()).unwrap();
+        assert!(matches!(
+            store.insert(5, header_on(skipping.bank_hash())),
+            Err(HeaderError::Fork { conflicting_slot: 5, .. })
+        ));
+    }
+
+    #[test]
+    fn test_fabricated_header_is_replaced() {
+        let headers = chain(&[1, 2, 3]);
+        let mut store = HeaderStore::new();
+        store.insert(1, headers[0].1.clone()).unwrap();
+        store.trust_checkpoint(1, headers[0].1.bank_hash()).unwrap();
+
+        // anyone can build a header for slot 2 on the trusted parent
+        let fabricated = header_on(headers[0].1.bank_hash());
+        store.insert(2, fabricated.clone()).unwrap();
+        assert_eq!(store.trust(2), Some(Trust::Descendant));
+
+        // the honest header replaces it, but after a fork building on a trusted parent proves nothing
+        assert_eq!(
+            store.insert(2, headers[1].1.clone()),
+            Ok(Insertion::Linked { parent_slot: 1 })
+        );
+        assert_eq!(store.bank_hash(2), Some(headers[1].1.bank_hash()));
+        assert_eq!(store.trust(2), None);
+        store.insert(3, headers[2].1.clone()).unwrap();
+        assert_eq!(store.trust(3), None);
+
+        // the fabricated header evicts the honest branch again, until a checkpoint settles the slot
+        store.insert(2, fabricated.clone()).unwrap();
+        assert_eq!(store.len(), 2);
+        store.trust_checkpoint(2, headers[1].1.bank_hash()).unwrap();
+        assert_eq!(store.bank_hash(2), None);
+        assert_eq!(
+            store.insert(2, headers[1].1.clone()),
+            Ok(Insertion::Linked { parent_slot: 1 })
+        );
+        assert_eq!(store.trust(2), Some(Trust::Committed));
+        store.insert(3, headers[2].1.clone()).unwrap();
+        assert_eq!(store.trust(3), Some(Trust::Descendant));
+        assert!(matches!(
+            store.insert(2, fabricated),
+            Err(HeaderError::Fork { conflicting_slot: 2, .. })
+        ));
+    }
+}
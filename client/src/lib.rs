@@ -1,3 +1,13 @@
+pub mod ancestry;
+pub mod attested;
+pub mod await_proof;
+pub mod copy_pda;
+pub mod headers;
+pub mod proof_file;
+pub mod subscriber;
+pub mod verify;
+pub mod watch;
+
 use std::fs::File;
 use std::io::{Read, Write};
 use std::path::Path;
@@ -7,6 +17,10 @@ use solana_runtime::accounts_hash::AccountsHasher;
 use solana_sdk::hash::Hash;
 use solana_sdk::pubkey::Pubkey;
 
+pub use subscriber::{
+    ProofSubscriber, SubscriberConfig, SubscriberError, SubscriberTlsConfig, VerifiedUpdate,
+};
+
 /// Util helper function to write `size` number of random bytes to file at path `P`
 pub fn write_random_bytes<P: AsRef<Path>>(path: P, size: u64) -> std::io::Result<()> {
     let mut file = File::create(path)?;
@@ -1,11 +1,16 @@
 use alloc::rc::Rc;
 use std::str::FromStr;
+use std::time::Duration;
 
-use account_proof_geyser::types::Update;
-use account_proof_geyser::utils::verify_leaves_against_bankhash;
-use borsh::BorshDeserialize;
-use tokio::io::AsyncReadExt;
-use tokio::net::TcpStream;
+use account_proof_geyser::copy_events::{assemble_copy_preimages, check_copy_digest, decode_copy_events, entries_digest};
+use account_proof_geyser::types::AccountInfo;
+use client::await_proof::await_account_proof;
+use client::copy_pda::CopyPdaMode;
+use client::proof_file::{load_update, save_update, ProofFormat};
+use client::subscriber::DEFAULT_GEYSER_ENDPOINT;
+use client::verify::{verify_update, TrustedRoot};
+use client::watch::{AccountWatcher, WatchEvent};
+use client::{ProofSubscriber, SubscriberConfig, VerifiedUpdate};
 use tokio::runtime::Runtime;
 
 use anchor_client::{Client, Cluster};
@@ -13,15 +18,21 @@ use anchor_lang::solana_program::sysvar::clock::Clock;
 use anchor_lang::AccountDeserialize;
 use clap::Parser;
 use clap::Subcommand;
-use copy::{accounts as copy_accounts, instruction as copy_instruction, PREFIX, CopyAccount, account_hasher};
+use copy::{accounts as copy_accounts, instruction as copy_instruction, ByteRange, CopyAccount, CopyEntry, CopyEvent, CopyScope, account_hasher, account_range_hasher, ACCOUNT_HASH_VERSION};
+use solana_account_decoder::{UiAccountEncoding, UiDataSliceConfig};
 use solana_rpc_client::rpc_client::RpcClient;
+use solana_rpc_client_api::config::{RpcAccountInfoConfig, RpcBlockConfig};
 use solana_sdk::account::Account;
 use solana_sdk::commitment_config::{CommitmentConfig, CommitmentLevel};
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::AccountMeta;
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::{Keypair, Signature, Signer};
 use solana_sdk::signer::keypair::read_keypair_file;
 use solana_sdk::sysvar::SysvarId;
-use solana_sdk::system_program;
+use solana_sdk::transaction::Transaction;
+use solana_sdk::{system_instruction, system_program};
+use solana_transaction_status::{TransactionDetails, UiTransactionEncoding};
 
 extern crate alloc;
 
@@ -32,55 +43,223 @@ pub struct CopyClient {
     pub rpc_url: String,
     pub ws_url: String,
     pub signer: Keypair,
-    pub copy_program: Pubkey,
-    pub copy_pda: (Pubkey, u8),
+    /// Copy program that copies go through; not needed to `touch` an account
+    pub copy_program: Option<Pubkey>,
+    pub pda_mode: CopyPdaMode,
     pub clock_account: Pubkey,
     pub system_program: Pubkey,
 }
 
 impl CopyClient {
-    pub fn new(rpc_url: String, ws_url: String, signer: Keypair, copy_program: &str) -> Self {
-        let copy_program_pubkey = Pubkey::from_str(copy_program).unwrap();
-        let (copy_pda, bump) =
-            Pubkey::find_program_address(&[PREFIX.as_bytes()], &copy_program_pubkey);
-
+    pub fn new(rpc_url: String, ws_url: String, signer: Keypair, copy_program: Option<&str>, pda_mode: CopyPdaMode) -> Self {
         CopyClient {
             rpc_url,
             ws_url,
             signer,
-            copy_program: Pubkey::from_str(copy_program).unwrap(),
-            copy_pda: (copy_pda, bump),
+            copy_program: copy_program.map(|program| Pubkey::from_str(program).unwrap()),
+            pda_mode,
             clock_account: Clock::id(),
             system_program: system_program::id(),
         }
     }
 
-    pub fn send_transaction(&self, source_account: &Pubkey) -> anyhow::Result<Signature> {
+    fn copy_program(&self) -> Pubkey {
+        self.copy_program.expect("CopyClient was created without a copy program")
+    }
+
+    /// Copy account that a copy of `source_account` is written to, along with its scope and bump
+    pub fn copy_pda(&self, source_account: &Pubkey) -> (CopyScope, Pubkey, u8) {
+        let scope = self.pda_mode.scope(source_account);
+        let (copy_pda, bump) = scope.find_copy_address(&self.signer.pubkey(), &self.copy_program());
+        (scope, copy_pda, bump)
+    }
+
+    /// Send `copy_hash` for `source_account` and wait until it is confirmed.
+    /// Returns the transaction signature and the slot it landed in.
+    pub fn send_transaction(&self, source_account: &Pubkey) -> anyhow::Result<(Signature, u64)> {
         let creator_pubkey = self.signer.pubkey();
         let c = Client::new(
             Cluster::Custom(self.rpc_url.clone(), self.ws_url.clone()),
             Rc::new(self.signer.insecure_clone()),
         );
-        let prog = c.program(self.copy_program).unwrap();
+        let prog = c.program(self.copy_program()).unwrap();
+        let (scope, copy_pda, bump) = self.copy_pda(source_account);
 
         let signature = prog
             .request()
             .accounts(copy_accounts::CopyHash {
                 creator: creator_pubkey,
                 source_account: *source_account,
-                copy_account: self.copy_pda.0,
+                copy_account: copy_pda,
                 clock: self.clock_account,
                 system_program: self.system_program,
             })
             .args(copy_instruction::CopyHash {
-                bump: self.copy_pda.1,
+                bump,
+                scope,
+            })
+            .options(CommitmentConfig {
+                commitment: CommitmentLevel::Confirmed,
+            })
+            .send()?;
+        let slot = self.transaction_slot(&signature)?;
+        Ok((signature, slot))
+    }
+
+    /// Send `copy_hash_range` for `range` of the data of `source_account` and wait until it is
+    /// confirmed. Returns the transaction signature and the slot it landed in.
+    pub fn send_copy_range(&self, source_account: &Pubkey, range: &ByteRange) -> anyhow::Result<(Signature, u64)> {
+        let creator_pubkey = self.signer.pubkey();
+        let c = Client::new(
+            Cluster::Custom(self.rpc_url.clone(), self.ws_url.clone()),
+            Rc::new(self.signer.insecure_clone()),
+        );
+        let prog = c.program(self.copy_program()).unwrap();
+        let (scope, copy_pda, bump) = self.copy_pda(source_account);
+
+        let signature = prog
+            .request()
+            .accounts(copy_accounts::CopyHash {
+                creator: creator_pubkey,
+                source_account: *source_account,
+                copy_account: copy_pda,
+                clock: self.clock_account,
+                system_program: self.system_program,
+            })
+            .args(copy_instruction::CopyHashRange {
+                bump,
+                scope,
+                offset: range.offset,
+                len: range.len,
+            })
+            .options(CommitmentConfig {
+                commitment: CommitmentLevel::Confirmed,
+            })
+            .send()?;
+        let slot = self.transaction_slot(&signature)?;
+        Ok((signature, slot))
+    }
+
+    /// Send `copy_hashes` for all of `source_accounts` in one transaction and wait until it is
+    /// confirmed. Returns the transaction signature and the slot it landed in.
+    /// When sharded, all the copies go to the shard of the first source account.
+    pub fn send_copy_hashes(&self, source_accounts: &[Pubkey]) -> anyhow::Result<(Signature, u64)> {
+        let creator_pubkey = self.signer.pubkey();
+        let c = Client::new(
+            Cluster::Custom(self.rpc_url.clone(), self.ws_url.clone()),
+            Rc::new(self.signer.insecure_clone()),
+        );
+        let prog = c.program(self.copy_program()).unwrap();
+        let Some(first_source) = source_accounts.first() else {
+            anyhow::bail!("No source accounts to copy");
+        };
+        let (scope, copy_pda, bump) = self.copy_pda(first_source);
+
+        let source_metas: Vec<AccountMeta> = source_accounts
+            .iter()
+            .map(|source_account| AccountMeta::new_readonly(*source_account, false))
+            .collect();
+        let signature = prog
+            .request()
+            .accounts(copy_accounts::CopyHashes {
+                creator: creator_pubkey,
+                copy_account: copy_pda,
+                clock: self.clock_account,
+                system_program: self.system_program,
+            })
+            .accounts(source_metas)
+            .args(copy_instruction::CopyHashes {
+                bump,
+                scope,
+            })
+            .options(CommitmentConfig {
+                commitment: CommitmentLevel::Confirmed,
+            })
+            .send()?;
+        let slot = self.transaction_slot(&signature)?;
+        Ok((signature, slot))
+    }
+
+    /// Close the copy account that copies of `source_account` go to and reclaim its rent. Only
+    /// works for the signer that paid for it, and for a shared one only once its slot is over.
+    pub fn close_copy_account(&self, source_account: &Pubkey) -> anyhow::Result<Signature> {
+        let c = Client::new(
+            Cluster::Custom(self.rpc_url.clone(), self.ws_url.clone()),
+            Rc::new(self.signer.insecure_clone()),
+        );
+        let prog = c.program(self.copy_program()).unwrap();
+        let (scope, copy_pda, _) = self.copy_pda(source_account);
+
+        let signature = prog
+            .request()
+            .accounts(copy_accounts::CloseCopyAccount {
+                payer: self.signer.pubkey(),
+                copy_account: copy_pda,
+            })
+            .args(copy_instruction::CloseCopyAccount { scope })
+            .options(CommitmentConfig {
+                commitment: CommitmentLevel::Confirmed,
             })
+            .send()?;
+        Ok(signature)
+    }
+
+    /// Grow the copy account that copies of `source_account` go to from an older layout to the
+    /// current one, paying for the extra rent
+    pub fn migrate_copy_account(&self, source_account: &Pubkey) -> anyhow::Result<Signature> {
+        let c = Client::new(
+            Cluster::Custom(self.rpc_url.clone(), self.ws_url.clone()),
+            Rc::new(self.signer.insecure_clone()),
+        );
+        let prog = c.program(self.copy_program()).unwrap();
+        let (scope, copy_pda, _) = self.copy_pda(source_account);
+
+        let signature = prog
+            .request()
+            .accounts(copy_accounts::MigrateCopyAccount {
+                payer: self.signer.pubkey(),
+                copy_account: copy_pda,
+                system_program: self.system_program,
+            })
+            .args(copy_instruction::MigrateCopyAccount { scope })
             .options(CommitmentConfig {
-                commitment: CommitmentLevel::Processed,
+                commitment: CommitmentLevel::Confirmed,
             })
             .send()?;
         Ok(signature)
     }
+
+    /// Slot in which a confirmed transaction landed
+    pub fn transaction_slot(&self, signature: &Signature) -> anyhow::Result<u64> {
+        transaction_slot(&self.rpc_url, signature)
+    }
+
+    /// Write to `account` by sending it `lamports`, which puts it in the accounts delta hash of the
+    /// slot without the copy program. Waits until the transfer is confirmed and returns its
+    /// signature and the slot it landed in.
+    pub fn touch(&self, account: &Pubkey, lamports: u64) -> anyhow::Result<(Signature, u64)> {
+        let rpc_client = RpcClient::new_with_commitment(self.rpc_url.clone(), CommitmentConfig::confirmed());
+        let blockhash = rpc_client.get_latest_blockhash()?;
+        let transaction = Transaction::new_signed_with_payer(
+            &[system_instruction::transfer(&self.signer.pubkey(), account, lamports)],
+            Some(&self.signer.pubkey()),
+            &[&self.signer],
+            blockhash,
+        );
+        let signature = rpc_client.send_and_confirm_transaction(&transaction)?;
+        let slot = self.transaction_slot(&signature)?;
+        Ok((signature, slot))
+    }
+}
+
+/// Slot in which a confirmed transaction landed
+fn transaction_slot(rpc_url: &str, signature: &Signature) -> anyhow::Result<u64> {
+    let rpc_client = RpcClient::new_with_commitment(rpc_url.to_string(), CommitmentConfig::confirmed());
+    let statuses = rpc_client.get_signature_statuses(&[*signature])?;
+    match statuses.value.into_iter().next().flatten() {
+        Some(status) => Ok(status.slot),
+        None => anyhow::bail!("No status found for transaction {}", signature),
+    }
 }
 
 #[derive(Parser)]
@@ -106,81 +285,681 @@ enum Commands {
         #[arg(short, long, default_value_t=DEFAULT_WS_URL.to_string())]
         /// URL for solana Websocket
         ws_url: String,
+
+        #[arg(short, long, default_value_t=DEFAULT_GEYSER_ENDPOINT.to_string())]
+        /// host:port of the geyser plugin's proof stream
+        geyser_endpoint: String,
+
+        #[arg(long)]
+        /// Save the verified update to this file
+        save_proof: Option<String>,
+
+        #[arg(long, default_value_t=ProofFormat::Borsh)]
+        /// Encoding of the saved update: borsh or json
+        proof_format: ProofFormat,
+
+        #[arg(long, default_value_t=CopyPdaMode::Global)]
+        /// Copy account to write to: global, signer (one per signer) or sharded:<N> (by source account)
+        pda_mode: CopyPdaMode,
+
+        #[arg(long, requires = "len")]
+        /// Only copy the account data from this byte offset
+        offset: Option<u64>,
+
+        #[arg(long, requires = "offset")]
+        /// Number of bytes of account data to copy from --offset
+        len: Option<u64>,
     },
+    /// Copy several accounts in one transaction and verify each of them against the proof
+    CopyHashes {
+        copy_program: String,
+        #[arg(required = true, num_args = 1..)]
+        /// Accounts to copy, in order
+        accounts_for_proof: Vec<String>,
+        #[arg(long, required = true)]
+        /// Path to the signer key
+        signer: String,
+        #[arg(short, long, default_value_t=DEFAULT_RPC_URL.to_string())]
+        /// URL for solana RPC
+        rpc_url: String,
+
+        #[arg(short, long, default_value_t=DEFAULT_WS_URL.to_string())]
+        /// URL for solana Websocket
+        ws_url: String,
+
+        #[arg(short, long, default_value_t=DEFAULT_GEYSER_ENDPOINT.to_string())]
+        /// host:port of the geyser plugin's proof stream
+        geyser_endpoint: String,
+
+        #[arg(long)]
+        /// Save the verified update to this file
+        save_proof: Option<String>,
+
+        #[arg(long, default_value_t=ProofFormat::Borsh)]
+        /// Encoding of the saved update: borsh or json
+        proof_format: ProofFormat,
+
+        #[arg(long, default_value_t=CopyPdaMode::Global)]
+        /// Copy account to write to: global, signer (one per signer) or sharded:<N> (by source account)
+        pda_mode: CopyPdaMode,
+    },
+    /// Verify a saved update against a trusted bank hash or a trusted validator set
+    Verify {
+        /// Update saved with --save-proof
+        proof_file: String,
+
+        #[arg(long, required_unless_present = "validators", conflicts_with = "validators")]
+        /// Bank hash trusted for the update's slot
+        bank_hash: Option<String>,
+
+        #[arg(long, value_delimiter = ',')]
+        /// Comma separated VOTE_ACCOUNT:AUTHORIZED_VOTER pairs of trusted validators that must have
+        /// voted for the bank hash
+        validators: Vec<String>,
+
+        #[arg(long)]
+        /// Number of trusted validators that must have voted. Defaults to two thirds of them
+        min_votes: Option<usize>,
+    },
+    /// Follow an account from a verified state, checking the proof for every confirmed slot
+    Watch {
+        account: String,
+        #[arg(short, long, default_value_t=DEFAULT_GEYSER_ENDPOINT.to_string())]
+        /// host:port of the geyser plugin's proof stream
+        geyser_endpoint: String,
+        #[arg(long, requires = "bank_hash")]
+        /// Start from the state proven by an update saved with --save-proof. Without it the
+        /// watch starts at the next slot that modifies the account
+        from_proof: Option<String>,
+        #[arg(long)]
+        /// Bank hash trusted for the slot of the saved update
+        bank_hash: Option<String>,
+    },
+    /// Write to an account with a minimal transfer and verify its full state from the proof,
+    /// without the copy program. The plugin must be proving the account (`account_list`).
+    Touch {
+        account: String,
+        #[arg(long, required = true)]
+        /// Path to the signer key
+        signer: String,
+        #[arg(short, long, default_value_t=DEFAULT_RPC_URL.to_string())]
+        /// URL for solana RPC
+        rpc_url: String,
+
+        #[arg(short, long, default_value_t=DEFAULT_WS_URL.to_string())]
+        /// URL for solana Websocket
+        ws_url: String,
+
+        #[arg(short, long, default_value_t=DEFAULT_GEYSER_ENDPOINT.to_string())]
+        /// host:port of the geyser plugin's proof stream
+        geyser_endpoint: String,
+
+        #[arg(long, default_value_t = 1)]
+        /// Lamports sent to the account
+        lamports: u64,
+
+        #[arg(long)]
+        /// Save the verified update to this file
+        save_proof: Option<String>,
+
+        #[arg(long, default_value_t=ProofFormat::Borsh)]
+        /// Encoding of the saved update: borsh or json
+        proof_format: ProofFormat,
+    },
+    /// Register an account with the plugin and verify its state at the next confirmed slot that
+    /// modifies it. Without --copy-program, fails if that doesn't happen within --timeout.
+    Await {
+        account: String,
+        #[arg(short, long, default_value_t=DEFAULT_GEYSER_ENDPOINT.to_string())]
+        /// host:port of the geyser plugin's proof stream
+        geyser_endpoint: String,
+
+        #[arg(long)]
+        /// API key of the plugin's proof stream. The plugin only registers accounts for clients
+        /// with a key
+        auth_token: Option<String>,
+
+        #[arg(long, default_value_t = 60)]
+        /// Seconds to wait for the account to be modified
+        timeout: u64,
+
+        #[arg(long, requires = "signer")]
+        /// Copy the account through this copy program if no proof arrives in time
+        copy_program: Option<String>,
+
+        #[arg(long)]
+        /// Path to the signer key, for the copy transaction
+        signer: Option<String>,
+
+        #[arg(long, default_value_t=CopyPdaMode::Global)]
+        /// global, signer or sharded:<N>, for the copy transaction
+        pda_mode: CopyPdaMode,
+
+        #[arg(short, long, default_value_t=DEFAULT_RPC_URL.to_string())]
+        /// URL for solana RPC
+        rpc_url: String,
+
+        #[arg(short, long, default_value_t=DEFAULT_WS_URL.to_string())]
+        /// URL for solana Websocket
+        ws_url: String,
+
+        #[arg(long)]
+        /// Save the verified update to this file
+        save_proof: Option<String>,
+
+        #[arg(long, default_value_t=ProofFormat::Borsh)]
+        /// Encoding of the saved update: borsh or json
+        proof_format: ProofFormat,
+    },
+    /// Close a copy account the signer paid for and reclaim its rent
+    CloseCopyAccount {
+        copy_program: String,
+        #[arg(long, required = true)]
+        /// Path to the signer key
+        signer: String,
+        #[arg(short, long, default_value_t=DEFAULT_RPC_URL.to_string())]
+        /// URL for solana RPC
+        rpc_url: String,
+
+        #[arg(short, long, default_value_t=DEFAULT_WS_URL.to_string())]
+        /// URL for solana Websocket
+        ws_url: String,
+
+        #[arg(long, default_value_t=CopyPdaMode::Global)]
+        /// global, signer or sharded:<N>
+        pda_mode: CopyPdaMode,
+
+        #[arg(long)]
+        /// Any source account copied to the shard to close, when sharded
+        source: Option<String>,
+    },
+    /// Grow a copy account created by an older version of the copy program to the current layout
+    MigrateCopyAccount {
+        copy_program: String,
+        #[arg(long, required = true)]
+        /// Path to the signer key, which pays for the extra rent
+        signer: String,
+        #[arg(short, long, default_value_t=DEFAULT_RPC_URL.to_string())]
+        /// URL for solana RPC
+        rpc_url: String,
+
+        #[arg(short, long, default_value_t=DEFAULT_WS_URL.to_string())]
+        /// URL for solana Websocket
+        ws_url: String,
+
+        #[arg(long, default_value_t=CopyPdaMode::Global)]
+        /// global, signer or sharded:<N>
+        pda_mode: CopyPdaMode,
+
+        #[arg(long)]
+        /// Any source account copied to the shard to migrate, when sharded
+        source: Option<String>,
+    },
+    /// Print the copy accounts a signer writes to
     CopyPda {
         copy_program: String,
+        #[arg(long, default_value_t=CopyPdaMode::Global)]
+        /// global, signer or sharded:<N>
+        pda_mode: CopyPdaMode,
+        #[arg(long, required_if_eq("pda_mode", "signer"))]
+        /// Signer pubkey, for per-signer copy accounts
+        creator: Option<String>,
+    },
+    /// Rebuild the digest of a copy account from the copy events of a confirmed slot
+    CopyEvents {
+        /// Copy account (PDA) to rebuild
+        copy_pda: String,
+        /// Slot whose copies are rebuilt
+        slot: u64,
+        #[arg(short, long, default_value_t=DEFAULT_RPC_URL.to_string())]
+        /// URL for solana RPC
+        rpc_url: String,
+        #[arg(long, default_value_t=copy::id().to_string())]
+        /// Copy program whose events are decoded
+        copy_program: String,
     }
 }
 
-fn query_account(addr: &Pubkey) -> Account {
-    let url = "http://localhost:8899".to_string();
-    let client = RpcClient::new(url);
-    client.get_account(addr).unwrap()
+/// Read accounts from a node that has processed at least `min_context_slot`, only fetching
+/// `range` of their data when given.
+/// Returns the slot of the state that was read along with the accounts, in the same order.
+fn query_accounts(rpc_url: &str, addrs: &[Pubkey], min_context_slot: u64, range: Option<&ByteRange>) -> anyhow::Result<(u64, Vec<Account>)> {
+    let client = RpcClient::new(rpc_url.to_string());
+    let response = client.get_multiple_accounts_with_config(addrs, RpcAccountInfoConfig {
+        encoding: Some(UiAccountEncoding::Base64),
+        commitment: Some(CommitmentConfig::confirmed()),
+        min_context_slot: Some(min_context_slot),
+        data_slice: range.map(|range| UiDataSliceConfig {
+            offset: range.offset as usize,
+            length: range.len as usize,
+        }),
+    })?;
+    let mut accounts = Vec::with_capacity(addrs.len());
+    for (addr, account) in addrs.iter().zip(response.value) {
+        let Some(account) = account else {
+            anyhow::bail!("Account {} not found", addr);
+        };
+        if let Some(range) = range {
+            if account.data.len() as u64 != range.len {
+                anyhow::bail!("Account {} has no bytes {}..{}", addr, range.offset, range.offset + range.len);
+            }
+        }
+        accounts.push(account);
+    }
+    Ok((response.context.slot, accounts))
 }
 
-async fn monitor_and_verify_updates(rpc_pubkey: &Pubkey, rpc_account: &Account) -> anyhow::Result<()> {
-    let mut stream = TcpStream::connect("127.0.0.1:10000")
-        .await
-        .expect("unable to connect to 127.0.0.1 on port 10000");
+/// Copy events emitted by `copy_program` in every transaction of a confirmed block, in block order
+fn query_copy_events(rpc_url: &str, slot: u64, copy_program: &Pubkey) -> anyhow::Result<Vec<CopyEvent>> {
+    let client = RpcClient::new(rpc_url.to_string());
+    let block = client.get_block_with_config(slot, RpcBlockConfig {
+        encoding: Some(UiTransactionEncoding::Base64),
+        transaction_details: Some(TransactionDetails::Full),
+        rewards: Some(false),
+        commitment: Some(CommitmentConfig::confirmed()),
+        max_supported_transaction_version: Some(0),
+    })?;
+    let mut events = vec![];
+    for transaction in block.transactions.unwrap_or_default() {
+        let logs: Option<Vec<String>> = transaction.meta.and_then(|meta| meta.log_messages.into());
+        events.extend(decode_copy_events(&logs.unwrap_or_default(), &[*copy_program]));
+    }
+    Ok(events)
+}
+
+/// The entry a copy of `account` read over RPC should have made. With a range, `account.data`
+/// holds only that range.
+fn expected_copy_entry(pubkey: &Pubkey, account: &Account, range: Option<ByteRange>) -> CopyEntry {
+    let hash = match &range {
+        None => account_hasher(pubkey, account.lamports, &account.data,
+                               &account.owner, account.executable, account.rent_epoch),
+        Some(range) => account_range_hasher(pubkey, account.lamports, &account.owner,
+                                            account.executable, account.rent_epoch, range, &account.data),
+    };
+    CopyEntry {
+        source: *pubkey,
+        hash: hash.to_bytes(),
+        range,
+    }
+}
+
+/// Wait for the update for `slot` and check that the copy account it proves has an entry for each
+/// source account with its hash as read over RPC
+async fn monitor_and_verify_updates(geyser_endpoint: String, copy_pda: Pubkey, slot: u64, rpc_accounts: &[(Pubkey, Account)], range: Option<ByteRange>, rpc_context_slot: u64, save_proof: Option<(String, ProofFormat)>) -> anyhow::Result<()> {
+    let mut subscriber = ProofSubscriber::new(SubscriberConfig {
+        endpoint: geyser_endpoint,
+        pubkeys: [copy_pda].into_iter().collect(),
+        start_slot: Some(slot),
+        end_slot: Some(slot),
+        ..SubscriberConfig::default()
+    });
+
+    let Some(verified_update) = subscriber.next_update().await else {
+        anyhow::bail!("No proof for the copy account {} in slot {}", copy_pda, slot);
+    };
+    let verified_update = verified_update?;
+    let Some(copy_pda_account) = verified_update.account(&copy_pda) else {
+        anyhow::bail!("Update for slot {} has no proof for the copy account", slot);
+    };
+
+    println!("\nBankHash proof verification succeeded for account with Pubkey: {:?} in slot {}", &copy_pda
+             ,slot);
+    let copy_account = CopyAccount::try_deserialize(&mut copy_pda_account.data.as_slice())?;
+    if copy_account.slot != slot {
+        anyhow::bail!("Copy account was last written in slot {}, expected slot {}", copy_account.slot, slot);
+    }
+    if copy_account.version != ACCOUNT_HASH_VERSION {
+        anyhow::bail!("Copy account hashes use version {}, only version {} is supported", copy_account.version, ACCOUNT_HASH_VERSION);
+    }
+    if entries_digest(&copy_account) != Some(copy_account.digest) {
+        anyhow::bail!("Copy account entries for slot {} do not match its digest", slot);
+    }
+    let preimages = verified_update.copy_preimages(&copy_pda);
+    if let Some(preimages) = preimages {
+        println!("The {} hashes the plugin saw accumulated in slot {} open the copy account digest", preimages.preimages.len(), slot);
+    }
+    for (rpc_pubkey, rpc_account) in rpc_accounts {
+        let entry = expected_copy_entry(rpc_pubkey, rpc_account, range);
+        // copies that rolled over out of a full copy account are only among the preimages
+        let rolled_over = preimages.iter().flat_map(|preimages| &preimages.preimages).any(|preimage| {
+            preimage.source == entry.source
+                && preimage.hash.to_bytes() == entry.hash
+                && preimage.range == entry.range.map(|range| (range.offset, range.len))
+        });
+        if !copy_account.contains(&entry) && !rolled_over {
+            anyhow::bail!(
+                "Hash of the rpc account {} (read at slot {}) is not among the copy account entries for slot {}. \
+                 The source account may have changed since it was copied",
+                rpc_pubkey,
+                rpc_context_slot,
+                slot
+            );
+        }
+        println!("Hash for rpc account {} matches an entry verified as part of the BankHash: {}", rpc_pubkey, Hash::new_from_array(entry.hash));
+    }
+    for (_, rpc_account) in rpc_accounts {
+        println!("{:?}", rpc_account);
+    }
+    if let Some((path, format)) = save_proof {
+        save_update(&path, &verified_update.update, format)?;
+        println!("Saved the proof for slot {} to {}", slot, path);
+    }
+    Ok(())
+}
 
-    let mut buffer = vec![0u8; 65536];
-    let n = stream.read(&mut buffer)
-        .await
-        .expect("unable to read to mutable buffer");
+/// Print a per-account report for a saved update. Returns whether everything verified.
+fn verify_proof_file(proof_file: &str, bank_hash: Option<&String>, validators: &[String], min_votes: Option<usize>) -> anyhow::Result<bool> {
+    let update = load_update(proof_file)?;
+    let trusted_root = match bank_hash {
+        Some(bank_hash) => TrustedRoot::BankHash(Hash::from_str(bank_hash)?),
+        None => {
+            let validators = validators
+                .iter()
+                .map(|validator| {
+                    let Some((vote_account, authority)) = validator.split_once(':') else {
+                        anyhow::bail!("expected VOTE_ACCOUNT:AUTHORIZED_VOTER, got {}", validator);
+                    };
+                    Ok((Pubkey::from_str(vote_account)?, Pubkey::from_str(authority)?))
+                })
+                .collect::<anyhow::Result<_>>()?;
+            match min_votes {
+                Some(min_votes) => TrustedRoot::Validators { validators, min_votes },
+                None => TrustedRoot::supermajority_of(validators),
+            }
+        }
+    };
 
-    if n == 0 {
-        anyhow::bail!("Connection closed");
+    let report = verify_update(&update, &trusted_root);
+    println!("Slot {} bank hash {}", report.slot, report.bank_hash);
+    match &report.bank_hash_trust {
+        Ok(reason) => println!("  trusted: {}", reason),
+        Err(reason) => println!("  NOT trusted: {}", reason),
+    }
+    if report.accounts.is_empty() {
+        println!("  no account proofs in the update");
+    }
+    for account in &report.accounts {
+        match &account.error {
+            None => println!("  OK     {} lamports={} owner={} data_len={}", account.pubkey, account.lamports, account.owner, account.data_len),
+            Some(error) => println!("  FAILED {}: {}", account.pubkey, error),
+        }
+    }
+    for transaction in &report.transactions {
+        match &transaction.error {
+            None => println!("  OK     transaction {} in entry {}", transaction.signature, transaction.entry_index),
+            Some(error) => println!("  FAILED transaction {}: {}", transaction.signature, error),
+        }
     }
+    Ok(report.is_valid())
+}
 
-    let received_update: Update = Update::try_from_slice(&buffer[..n]).unwrap();
+/// Wait for the proof of `account` in `slot` and print the state it proves. The account data is
+/// checked against the leaf hash by the subscriber, so no RPC read is needed.
+async fn monitor_and_verify_touch(geyser_endpoint: String, account: Pubkey, signature: Signature, slot: u64, save_proof: Option<(String, ProofFormat)>) -> anyhow::Result<()> {
+    let mut subscriber = ProofSubscriber::new(SubscriberConfig {
+        endpoint: geyser_endpoint,
+        pubkeys: [account].into_iter().collect(),
+        start_slot: Some(slot),
+        end_slot: Some(slot),
+        ..SubscriberConfig::default()
+    });
+
+    let Some(verified_update) = subscriber.next_update().await else {
+        anyhow::bail!("No proof for {} in slot {}. Is the plugin proving it (account_list)?", account, slot);
+    };
+    let verified_update = verified_update?;
+    match verified_update.transaction_proof(&signature) {
+        Some(proof) => println!("Touch transaction {} proven in entry {} of slot {}", signature, proof.entry_index, slot),
+        None => println!("No inclusion proof for the touch transaction {} in slot {}", signature, slot),
+    }
+    print_proven_account(&verified_update, &account, save_proof)
+}
 
-    let bankhash = received_update.root;
-    let bankhash_proof = received_update.proof;
-    let slot_num = received_update.slot;
-    for p in bankhash_proof.proofs {
-        verify_leaves_against_bankhash(&p,
-                                       bankhash,
-                                       bankhash_proof.num_sigs,
-                                       bankhash_proof.account_delta_root,
-                                       bankhash_proof.parent_bankhash,
-                                       bankhash_proof.blockhash).unwrap();
+/// Print the state of `account` proven by a verified update, and save the update if asked to
+fn print_proven_account(verified_update: &VerifiedUpdate, account: &Pubkey, save_proof: Option<(String, ProofFormat)>) -> anyhow::Result<()> {
+    let slot = verified_update.slot;
+    let Some(proven_account) = verified_update.account(account) else {
+        anyhow::bail!("Update for slot {} has no proof for {}", slot, account);
+    };
 
-        println!("\nBankHash proof verification succeeded for account with Pubkey: {:?} in slot {}", &p.0
-                 ,slot_num);
-        let copy_account = CopyAccount::try_deserialize(&mut p.1.0.account.data.as_slice())?;
-        let rpc_account_hash = account_hasher(&rpc_pubkey, rpc_account.lamports, &rpc_account.data,
-                                              &rpc_account.owner,rpc_account.rent_epoch);
-        assert_eq!(rpc_account_hash.as_ref(),&copy_account.digest);
-        println!("Hash for rpc account matches Hash verified as part of the BankHash: {}",rpc_account_hash);
-        println!("{:?}",&rpc_account);
+    println!("\nBankHash proof verification succeeded for account with Pubkey: {:?} in slot {}", account, slot);
+    print_account_state(slot, proven_account);
+    println!("data: {}", hex::encode(&proven_account.data));
+    if let Some((path, format)) = save_proof {
+        save_update(&path, &verified_update.update, format)?;
+        println!("Saved the proof for slot {} to {}", slot, path);
     }
     Ok(())
 }
 
+/// Load a saved update to start watching from, checking it against a trusted bank hash
+fn load_verified_update(proof_file: &str, bank_hash: &str) -> anyhow::Result<VerifiedUpdate> {
+    let update = load_update(proof_file)?;
+    let report = verify_update(&update, &TrustedRoot::BankHash(Hash::from_str(bank_hash)?));
+    if !report.is_valid() {
+        anyhow::bail!("Saved update for slot {} does not verify against bank hash {}", update.slot, bank_hash);
+    }
+    Ok(VerifiedUpdate {
+        slot: update.slot,
+        bank_hash: update.root,
+        update,
+    })
+}
+
+fn print_account_state(slot: u64, account: &AccountInfo) {
+    println!("slot {}: verified new state lamports={} owner={} executable={} rent_epoch={} data_len={}",
+             slot, account.lamports, account.owner, account.executable, account.rent_epoch, account.data.len());
+}
+
+/// Verify every confirmed update from `start` onwards and print the state of `account`.
+/// Only returns on failure: a missed slot or an update without a proof for the account is an error.
+async fn watch_account(geyser_endpoint: String, account: Pubkey, start: Option<VerifiedUpdate>) -> anyhow::Result<()> {
+    let mut watcher = match &start {
+        Some(update) => match AccountWatcher::new(account, update) {
+            Some(watcher) => Some(watcher),
+            None => anyhow::bail!("Saved update for slot {} has no proof for {}", update.slot, account),
+        },
+        None => None,
+    };
+    if let Some(watcher) = &watcher {
+        print_account_state(watcher.modified_slot, &watcher.account);
+    } else {
+        println!("Waiting for a slot that modifies {}", account);
+    }
+
+    // Only the account's own proofs are verified, so a failing proof of another account doesn't
+    // end the watch. An update dropped for lacking a proof of the account breaks the chain of
+    // parent bank hashes, which the watcher reports as a gap.
+    let mut subscriber = ProofSubscriber::new(SubscriberConfig {
+        endpoint: geyser_endpoint,
+        pubkeys: [account].into_iter().collect(),
+        start_slot: watcher.as_ref().map(|watcher| watcher.verified_slot + 1),
+        ..SubscriberConfig::default()
+    });
+    while let Some(update) = subscriber.next_update().await {
+        let update = update?;
+        match watcher.as_mut() {
+            None => {
+                watcher = AccountWatcher::new(account, &update);
+                if let Some(watcher) = &watcher {
+                    print_account_state(watcher.modified_slot, &watcher.account);
+                }
+            }
+            Some(watcher) => match watcher.apply(&update)? {
+                WatchEvent::Changed { slot, account } => print_account_state(slot, &account),
+                WatchEvent::Unchanged { slot, since_slot } => {
+                    println!("slot {}: verified unchanged since slot {}", slot, since_slot)
+                }
+            },
+        }
+    }
+    anyhow::bail!("Proof stream ended")
+}
+
 fn main() {
     let cli = Cli::parse();
 
     match &cli.command {
-        Commands::CopyTransaction {copy_program,account_for_proof, signer, rpc_url, ws_url} => {
+        Commands::CopyTransaction {copy_program,account_for_proof, signer, rpc_url, ws_url, geyser_endpoint, save_proof, proof_format, pda_mode, offset, len} => {
 
             let account_for_proof = Pubkey::from_str(account_for_proof).unwrap();
             let signer_keypair = read_keypair_file(signer).unwrap();
-            let account_state_from_rpc = query_account(&account_for_proof);
 
-            let monitor_handle = std::thread::spawn( move || {
-                let rt = Runtime::new().unwrap(); // Create a new Tokio runtime
-                rt.block_on(monitor_and_verify_updates(&account_for_proof, &account_state_from_rpc)).unwrap(); // Run the async function `monitor_updates` to completion
+            let copy_client = CopyClient::new(rpc_url.to_string(), ws_url.to_string(), signer_keypair, Some(copy_program.as_str()), *pda_mode);
+            let range = offset.zip(*len).map(|(offset, len)| ByteRange { offset, len });
+            let (signature, slot) = match &range {
+                Some(range) => copy_client.send_copy_range(&account_for_proof, range).unwrap(),
+                None => copy_client.send_transaction(&account_for_proof).unwrap(),
+            };
+            println!("Copy transaction {} landed in slot {}", signature, slot);
+
+            // Read the source account at (or as close as possible after) the slot the copy was made in
+            let (rpc_context_slot, accounts_from_rpc) = query_accounts(rpc_url, &[account_for_proof], slot, range.as_ref()).unwrap();
+            let rpc_accounts: Vec<(Pubkey, Account)> = vec![account_for_proof].into_iter().zip(accounts_from_rpc).collect();
+
+            let rt = Runtime::new().unwrap(); // Create a new Tokio runtime
+            let (_, copy_pda, _) = copy_client.copy_pda(&account_for_proof);
+            rt.block_on(monitor_and_verify_updates(geyser_endpoint.clone(), copy_pda, slot, &rpc_accounts, range, rpc_context_slot, save_proof.clone().map(|path| (path, *proof_format)))).unwrap();
+        }
+        Commands::CopyHashes {copy_program, accounts_for_proof, signer, rpc_url, ws_url, geyser_endpoint, save_proof, proof_format, pda_mode} => {
+            let accounts_for_proof: Vec<Pubkey> = accounts_for_proof.iter().map(|account| Pubkey::from_str(account).unwrap()).collect();
+            let signer_keypair = read_keypair_file(signer).unwrap();
+            let copy_client = CopyClient::new(rpc_url.to_string(), ws_url.to_string(), signer_keypair, Some(copy_program.as_str()), *pda_mode);
+            let (signature, slot) = copy_client.send_copy_hashes(&accounts_for_proof).unwrap();
+            println!("Copy transaction {} for {} accounts landed in slot {}", signature, accounts_for_proof.len(), slot);
+
+            let (rpc_context_slot, accounts_from_rpc) = query_accounts(rpc_url, &accounts_for_proof, slot, None).unwrap();
+            let (_, copy_pda, _) = copy_client.copy_pda(&accounts_for_proof[0]);
+            let rpc_accounts: Vec<(Pubkey, Account)> = accounts_for_proof.into_iter().zip(accounts_from_rpc).collect();
+            let rt = Runtime::new().unwrap();
+            rt.block_on(monitor_and_verify_updates(geyser_endpoint.clone(), copy_pda, slot, &rpc_accounts, None, rpc_context_slot, save_proof.clone().map(|path| (path, *proof_format)))).unwrap();
+        }
+        Commands::Verify {proof_file, bank_hash, validators, min_votes} => {
+            match verify_proof_file(proof_file, bank_hash.as_ref(), validators, *min_votes) {
+                Ok(true) => {}
+                Ok(false) => std::process::exit(1),
+                Err(e) => {
+                    eprintln!("Error: {:?}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::Watch {account, geyser_endpoint, from_proof, bank_hash} => {
+            let account = Pubkey::from_str(account).unwrap();
+            let start = from_proof.as_ref().map(|proof_file| {
+                load_verified_update(proof_file, bank_hash.as_ref().unwrap()).unwrap()
             });
+            let rt = Runtime::new().unwrap();
+            if let Err(e) = rt.block_on(watch_account(geyser_endpoint.clone(), account, start)) {
+                eprintln!("Watch failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Commands::Touch {account, signer, rpc_url, ws_url, geyser_endpoint, lamports, save_proof, proof_format} => {
+            let account = Pubkey::from_str(account).unwrap();
+            let signer_keypair = read_keypair_file(signer).unwrap();
+            let copy_client = CopyClient::new(rpc_url.to_string(), ws_url.to_string(), signer_keypair, None, CopyPdaMode::Global);
+            let (signature, slot) = copy_client.touch(&account, *lamports).unwrap();
+            println!("Touch transaction {} landed in slot {}", signature, slot);
+
+            let rt = Runtime::new().unwrap();
+            rt.block_on(monitor_and_verify_touch(geyser_endpoint.clone(), account, signature, slot, save_proof.clone().map(|path| (path, *proof_format)))).unwrap();
+        }
+        Commands::Await {account, geyser_endpoint, auth_token, timeout, copy_program, signer, pda_mode, rpc_url, ws_url, save_proof, proof_format} => {
+            let account = Pubkey::from_str(account).unwrap();
+            let save_proof = save_proof.clone().map(|path| (path, *proof_format));
+            let config = SubscriberConfig {
+                endpoint: geyser_endpoint.clone(),
+                auth_token: auth_token.clone(),
+                ..SubscriberConfig::default()
+            };
+            println!("Waiting up to {}s for a confirmed slot that modifies {}", timeout, account);
+            let rt = Runtime::new().unwrap();
+            match rt.block_on(await_account_proof(config, account, Duration::from_secs(*timeout))) {
+                Ok(verified_update) => print_proven_account(&verified_update, &account, save_proof).unwrap(),
+                Err(e) if e.can_fall_back() && copy_program.is_some() => {
+                    println!("{}, copying the account instead", e);
+                    let signer_keypair = read_keypair_file(signer.as_ref().unwrap()).unwrap();
+                    let copy_client = CopyClient::new(rpc_url.to_string(), ws_url.to_string(), signer_keypair, copy_program.as_deref(), *pda_mode);
+                    let (signature, slot) = copy_client.send_transaction(&account).unwrap();
+                    println!("Copy transaction {} landed in slot {}", signature, slot);
 
-            let copy_client = CopyClient::new(rpc_url.to_string(), ws_url.to_string(), signer_keypair, copy_program);
-            copy_client.send_transaction(&account_for_proof).unwrap();
-            monitor_handle.join().unwrap();
+                    let (rpc_context_slot, accounts_from_rpc) = query_accounts(rpc_url, &[account], slot, None).unwrap();
+                    let rpc_accounts: Vec<(Pubkey, Account)> = vec![account].into_iter().zip(accounts_from_rpc).collect();
+                    let (_, copy_pda, _) = copy_client.copy_pda(&account);
+                    rt.block_on(monitor_and_verify_updates(geyser_endpoint.clone(), copy_pda, slot, &rpc_accounts, None, rpc_context_slot, save_proof)).unwrap();
+                }
+                Err(e) => {
+                    eprintln!("Await failed: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::CloseCopyAccount {copy_program, signer, rpc_url, ws_url, pda_mode, source} => {
+            if matches!(pda_mode, CopyPdaMode::Sharded(_)) && source.is_none() {
+                eprintln!("--source is required to pick the shard to close");
+                std::process::exit(1);
+            }
+            let source = source.as_ref().map_or(Pubkey::default(), |source| Pubkey::from_str(source).unwrap());
+            let signer_keypair = read_keypair_file(signer).unwrap();
+            let copy_client = CopyClient::new(rpc_url.to_string(), ws_url.to_string(), signer_keypair, Some(copy_program.as_str()), *pda_mode);
+            let (_, copy_pda, _) = copy_client.copy_pda(&source);
+            let signature = copy_client.close_copy_account(&source).unwrap();
+            println!("Closed copy account {} in transaction {}", copy_pda, signature);
         }
-        Commands::CopyPda {copy_program} => {
+        Commands::MigrateCopyAccount {copy_program, signer, rpc_url, ws_url, pda_mode, source} => {
+            if matches!(pda_mode, CopyPdaMode::Sharded(_)) && source.is_none() {
+                eprintln!("--source is required to pick the shard to migrate");
+                std::process::exit(1);
+            }
+            let source = source.as_ref().map_or(Pubkey::default(), |source| Pubkey::from_str(source).unwrap());
+            let signer_keypair = read_keypair_file(signer).unwrap();
+            let copy_client = CopyClient::new(rpc_url.to_string(), ws_url.to_string(), signer_keypair, Some(copy_program.as_str()), *pda_mode);
+            let (_, copy_pda, _) = copy_client.copy_pda(&source);
+            let signature = copy_client.migrate_copy_account(&source).unwrap();
+            println!("Migrated copy account {} in transaction {}", copy_pda, signature);
+        }
+        Commands::CopyPda {copy_program, pda_mode, creator} => {
             let copy_program_pubkey = Pubkey::from_str(copy_program).unwrap();
-            let (copy_pda, _) =
-                Pubkey::find_program_address(&[PREFIX.as_bytes()], &copy_program_pubkey);
-            println!("account: {}",copy_pda);
+            let creator = creator.as_ref().map_or(Pubkey::default(), |creator| Pubkey::from_str(creator).unwrap());
+            for (scope, copy_pda) in pda_mode.family(&creator, &copy_program_pubkey) {
+                match scope {
+                    CopyScope::Shard { index } => println!("shard {}: {}", index, copy_pda),
+                    _ => println!("account: {}",copy_pda),
+                }
+            }
+        }
+        Commands::CopyEvents {copy_pda, slot, rpc_url, copy_program} => {
+            let copy_pda = Pubkey::from_str(copy_pda).unwrap();
+            let copy_program = Pubkey::from_str(copy_program).unwrap();
+            let events = query_copy_events(rpc_url, *slot, &copy_program).unwrap();
+            for event in events.iter().filter(|event| event.copy_account == copy_pda) {
+                println!("{}: {} {}", event.position, event.source, hex::encode(event.hash));
+            }
+            let preimages = match assemble_copy_preimages(&[(0, events)], &copy_pda, *slot) {
+                Ok(preimages) => preimages,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            };
+            println!("digest: {}", hex::encode(preimages.digest().unwrap()));
+
+            // the copy account only holds the digest of the last slot it was copied to
+            let client = RpcClient::new(rpc_url.to_string());
+            let copy_pda_account = client.get_account_with_commitment(&copy_pda, CommitmentConfig::confirmed()).unwrap().value;
+            if let Some(account) = copy_pda_account {
+                let copy_account = CopyAccount::try_deserialize(&mut account.data.as_slice()).unwrap();
+                if copy_account.slot == *slot {
+                    match check_copy_digest(&preimages, &copy_account, *slot) {
+                        Ok(()) => println!("digest matches the copy account"),
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+            }
         }
     }
 }
@@ -0,0 +1,60 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use account_proof_geyser::types::Update;
+use borsh::{BorshDeserialize, BorshSerialize};
+
+/// On-disk encoding of a saved `Update`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofFormat {
+    Borsh,
+    Json,
+}
+
+impl FromStr for ProofFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "borsh" => Ok(ProofFormat::Borsh),
+            "json" => Ok(ProofFormat::Json),
+            _ => Err(format!("unknown proof format {} (expected borsh or json)", s)),
+        }
+    }
+}
+
+impl fmt::Display for ProofFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProofFormat::Borsh => write!(f, "borsh"),
+            ProofFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+pub fn save_update<P: AsRef<Path>>(path: P, update: &Update, format: ProofFormat) -> anyhow::Result<()> {
+    let bytes = match format {
+        ProofFormat::Borsh => update.try_to_vec()?,
+        ProofFormat::Json => serde_json::to_vec_pretty(update)?,
+    };
+    fs::write(path, bytes)?;
+    Ok(())
+}
+
+/// Load an `Update` saved by `save_update` in either format
+pub fn load_update<P: AsRef<Path>>(path: P) -> anyhow::Result<Update> {
+    let bytes = fs::read(path)?;
+    // Borsh requires the whole file to be consumed, so JSON text is never mistaken for it
+    match Update::try_from_slice(&bytes) {
+        Ok(update) => Ok(update),
+        Err(borsh_error) => serde_json::from_slice(&bytes).map_err(|json_error| {
+            anyhow::anyhow!(
+                "not a borsh ({}) or JSON ({}) encoded update",
+                borsh_error,
+                json_error
+            )
+        }),
+    }
+}
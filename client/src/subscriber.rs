@@ -0,0 +1,702 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use account_proof_geyser::copy_events::verify_copy_preimages;
+use account_proof_geyser::poh::verify_transaction_proof;
+use account_proof_geyser::tls::load_certs;
+use account_proof_geyser::types::{
+    AccountDeltaNonInclusionProof, AccountDeltaProof, AccountInfo, BankHashComponents,
+    CopyPreimages, TransactionProof, Update,
+};
+use account_proof_geyser::utils::{
+    verify_leaves_against_bankhash, verify_non_inclusion_against_bankhash,
+};
+use account_proof_geyser::wire::{read_frame, write_frame, StreamRequest, StreamResponse};
+use backoff::ExponentialBackoff;
+use futures::Stream;
+use solana_sdk::hash::Hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore, ServerName};
+use tokio_rustls::TlsConnector;
+
+pub const DEFAULT_GEYSER_ENDPOINT: &str = "127.0.0.1:10000";
+
+#[derive(Debug, Clone)]
+pub struct SubscriberConfig {
+    /// `host:port` of the plugin's proof stream
+    pub endpoint: String,
+    /// Connect over TLS with these settings. Plaintext when absent.
+    pub tls: Option<SubscriberTlsConfig>,
+    /// API key presented during the stream handshake
+    pub auth_token: Option<String>,
+    /// Only yield proofs for these accounts. Every account is yielded when empty.
+    pub pubkeys: HashSet<Pubkey>,
+    /// Ask the plugin to prove `pubkeys` for as long as the subscription lasts, for accounts that
+    /// aren't in its `account_list`
+    pub register: bool,
+    /// First slot to yield. Earlier slots are replayed by the plugin if it still has them.
+    pub start_slot: Option<u64>,
+    /// Last slot to yield. The stream ends once it has been reached.
+    pub end_slot: Option<u64>,
+    /// Give up reconnecting after this long. Retries forever when `None`.
+    pub max_reconnect_time: Option<Duration>,
+}
+
+impl Default for SubscriberConfig {
+    fn default() -> Self {
+        SubscriberConfig {
+            endpoint: DEFAULT_GEYSER_ENDPOINT.to_string(),
+            tls: None,
+            auth_token: None,
+            pubkeys: HashSet::new(),
+            register: false,
+            start_slot: None,
+            end_slot: None,
+            max_reconnect_time: Some(Duration::from_secs(60)),
+        }
+    }
+}
+
+/// How to check the certificate of a plugin serving its proof stream over TLS
+#[derive(Debug, Clone)]
+pub struct SubscriberTlsConfig {
+    /// PEM file with the CA certificates the plugin's certificate must chain to
+    pub ca_cert_path: String,
+    /// Name the plugin's certificate must be valid for
+    pub domain: String,
+}
+
+#[derive(Debug)]
+pub enum SubscriberError {
+    /// The plugin could not be reached or the connection failed
+    Io(io::Error),
+    /// The plugin closed the connection during the handshake
+    ConnectionClosed,
+    /// The CA certificates or the domain in `SubscriberTlsConfig` could not be used
+    InvalidTlsConfig(String),
+    /// The plugin refused the subscription, e.g. because of an invalid API key
+    Rejected(String),
+    /// A proof in the update for `slot` did not verify against its bank hash
+    Verification {
+        slot: u64,
+        pubkey: Pubkey,
+        reason: String,
+    },
+    /// A transaction proof in the update for `slot` did not verify against its blockhash
+    TransactionVerification {
+        slot: u64,
+        signature: Signature,
+        reason: String,
+    },
+}
+
+impl fmt::Display for SubscriberError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubscriberError::Io(e) => write!(f, "proof stream connection failed: {}", e),
+            SubscriberError::ConnectionClosed => write!(f, "proof stream closed by the plugin"),
+            SubscriberError::InvalidTlsConfig(reason) => write!(f, "invalid TLS config: {}", reason),
+            SubscriberError::Rejected(reason) => write!(f, "subscription rejected: {}", reason),
+            SubscriberError::Verification {
+                slot,
+                pubkey,
+                reason,
+            } => write!(
+                f,
+                "proof for {} in slot {} failed verification: {}",
+                pubkey, slot, reason
+            ),
+            SubscriberError::TransactionVerification {
+                slot,
+                signature,
+                reason,
+            } => write!(
+                f,
+                "proof for transaction {} in slot {} failed verification: {}",
+                signature, slot, reason
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SubscriberError {}
+
+impl From<io::Error> for SubscriberError {
+    fn from(e: io::Error) -> Self {
+        SubscriberError::Io(e)
+    }
+}
+
+/// An update whose proofs have all been verified against its bank hash
+#[derive(Debug, Clone)]
+pub struct VerifiedUpdate {
+    pub slot: u64,
+    pub bank_hash: Hash,
+    /// The update as received, keeping only the proofs for the subscribed accounts
+    pub update: Update,
+}
+
+impl VerifiedUpdate {
+    /// Proofs for the subscribed accounts modified in this slot
+    pub fn proofs(&self) -> &[AccountDeltaProof] {
+        &self.update.proof.proofs
+    }
+
+    pub fn account(&self, pubkey: &Pubkey) -> Option<&AccountInfo> {
+        self.proofs()
+            .iter()
+            .find(|proof| &proof.0 == pubkey)
+            .map(|proof| &proof.1 .0.account)
+    }
+
+    /// Proofs for the subscribed accounts that were not modified in this slot
+    pub fn non_inclusion_proofs(&self) -> &[AccountDeltaNonInclusionProof] {
+        &self.update.proof.non_inclusion_proofs
+    }
+
+    /// Whether the update proves that `pubkey` was not modified in this slot
+    pub fn is_unchanged(&self, pubkey: &Pubkey) -> bool {
+        self.non_inclusion_proofs()
+            .iter()
+            .any(|proof| &proof.pubkey == pubkey)
+    }
+
+    /// Hashes accumulated into the copy PDA `pubkey` during this slot, checked against its digest
+    pub fn copy_preimages(&self, pubkey: &Pubkey) -> Option<&CopyPreimages> {
+        self.update
+            .copy_preimages
+            .iter()
+            .find(|preimages| &preimages.copy_account == pubkey)
+    }
+
+    /// Proof that the transaction signed with `signature` is in the block, checked against its
+    /// blockhash
+    pub fn transaction_proof(&self, signature: &Signature) -> Option<&TransactionProof> {
+        self.update
+            .transaction_proofs
+            .iter()
+            .find(|proof| proof.signature == signature.as_ref())
+    }
+}
+
+/// A plaintext or TLS connection to the proof stream
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send + Sync {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + Sync> Connection for T {}
+
+/// Build a TLS connector trusting the CA certificates in `tls` and the name to check the plugin's
+/// certificate against
+fn tls_connector(tls: &SubscriberTlsConfig) -> Result<(TlsConnector, ServerName), SubscriberError> {
+    let invalid = |reason: String| SubscriberError::InvalidTlsConfig(reason);
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(&tls.ca_cert_path).map_err(|e| invalid(e.to_string()))? {
+        roots.add(&cert).map_err(|e| invalid(e.to_string()))?;
+    }
+    let domain = ServerName::try_from(tls.domain.as_str())
+        .map_err(|_| invalid(format!("invalid domain {}", tls.domain)))?;
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok((TlsConnector::from(Arc::new(config)), domain))
+}
+
+/// Subscribes to the proof stream of an account proof geyser plugin and verifies every update it
+/// receives. Reconnects with exponential backoff, resuming after the last slot it has seen.
+pub struct ProofSubscriber {
+    config: SubscriberConfig,
+    connection: Option<Box<dyn Connection>>,
+    last_slot: Option<u64>,
+    finished: bool,
+}
+
+impl ProofSubscriber {
+    pub fn new(config: SubscriberConfig) -> Self {
+        ProofSubscriber {
+            config,
+            connection: None,
+            last_slot: None,
+            finished: false,
+        }
+    }
+
+    async fn connect(&self) -> Result<Box<dyn Connection>, SubscriberError> {
+        // The plugin replays every update after `resume_from_slot`
+        let resume_from_slot = self.last_slot.or_else(|| {
+            self.config
+                .start_slot
+                .and_then(|start_slot| start_slot.checked_sub(1))
+        });
+        let tcp = TcpStream::connect(&self.config.endpoint).await?;
+        let mut stream: Box<dyn Connection> = match &self.config.tls {
+            Some(tls) => {
+                let (connector, domain) = tls_connector(tls)?;
+                Box::new(connector.connect(domain, tcp).await?)
+            }
+            None => Box::new(tcp),
+        };
+        let request = StreamRequest {
+            resume_from_slot,
+            auth_token: self.config.auth_token.clone(),
+            watch_accounts: if self.config.register {
+                self.config.pubkeys.iter().copied().collect()
+            } else {
+                vec![]
+            },
+        };
+        write_frame(&mut stream, &request).await?;
+        match read_frame(&mut stream).await? {
+            Some(StreamResponse::Accepted) => Ok(stream),
+            Some(StreamResponse::Rejected(reason)) => Err(SubscriberError::Rejected(reason)),
+            None => Err(SubscriberError::ConnectionClosed),
+        }
+    }
+
+    async fn reconnect(&self) -> Result<Box<dyn Connection>, SubscriberError> {
+        let backoff = ExponentialBackoff {
+            max_elapsed_time: self.config.max_reconnect_time,
+            ..ExponentialBackoff::default()
+        };
+        backoff::future::retry(backoff, || async {
+            self.connect().await.map_err(|e| match e {
+                SubscriberError::Rejected(_) | SubscriberError::InvalidTlsConfig(_) => {
+                    backoff::Error::permanent(e)
+                }
+                e => backoff::Error::transient(e),
+            })
+        })
+        .await
+    }
+
+    /// Apply the slot range and pubkey filters to an update and verify what is left
+    fn accept(&mut self, mut update: Update) -> Option<Result<VerifiedUpdate, SubscriberError>> {
+        let slot = update.slot;
+        // Updates replayed after a reconnect may overlap with what was already seen
+        if matches!(self.last_slot, Some(last_slot) if slot <= last_slot) {
+            return None;
+        }
+        self.last_slot = Some(slot);
+
+        if let Some(end_slot) = self.config.end_slot {
+            if slot >= end_slot {
+                self.finished = true;
+            }
+            if slot > end_slot {
+                return None;
+            }
+        }
+        if matches!(self.config.start_slot, Some(start_slot) if slot < start_slot) {
+            return None;
+        }
+
+        let pubkeys = &self.config.pubkeys;
+        update
+            .proof
+            .proofs
+            .retain(|p| pubkeys.is_empty() || pubkeys.contains(&p.0));
+        update
+            .proof
+            .non_inclusion_proofs
+            .retain(|p| pubkeys.is_empty() || pubkeys.contains(&p.pubkey));
+        if update.proof.proofs.is_empty() && update.proof.non_inclusion_proofs.is_empty() {
+            return None;
+        }
+        let proven: HashSet<Pubkey> = update.proof.proofs.iter().map(|p| p.0).collect();
+        update
+            .copy_preimages
+            .retain(|preimages| proven.contains(&preimages.copy_account));
+
+        let proof = &update.proof;
+        for p in &proof.proofs {
+            if let Err(e) = verify_leaves_against_bankhash(
+                p,
+                update.root,
+                proof.num_sigs,
+                proof.account_delta_root,
+                proof.parent_bankhash,
+                proof.blockhash,
+            ) {
+                return Some(Err(SubscriberError::Verification {
+                    slot,
+                    pubkey: p.0,
+                    reason: e.to_string(),
+                }));
+            }
+        }
+        for p in &proof.non_inclusion_proofs {
+            if let Err(e) = verify_non_inclusion_against_bankhash(
+                p,
+                update.root,
+                proof.num_sigs,
+                proof.account_delta_root,
+                proof.parent_bankhash,
+                proof.blockhash,
+            ) {
+                return Some(Err(SubscriberError::Verification {
+                    slot,
+                    pubkey: p.pubkey,
+                    reason: e.to_string(),
+                }));
+            }
+        }
+        // The preimages aren't covered by the bank hash, only the copy account they open is
+        for preimages in &update.copy_preimages {
+            let account = proof
+                .proofs
+                .iter()
+                .find(|p| p.0 == preimages.copy_account)
+                .map(|p| &p.1 .0.account);
+            if let Some(Err(e)) = account.map(|account| verify_copy_preimages(preimages, account, slot)) {
+                return Some(Err(SubscriberError::Verification {
+                    slot,
+                    pubkey: preimages.copy_account,
+                    reason: e.to_string(),
+                }));
+            }
+        }
+        // The blockhash is only committed to through the bank hash
+        if !update.transaction_proofs.is_empty()
+            && BankHashComponents::from(proof).bank_hash() != update.root
+        {
+            return Some(Err(SubscriberError::TransactionVerification {
+                slot,
+                signature: Signature::default(),
+                reason: "bank hash does not match its components".to_string(),
+            }));
+        }
+        for transaction_proof in &update.transaction_proofs {
+            if let Err(e) = verify_transaction_proof(transaction_proof, proof.blockhash) {
+                return Some(Err(SubscriberError::TransactionVerification {
+                    slot,
+                    signature: Signature::try_from(transaction_proof.signature.as_slice())
+                        .unwrap_or_default(),
+                    reason: e.to_string(),
+                }));
+            }
+        }
+
+        Some(Ok(VerifiedUpdate {
+            slot,
+            bank_hash: update.root,
+            update,
+        }))
+    }
+
+    /// Wait for the next update matching the filters. Returns `None` once the end of the slot
+    /// range has been reached or after a permanent error has been returned.
+    pub async fn next_update(&mut self) -> Option<Result<VerifiedUpdate, SubscriberError>> {
+        while !self.finished {
+            let connection = match self.connection.as_mut() {
+                Some(connection) => connection,
+                None => match self.reconnect().await {
+                    Ok(connection) => self.connection.insert(connection),
+                    Err(e) => {
+                        self.finished = true;
+                        return Some(Err(e));
+                    }
+                },
+            };
+            match read_frame::<_, Update>(connection).await {
+                Ok(Some(update)) => {
+                    if let Some(result) = self.accept(update) {
+                        return Some(result);
+                    }
+                }
+                // Dropped by the plugin (e.g. for lagging) or a network error, resume from the last slot
+                Ok(None) | Err(_) => self.connection = None,
+            }
+        }
+        None
+    }
+
+    /// Turn the subscriber into a `Stream` of verified updates
+    pub fn into_stream(self) -> impl Stream<Item = Result<VerifiedUpdate, SubscriberError>> {
+        futures::stream::unfold(self, |mut subscriber| async move {
+            subscriber
+                .next_update()
+                .await
+                .map(|result| (result, subscriber))
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use account_proof_geyser::auth::AccessControl;
+    use account_proof_geyser::config::TlsConfig;
+    use account_proof_geyser::metrics::Metrics;
+    use account_proof_geyser::replay::ReplayBuffer;
+    use account_proof_geyser::server::{ProofServer, StreamContext};
+    use account_proof_geyser::tls::load_tls_acceptor;
+    use account_proof_geyser::types::BankHashProof;
+    use account_proof_geyser::utils::{
+        assemble_account_delta_inclusion_proof, calculate_root_and_proofs, hash_solana_account,
+    };
+    use futures::StreamExt;
+    use solana_sdk::hash::hashv;
+    use tokio::net::TcpListener;
+    use tokio::sync::broadcast;
+
+    use super::*;
+
+    pub(crate) fn signed_update(slot: u64, pubkeys: &[Pubkey]) -> Update {
+        let mut account_data = HashMap::new();
+        for (i, pubkey) in pubkeys.iter().enumerate() {
+            let account = AccountInfo {
+                pubkey: *pubkey,
+                lamports: 1 + i as u64,
+                slot,
+                ..AccountInfo::default()
+            };
+            let hash = Hash::from(hash_solana_account(
+                account.lamports,
+                account.owner.as_ref(),
+                account.executable,
+                account.rent_epoch,
+                &account.data,
+                pubkey.as_ref(),
+            ));
+            account_data.insert(*pubkey, (1, hash, account));
+        }
+        let mut leaves: Vec<(Pubkey, Hash)> =
+            account_data.iter().map(|(k, (_, h, _))| (*k, *h)).collect();
+        let (account_delta_root, account_proofs) = calculate_root_and_proofs(&mut leaves, pubkeys);
+        let proofs =
+            assemble_account_delta_inclusion_proof(&account_data, &account_proofs, pubkeys).unwrap();
+
+        let parent_bankhash = Hash::new_unique();
+        let blockhash = Hash::new_unique();
+        let num_sigs: u64 = 2;
+        Update {
+            slot,
+            root: hashv(&[
+                parent_bankhash.as_ref(),
+                account_delta_root.as_ref(),
+                &num_sigs.to_le_bytes(),
+                blockhash.as_ref(),
+            ]),
+            proof: BankHashProof {
+                proofs,
+                non_inclusion_proofs: vec![],
+                num_sigs,
+                account_delta_root,
+                parent_bankhash,
+                blockhash,
+            },
+            votes: vec![],
+            copy_preimages: vec![],
+            transaction_proofs: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_filters_and_verifies_updates() {
+        let watched = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+
+        let mut replay_buffer = ReplayBuffer::new(16);
+        for slot in 1..=6 {
+            let pubkeys = if slot % 2 == 0 {
+                vec![watched, other]
+            } else {
+                vec![other]
+            };
+            let mut update = signed_update(slot, &pubkeys);
+            if slot == 4 {
+                update.proof.num_sigs += 1;
+            }
+            replay_buffer.push(update).unwrap();
+        }
+
+        let (tx, _rx) = broadcast::channel(16);
+        let server = ProofServer::new(
+            tx,
+            StreamContext {
+                replay_buffer: Arc::new(Mutex::new(replay_buffer)),
+                archive: None,
+                access_control: AccessControl::new(&[], 1),
+                metrics: Arc::new(Metrics::new()),
+                geyser_sender: None,
+            },
+            None,
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = listener.local_addr().unwrap().to_string();
+        tokio::spawn(server.serve(listener));
+
+        let subscriber = ProofSubscriber::new(SubscriberConfig {
+            endpoint,
+            pubkeys: [watched].into_iter().collect(),
+            start_slot: Some(2),
+            end_slot: Some(5),
+            ..SubscriberConfig::default()
+        });
+        let results: Vec<_> = subscriber.into_stream().collect().await;
+
+        assert_eq!(results.len(), 2);
+        let first = results[0].as_ref().unwrap();
+        assert_eq!(first.slot, 2);
+        assert_eq!(first.proofs().len(), 1);
+        assert_eq!(first.account(&watched).unwrap().lamports, 1);
+        assert!(matches!(
+            results[1],
+            Err(SubscriberError::Verification { slot: 4, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_filtered_stream_ignores_other_accounts() {
+        let watched = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        let mut update = signed_update(1, &[watched, other]);
+        for proof in update.proof.proofs.iter_mut().filter(|proof| proof.0 == other) {
+            proof.1 .0.account.lamports += 1;
+        }
+        let mut replay_buffer = ReplayBuffer::new(4);
+        replay_buffer.push(update).unwrap();
+
+        let (tx, _rx) = broadcast::channel(16);
+        let server = ProofServer::new(
+            tx,
+            StreamContext {
+                replay_buffer: Arc::new(Mutex::new(replay_buffer)),
+                archive: None,
+                access_control: AccessControl::new(&[], 2),
+                metrics: Arc::new(Metrics::new()),
+                geyser_sender: None,
+            },
+            None,
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = listener.local_addr().unwrap().to_string();
+        tokio::spawn(server.serve(listener));
+
+        let config = |pubkeys: &[Pubkey]| SubscriberConfig {
+            endpoint: endpoint.clone(),
+            pubkeys: pubkeys.iter().copied().collect(),
+            start_slot: Some(1),
+            end_slot: Some(1),
+            ..SubscriberConfig::default()
+        };
+        // the tampered proof of the other account only fails a subscriber that asked for it
+        let mut subscriber = ProofSubscriber::new(config(&[watched]));
+        let update = subscriber.next_update().await.unwrap().unwrap();
+        assert_eq!(update.account(&watched).unwrap().lamports, 1);
+        assert!(update.account(&other).is_none());
+
+        let mut subscriber = ProofSubscriber::new(config(&[]));
+        assert!(matches!(
+            subscriber.next_update().await,
+            Some(Err(SubscriberError::Verification { .. }))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_stream_over_tls() {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let dir = std::env::temp_dir().join(format!(
+            "subscriber_tls_{}_{}",
+            std::process::id(),
+            rand::random::<u64>()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cert_path = dir.join("cert.pem").to_string_lossy().to_string();
+        let key_path = dir.join("key.pem").to_string_lossy().to_string();
+        std::fs::write(&cert_path, cert.serialize_pem().unwrap()).unwrap();
+        std::fs::write(&key_path, cert.serialize_private_key_pem()).unwrap();
+
+        let watched = Pubkey::new_unique();
+        let mut replay_buffer = ReplayBuffer::new(4);
+        replay_buffer
+            .push(signed_update(1, &[watched, Pubkey::new_unique()]))
+            .unwrap();
+        let (tx, _rx) = broadcast::channel(16);
+        let server = ProofServer::new(
+            tx,
+            StreamContext {
+                replay_buffer: Arc::new(Mutex::new(replay_buffer)),
+                archive: None,
+                access_control: AccessControl::new(&[], 1),
+                metrics: Arc::new(Metrics::new()),
+                geyser_sender: None,
+            },
+            Some(
+                load_tls_acceptor(&TlsConfig {
+                    cert_path: cert_path.clone(),
+                    key_path,
+                })
+                .unwrap(),
+            ),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = listener.local_addr().unwrap().to_string();
+        tokio::spawn(server.serve(listener));
+
+        let config = |domain: &str| SubscriberConfig {
+            endpoint: endpoint.clone(),
+            tls: Some(SubscriberTlsConfig {
+                ca_cert_path: cert_path.clone(),
+                domain: domain.to_string(),
+            }),
+            start_slot: Some(1),
+            end_slot: Some(1),
+            max_reconnect_time: Some(Duration::from_secs(1)),
+            ..SubscriberConfig::default()
+        };
+        let mut subscriber = ProofSubscriber::new(config("localhost"));
+        let update = subscriber.next_update().await.unwrap().unwrap();
+        assert_eq!(update.account(&watched).unwrap().lamports, 1);
+
+        // the certificate isn't valid for another name
+        let mut subscriber = ProofSubscriber::new(config("example.com"));
+        assert!(matches!(
+            subscriber.next_update().await,
+            Some(Err(SubscriberError::Io(_)))
+        ));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rejection_ends_stream() {
+        let (tx, _rx) = broadcast::channel(16);
+        let api_keys = vec![account_proof_geyser::config::ApiKeyConfig {
+            name: "client".to_string(),
+            key: "secret".to_string(),
+            max_subscriptions: None,
+        }];
+        let server = ProofServer::new(
+            tx,
+            StreamContext {
+                replay_buffer: Arc::new(Mutex::new(ReplayBuffer::new(0))),
+                archive: None,
+                access_control: AccessControl::new(&api_keys, 1),
+                metrics: Arc::new(Metrics::new()),
+                geyser_sender: None,
+            },
+            None,
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = listener.local_addr().unwrap().to_string();
+        tokio::spawn(server.serve(listener));
+
+        let mut subscriber = ProofSubscriber::new(SubscriberConfig {
+            endpoint,
+            auth_token: Some("wrong".to_string()),
+            ..SubscriberConfig::default()
+        });
+        assert!(matches!(
+            subscriber.next_update().await,
+            Some(Err(SubscriberError::Rejected(_)))
+        ));
+        assert!(subscriber.next_update().await.is_none());
+    }
+}
@@ -0,0 +1,382 @@
+use std::collections::{HashMap, HashSet};
+
+use account_proof_geyser::poh::verify_transaction_proof;
+use account_proof_geyser::types::{BankHashComponents, Update, VoteProof};
+use account_proof_geyser::utils::{verify_leaves_against_bankhash, verify_vote};
+use solana_sdk::hash::Hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+
+/// What a saved update's bank hash is checked against
+#[derive(Debug, Clone)]
+pub enum TrustedRoot {
+    /// A bank hash obtained from a trusted source
+    BankHash(Hash),
+    /// At least `min_votes` of these vote accounts must have voted for the bank hash, each through
+    /// the authorized voter it maps to
+    Validators {
+        validators: HashMap<Pubkey, Pubkey>,
+        min_votes: usize,
+    },
+}
+
+impl TrustedRoot {
+    /// Trust a validator set once two thirds of it has voted
+    pub fn supermajority_of(validators: HashMap<Pubkey, Pubkey>) -> Self {
+        let min_votes = (validators.len() * 2 + 2) / 3;
+        TrustedRoot::Validators {
+            validators,
+            min_votes,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AccountReport {
+    pub pubkey: Pubkey,
+    pub lamports: u64,
+    pub owner: Pubkey,
+    pub data_len: usize,
+    /// Why the proof failed verification, if it did
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TransactionReport {
+    pub signature: Signature,
+    pub entry_index: u64,
+    /// Why the proof failed verification, if it did
+    pub error: Option<String>,
+}
+
+/// Outcome of checking a saved update against a `TrustedRoot`
+#[derive(Debug, Clone)]
+pub struct VerificationReport {
+    pub slot: u64,
+    pub bank_hash: Hash,
+    /// Why the bank hash is trusted, or why it is not
+    pub bank_hash_trust: Result<String, String>,
+    pub accounts: Vec<AccountReport>,
+    pub transactions: Vec<TransactionReport>,
+}
+
+impl VerificationReport {
+    pub fn is_valid(&self) -> bool {
+        self.bank_hash_trust.is_ok()
+            && !self.accounts.is_empty()
+            && self.accounts.iter().all(|account| account.error.is_none())
+            && self.transactions.iter().all(|transaction| transaction.error.is_none())
+    }
+}
+
+/// Vote accounts among `validators` with a valid vote for `bank_hash` as the bank hash of `slot`,
+/// signed by the authorized voter `validators` maps them to
+pub(crate) fn trusted_voters(
+    votes: &[VoteProof],
+    slot: u64,
+    bank_hash: Hash,
+    validators: &HashMap<Pubkey, Pubkey>,
+) -> HashSet<Pubkey> {
+    votes
+        .iter()
+        .filter_map(|vote| verify_vote(vote).ok())
+        .filter(|vote| vote.slot == slot && vote.bank_hash == bank_hash)
+        .filter(|vote| validators.get(&vote.vote_account) == Some(&vote.authority))
+        .map(|vote| vote.vote_account)
+        .collect()
+}
+
+fn check_bank_hash(update: &Update, trusted_root: &TrustedRoot) -> Result<String, String> {
+    match trusted_root {
+        TrustedRoot::BankHash(bank_hash) => {
+            if *bank_hash == update.root {
+                Ok("matches the trusted bank hash".to_string())
+            } else {
+                Err(format!("does not match the trusted bank hash {}", bank_hash))
+            }
+        }
+        TrustedRoot::Validators {
+            validators,
+            min_votes,
+        } => {
+            let voters = trusted_voters(&update.votes, update.slot, update.root, validators);
+            let summary = format!(
+                "{} of {} trusted validators voted for it ({} required)",
+                voters.len(),
+                validators.len(),
+                min_votes
+            );
+            if voters.len() >= *min_votes {
+                Ok(summary)
+            } else {
+                Err(summary)
+            }
+        }
+    }
+}
+
+/// Check the bank hash of `update` against `trusted_root`, every account proof against the bank
+/// hash and every transaction proof against the blockhash it commits to
+pub fn verify_update(update: &Update, trusted_root: &TrustedRoot) -> VerificationReport {
+    let proof = &update.proof;
+    let accounts = proof
+        .proofs
+        .iter()
+        .map(|account_proof| {
+            let account = &account_proof.1 .0.account;
+            AccountReport {
+                pubkey: account_proof.0,
+                lamports: account.lamports,
+                owner: account.owner,
+                data_len: account.data.len(),
+                error: verify_leaves_against_bankhash(
+                    account_proof,
+                    update.root,
+                    proof.num_sigs,
+                    proof.account_delta_root,
+                    proof.parent_bankhash,
+                    proof.blockhash,
+                )
+                .err()
+                .map(|e| e.to_string()),
+            }
+        })
+        .collect();
+
+    let blockhash_committed = BankHashComponents::from(proof).bank_hash() == update.root;
+    let transactions = update
+        .transaction_proofs
+        .iter()
+        .map(|transaction_proof| TransactionReport {
+            signature: Signature::try_from(transaction_proof.signature.as_slice())
+                .unwrap_or_default(),
+            entry_index: transaction_proof.entry_index,
+            error: if blockhash_committed {
+                verify_transaction_proof(transaction_proof, proof.blockhash)
+                    .err()
+                    .map(|e| e.to_string())
+            } else {
+                Some("bank hash does not match its components".to_string())
+            },
+        })
+        .collect();
+
+    VerificationReport {
+        slot: update.slot,
+        bank_hash: update.root,
+        bank_hash_trust: check_bank_hash(update, trusted_root),
+        accounts,
+        transactions,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use account_proof_geyser::poh::{assemble_transaction_proofs, hash_signatures, next_entry_hash};
+    use account_proof_geyser::types::{
+        AccountDeltaProof, AccountInfo, BankHashProof, Data, EntryInfo, Proof, SlotEntries,
+        TransactionInfo,
+    };
+    use account_proof_geyser::utils::hash_solana_account;
+    use solana_sdk::hash::hashv;
+    use solana_sdk::message::legacy::Message;
+    use solana_sdk::signature::{Keypair, Signer};
+    use solana_sdk::vote::instruction::compact_update_vote_state;
+    use solana_sdk::vote::state::VoteStateUpdate;
+
+    use super::*;
+    use crate::proof_file::{load_update, save_update, ProofFormat};
+
+    /// An update with a single account that is the only leaf of the accounts delta tree
+    fn single_account_update(slot: u64) -> Update {
+        account_update(AccountInfo {
+            pubkey: Pubkey::new_unique(),
+            lamports: 10,
+            data: vec![1, 2, 3],
+            slot,
+            ..AccountInfo::default()
+        })
+    }
+
+    /// An update for `account.slot` in which `account` is the only leaf of the accounts delta tree
+    pub(crate) fn account_update(account: AccountInfo) -> Update {
+        let (slot, pubkey) = (account.slot, account.pubkey);
+        let hash = Hash::from(hash_solana_account(
+            account.lamports,
+            account.owner.as_ref(),
+            account.executable,
+            account.rent_epoch,
+            &account.data,
+            pubkey.as_ref(),
+        ));
+        let parent_bankhash = Hash::new_unique();
+        let blockhash = Hash::new_unique();
+        let num_sigs: u64 = 1;
+        Update {
+            slot,
+            root: hashv(&[
+                parent_bankhash.as_ref(),
+                hash.as_ref(),
+                &num_sigs.to_le_bytes(),
+                blockhash.as_ref(),
+            ]),
+            proof: BankHashProof {
+                proofs: vec![AccountDeltaProof(
+                    pubkey,
+                    (
+                        Data {
+                            pubkey,
+                            hash,
+                            account,
+                        },
+                        Proof {
+                            path: vec![],
+                            siblings: vec![],
+                        },
+                    ),
+                )],
+                non_inclusion_proofs: vec![],
+                num_sigs,
+                account_delta_root: hash,
+                parent_bankhash,
+                blockhash,
+            },
+            votes: vec![],
+            copy_preimages: vec![],
+            transaction_proofs: vec![],
+        }
+    }
+
+    /// The vote account `identity` votes for in tests
+    pub(crate) fn vote_account(identity: &Keypair) -> Pubkey {
+        Pubkey::create_with_seed(&identity.pubkey(), "vote", &solana_sdk::vote::program::id()).unwrap()
+    }
+
+    /// A validator set of `identities`, each the authorized voter of its own vote account
+    pub(crate) fn validator_set(identities: &[Keypair]) -> HashMap<Pubkey, Pubkey> {
+        identities
+            .iter()
+            .map(|identity| (vote_account(identity), identity.pubkey()))
+            .collect()
+    }
+
+    pub(crate) fn vote(identity: &Keypair, slot: u64, bank_hash: Hash) -> VoteProof {
+        let mut vote_state_update = VoteStateUpdate::from(vec![(slot, 1)]);
+        vote_state_update.hash = bank_hash;
+        let instruction =
+            compact_update_vote_state(&vote_account(identity), &identity.pubkey(), vote_state_update);
+        let message = Message::new(&[instruction], Some(&identity.pubkey()));
+        VoteProof {
+            signature: identity.sign_message(&message.serialize()).as_ref().to_vec(),
+            message: message.serialize(),
+        }
+    }
+
+    /// A vote for the vote account of `validator` signed by `signer` instead of its authorized voter
+    fn impersonated_vote(validator: &Keypair, signer: &Keypair, slot: u64, bank_hash: Hash) -> VoteProof {
+        let mut vote_state_update = VoteStateUpdate::from(vec![(slot, 1)]);
+        vote_state_update.hash = bank_hash;
+        let instruction =
+            compact_update_vote_state(&vote_account(validator), &signer.pubkey(), vote_state_update);
+        let message = Message::new(&[instruction], Some(&signer.pubkey()));
+        VoteProof {
+            signature: signer.sign_message(&message.serialize()).as_ref().to_vec(),
+            message: message.serialize(),
+        }
+    }
+
+    #[test]
+    fn test_verify_against_bank_hash() {
+        let update = single_account_update(7);
+        let report = verify_update(&update, &TrustedRoot::BankHash(update.root));
+        assert!(report.is_valid());
+
+        let report = verify_update(&update, &TrustedRoot::BankHash(Hash::new_unique()));
+        assert!(!report.is_valid());
+        assert!(report.accounts[0].error.is_none());
+
+        let mut tampered = update.clone();
+        tampered.proof.proofs[0].1 .0.account.lamports = 11;
+        let report = verify_update(&tampered, &TrustedRoot::BankHash(update.root));
+        assert!(!report.is_valid());
+        assert!(report.accounts[0].error.is_some());
+    }
+
+    #[test]
+    fn test_verify_transaction_proofs() {
+        let mut update = single_account_update(7);
+        // a block of a single entry holding a single transaction
+        let signature = Signature::new_unique();
+        let start_hash = Hash::new_unique();
+        let blockhash = next_entry_hash(&start_hash, 2, Some(hash_signatures(&[signature])));
+        let slot_entries = SlotEntries {
+            entries: vec![EntryInfo {
+                slot: 7,
+                index: 0,
+                num_hashes: 2,
+                hash: blockhash,
+                executed_transaction_count: 1,
+            }],
+            transactions: vec![TransactionInfo {
+                slot: 7,
+                index: 0,
+                signatures: vec![signature],
+                account_keys: vec![],
+            }],
+        };
+        update.transaction_proofs =
+            assemble_transaction_proofs(&slot_entries, start_hash, blockhash, &[signature]).unwrap();
+        update.proof.blockhash = blockhash;
+        update.root = BankHashComponents::from(&update.proof).bank_hash();
+
+        let report = verify_update(&update, &TrustedRoot::BankHash(update.root));
+        assert!(report.is_valid());
+        assert_eq!(report.transactions[0].signature, signature);
+
+        let mut tampered = update.clone();
+        tampered.transaction_proofs[0].start_hash = Hash::new_unique();
+        let report = verify_update(&tampered, &TrustedRoot::BankHash(update.root));
+        assert!(!report.is_valid());
+        assert!(report.accounts[0].error.is_none());
+        assert!(report.transactions[0].error.is_some());
+    }
+
+    #[test]
+    fn test_verify_against_validator_set() {
+        let validators: Vec<Keypair> = (0..3).map(|_| Keypair::new()).collect();
+        let trusted = TrustedRoot::supermajority_of(validator_set(&validators));
+
+        let mut update = single_account_update(7);
+        update.votes = vec![
+            vote(&validators[0], 7, update.root),
+            // votes for another bank hash or by untrusted validators don't count
+            vote(&validators[1], 7, Hash::new_unique()),
+            vote(&Keypair::new(), 7, update.root),
+            impersonated_vote(&validators[1], &Keypair::new(), 7, update.root),
+        ];
+        assert!(!verify_update(&update, &trusted).is_valid());
+
+        update.votes.push(vote(&validators[2], 7, update.root));
+        let report = verify_update(&update, &trusted);
+        assert!(report.is_valid(), "{:?}", report.bank_hash_trust);
+    }
+
+    #[test]
+    fn test_proof_file_round_trip() {
+        let update = single_account_update(123);
+        for format in [ProofFormat::Borsh, ProofFormat::Json] {
+            let path = std::env::temp_dir().join(format!(
+                "proof_{}_{}.{}",
+                std::process::id(),
+                rand::random::<u64>(),
+                format
+            ));
+            save_update(&path, &update, format).unwrap();
+            let loaded = load_update(&path).unwrap();
+            assert_eq!(loaded.slot, update.slot);
+            assert_eq!(loaded.root, update.root);
+            assert!(verify_update(&loaded, &TrustedRoot::BankHash(update.root)).is_valid());
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
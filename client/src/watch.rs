@@ -0,0 +1,235 @@
+use std::fmt;
+
+use account_proof_geyser::types::AccountInfo;
+use solana_sdk::hash::Hash;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::subscriber::VerifiedUpdate;
+
+/// What a confirmed update says about the watched account
+#[derive(Debug, Clone)]
+pub enum WatchEvent {
+    /// The account was modified in `slot` and this is its new state
+    Changed { slot: u64, account: AccountInfo },
+    /// The account was proven unmodified in `slot`, so it is unchanged since `since_slot`
+    Unchanged { slot: u64, since_slot: u64 },
+}
+
+#[derive(Debug, Clone)]
+pub enum WatchError {
+    /// The update doesn't build on the last verified bank hash, so slots in between were missed
+    /// or the update is on another fork
+    Gap {
+        slot: u64,
+        parent_bankhash: Hash,
+        expected_parent_bankhash: Hash,
+    },
+    /// The update proves neither a new state nor the absence of a modification
+    MissingProof { slot: u64, pubkey: Pubkey },
+}
+
+impl fmt::Display for WatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchError::Gap {
+                slot,
+                parent_bankhash,
+                expected_parent_bankhash,
+            } => write!(
+                f,
+                "update for slot {} has parent bank hash {} but the last verified bank hash is {}",
+                slot, parent_bankhash, expected_parent_bankhash
+            ),
+            WatchError::MissingProof { slot, pubkey } => write!(
+                f,
+                "update for slot {} has no inclusion or non-inclusion proof for {}",
+                slot, pubkey
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WatchError {}
+
+/// Tracks the state of one account across consecutive confirmed updates, starting from a verified
+/// state. Every update must chain onto the previous one through its parent bank hash and prove
+/// either a new state or that the account was not modified, so no modification can go unnoticed.
+#[derive(Debug, Clone)]
+pub struct AccountWatcher {
+    pub pubkey: Pubkey,
+    /// Latest verified state of the account
+    pub account: AccountInfo,
+    /// Slot in which `account` was last modified
+    pub modified_slot: u64,
+    /// Last slot whose update was applied
+    pub verified_slot: u64,
+    /// Bank hash of `verified_slot`
+    pub bank_hash: Hash,
+}
+
+impl AccountWatcher {
+    /// Start from the state of `pubkey` proven by `update`. Returns `None` when the update has no
+    /// inclusion proof for it.
+    pub fn new(pubkey: Pubkey, update: &VerifiedUpdate) -> Option<Self> {
+        let account = update.account(&pubkey)?.clone();
+        Some(AccountWatcher {
+            pubkey,
+            account,
+            modified_slot: update.slot,
+            verified_slot: update.slot,
+            bank_hash: update.bank_hash,
+        })
+    }
+
+    /// Apply the next confirmed update after `verified_slot`
+    pub fn apply(&mut self, update: &VerifiedUpdate) -> Result<WatchEvent, WatchError> {
+        let parent_bankhash = update.update.proof.parent_bankhash;
+        if parent_bankhash != self.bank_hash {
+            return Err(WatchError::Gap {
+                slot: update.slot,
+                parent_bankhash,
+                expected_parent_bankhash: self.bank_hash,
+            });
+        }
+
+        let event = if let Some(account) = update.account(&self.pubkey) {
+            self.account = account.clone();
+            self.modified_slot = update.slot;
+            WatchEvent::Changed {
+                slot: update.slot,
+                account: account.clone(),
+            }
+        } else if update.is_unchanged(&self.pubkey) {
+            WatchEvent::Unchanged {
+                slot: update.slot,
+                since_slot: self.modified_slot,
+            }
+        } else {
+            return Err(WatchError::MissingProof {
+                slot: update.slot,
+                pubkey: self.pubkey,
+            });
+        };
+        self.verified_slot = update.slot;
+        self.bank_hash = update.bank_hash;
+        Ok(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use account_proof_geyser::types::{
+        AccountDeltaNonInclusionProof, AccountDeltaProof, BankHashProof, Data, Proof, Update,
+    };
+
+    use super::*;
+
+    fn update(slot: u64, parent_bankhash: Hash) -> VerifiedUpdate {
+        let bank_hash = Hash::new_unique();
+        VerifiedUpdate {
+            slot,
+            bank_hash,
+            update: Update {
+                slot,
+                root: bank_hash,
+                proof: BankHashProof {
+                    proofs: vec![],
+                    non_inclusion_proofs: vec![],
+                    num_sigs: 0,
+                    account_delta_root: Hash::default(),
+                    parent_bankhash,
+                    blockhash: Hash::default(),
+                },
+                votes: vec![],
+                copy_preimages: vec![],
+                transaction_proofs: vec![],
+            },
+        }
+    }
+
+    fn with_account(mut update: VerifiedUpdate, pubkey: Pubkey, lamports: u64) -> VerifiedUpdate {
+        let account = AccountInfo {
+            pubkey,
+            lamports,
+            slot: update.slot,
+            ..AccountInfo::default()
+        };
+        update.update.proof.proofs.push(AccountDeltaProof(
+            pubkey,
+            (
+                Data {
+                    pubkey,
+                    hash: Hash::default(),
+                    account,
+                },
+                Proof {
+                    path: vec![],
+                    siblings: vec![],
+                },
+            ),
+        ));
+        update
+    }
+
+    fn unchanged(mut update: VerifiedUpdate, pubkey: Pubkey) -> VerifiedUpdate {
+        update
+            .update
+            .proof
+            .non_inclusion_proofs
+            .push(AccountDeltaNonInclusionProof {
+                pubkey,
+                left: None,
+                right: None,
+            });
+        update
+    }
+
+    #[test]
+    fn test_watch_tracks_state_across_updates() {
+        let pubkey = Pubkey::new_unique();
+        let start = with_account(update(10, Hash::new_unique()), pubkey, 5);
+        let mut watcher = AccountWatcher::new(pubkey, &start).unwrap();
+
+        // slot 11 was skipped, slot 12 builds on slot 10
+        let next = unchanged(update(12, start.bank_hash), pubkey);
+        assert!(matches!(
+            watcher.apply(&next).unwrap(),
+            WatchEvent::Unchanged {
+                slot: 12,
+                since_slot: 10
+            }
+        ));
+
+        let changed = with_account(update(13, next.bank_hash), pubkey, 7);
+        assert!(matches!(
+            watcher.apply(&changed).unwrap(),
+            WatchEvent::Changed { slot: 13, .. }
+        ));
+        assert_eq!(watcher.account.lamports, 7);
+        assert_eq!(watcher.modified_slot, 13);
+        assert_eq!(watcher.verified_slot, 13);
+    }
+
+    #[test]
+    fn test_watch_fails_on_gap_or_missing_proof() {
+        let pubkey = Pubkey::new_unique();
+        let start = with_account(update(10, Hash::new_unique()), pubkey, 5);
+        let mut watcher = AccountWatcher::new(pubkey, &start).unwrap();
+        assert!(AccountWatcher::new(Pubkey::new_unique(), &start).is_none());
+
+        let disconnected = unchanged(update(12, Hash::new_unique()), pubkey);
+        assert!(matches!(
+            watcher.apply(&disconnected),
+            Err(WatchError::Gap { slot: 12, .. })
+        ));
+
+        let no_proof = unchanged(update(12, start.bank_hash), Pubkey::new_unique());
+        assert!(matches!(
+            watcher.apply(&no_proof),
+            Err(WatchError::MissingProof { slot: 12, .. })
+        ));
+        // failed updates leave the state untouched
+        assert_eq!(watcher.verified_slot, 10);
+        assert_eq!(watcher.bank_hash, start.bank_hash);
+    }
+}
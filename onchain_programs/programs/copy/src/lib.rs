@@ -1,17 +1,102 @@
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::keccak::{hashv, Hash};
+use anchor_lang::system_program::{transfer, Transfer};
+use anchor_lang::Discriminator;
 
 declare_id!("97rYcJXAFPCGZCecRktMg9GueXsdgU55V8FsdxUiDZrc");
 
 pub const PREFIX: &str = "copy_hash";
 
-pub fn account_hasher(pubkey: &Pubkey, lamports: u64, data: &[u8], owner: &Pubkey, rent_epoch: u64) -> Hash {
+/// Number of the latest copies of a slot a `CopyAccount` keeps in `entries`. Older copies of the
+/// slot roll over into `rolled_over_digest`.
+pub const MAX_COPY_ENTRIES: usize = 16;
+
+pub const COPY_ACCOUNT_SPACE: usize = 8 + 32 + 8 + 1 + 32 + 4 + 32 + 4 + MAX_COPY_ENTRIES * (32 + 32 + 1 + 8 + 8);
+
+/// Size of the first `CopyAccount` layout, which only held the digest and its slot. Every layout
+/// since starts with those two fields.
+pub const LEGACY_COPY_ACCOUNT_SPACE: usize = 8 + 32 + 8;
+
+/// Version of the account hashing scheme, recorded in `CopyAccount.version`
+pub const ACCOUNT_HASH_VERSION: u8 = 1;
+
+/// Prefix of every account hash so it can't collide with other keccak hashes
+pub const ACCOUNT_HASH_DOMAIN: &[u8] = b"copy_hash:account";
+
+/// Prefix of the hashes of a byte range of an account
+pub const ACCOUNT_RANGE_HASH_DOMAIN: &[u8] = b"copy_hash:range";
+
+/// Which copy account a copy is written to. Copies from different scopes don't contend for the same
+/// write lock.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CopyScope {
+    /// The single PDA derived from `PREFIX` alone
+    Global,
+    /// A PDA per creator, derived from `PREFIX` and the creator's pubkey
+    Creator,
+    /// One of several shared PDAs, derived from `PREFIX` and the shard index
+    Shard { index: u8 },
+}
+
+impl CopyScope {
+    /// Seed that follows `PREFIX` when deriving the copy account
+    pub fn seed<'a>(&'a self, creator: &'a Pubkey) -> &'a [u8] {
+        match self {
+            CopyScope::Global => &[],
+            CopyScope::Creator => creator.as_ref(),
+            CopyScope::Shard { index } => std::slice::from_ref(index),
+        }
+    }
+
+    pub fn find_copy_address(&self, creator: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[PREFIX.as_bytes(), self.seed(creator)], program_id)
+    }
+
+    /// Check that `bump` derives `copy_account`. The accounts constraints already require the
+    /// canonical PDA, so only its bump passes.
+    pub fn check_bump(&self, creator: &Pubkey, bump: u8, copy_account: &Pubkey, program_id: &Pubkey) -> Result<()> {
+        let address = Pubkey::create_program_address(&[PREFIX.as_bytes(), self.seed(creator), &[bump]], program_id)
+            .map_err(|_| error!(CopyError::WrongBump))?;
+        require_keys_eq!(address, *copy_account, CopyError::WrongBump);
+        Ok(())
+    }
+}
+
+/// Shard a source account is copied to when copies are spread over `shards` shared PDAs
+pub fn shard_index(source: &Pubkey, shards: u8) -> u8 {
+    hashv(&[source.as_ref()]).0[0] % shards.max(1)
+}
+
+/// Hash of every field of an account under `ACCOUNT_HASH_VERSION`. The data goes last, after
+/// its length, so that the encoding is unambiguous.
+pub fn account_hasher(pubkey: &Pubkey, lamports: u64, data: &[u8], owner: &Pubkey, executable: bool, rent_epoch: u64) -> Hash {
     hashv(&[
+        ACCOUNT_HASH_DOMAIN,
+        &[ACCOUNT_HASH_VERSION],
         pubkey.as_ref(),
         &lamports.to_le_bytes(),
+        owner.as_ref(),
+        &[executable as u8],
+        &rent_epoch.to_le_bytes(),
+        &(data.len() as u64).to_le_bytes(),
         data,
+    ])
+}
+
+/// Hash of the account metadata and `data[offset..offset + len]` under `ACCOUNT_HASH_VERSION`.
+/// `slice` must be that range of the account data.
+pub fn account_range_hasher(pubkey: &Pubkey, lamports: u64, owner: &Pubkey, executable: bool, rent_epoch: u64, range: &ByteRange, slice: &[u8]) -> Hash {
+    hashv(&[
+        ACCOUNT_RANGE_HASH_DOMAIN,
+        &[ACCOUNT_HASH_VERSION],
+        pubkey.as_ref(),
+        &lamports.to_le_bytes(),
         owner.as_ref(),
+        &[executable as u8],
         &rent_epoch.to_le_bytes(),
+        &range.offset.to_le_bytes(),
+        &range.len.to_le_bytes(),
+        slice,
     ])
 }
 
@@ -19,8 +104,7 @@ pub fn account_hasher(pubkey: &Pubkey, lamports: u64, data: &[u8], owner: &Pubke
 pub mod copy {
     use super::*;
 
-    #[allow(unused_variables)]
-    pub fn copy_hash<'info>(ctx: Context<CopyHash>, bump: u8) -> Result<()> {
+    pub fn copy_hash<'info>(ctx: Context<CopyHash>, bump: u8, scope: CopyScope) -> Result<()> {
         let acc = &ctx.accounts.source_account;
         let current_slot_num = ctx.accounts.clock.slot;
         let lamport_ref = acc.lamports.borrow();
@@ -30,10 +114,16 @@ pub mod copy {
                                           **lamport_ref,
                                           &data_ref,
                                           acc.owner,
+                                          acc.executable,
                                           acc.rent_epoch);
 
+        let creator = ctx.accounts.creator.key();
+        let copy_account = ctx.accounts.copy_account.key();
+        scope.check_bump(&creator, bump, &copy_account, ctx.program_id)?;
         let ca = &mut ctx.accounts.copy_account;
-        ca.accumulate_hash(&account_hash.to_bytes(), current_slot_num);
+        ca.set_payer_if_new(&creator);
+        let position = ca.accumulate_hash(acc.key, &account_hash.to_bytes(), None, current_slot_num)?;
+        emit!(ca.copy_event(copy_account, position));
         msg!(
             "slot: {:?}, triggering account hash: {:?}, accumulated hash: {:?}",
             current_slot_num,
@@ -42,9 +132,170 @@ pub mod copy {
         );
         Ok(())
     }
+
+    /// Same as `copy_hash` for every account passed in `remaining_accounts`, in order
+    pub fn copy_hashes<'info>(ctx: Context<'_, '_, '_, 'info, CopyHashes<'info>>, bump: u8, scope: CopyScope) -> Result<()> {
+        require!(!ctx.remaining_accounts.is_empty(), CopyError::NoSourceAccounts);
+        let current_slot_num = ctx.accounts.clock.slot;
+
+        let creator = ctx.accounts.creator.key();
+        let copy_account = ctx.accounts.copy_account.key();
+        scope.check_bump(&creator, bump, &copy_account, ctx.program_id)?;
+        let ca = &mut ctx.accounts.copy_account;
+        ca.set_payer_if_new(&creator);
+        for acc in ctx.remaining_accounts.iter() {
+            let lamport_ref = acc.lamports.borrow();
+            let data_ref = acc.data.borrow();
+            let account_hash = account_hasher(&acc.key,
+                                              **lamport_ref,
+                                              &data_ref,
+                                              acc.owner,
+                                              acc.executable,
+                                              acc.rent_epoch);
+            let position = ca.accumulate_hash(acc.key, &account_hash.to_bytes(), None, current_slot_num)?;
+            emit!(ca.copy_event(copy_account, position));
+            msg!("slot: {:?}, triggering account: {:?}, account hash: {:?}", current_slot_num, acc.key, account_hash);
+        }
+        msg!("slot: {:?}, accumulated hash: {:?}", current_slot_num, ca.digest);
+        Ok(())
+    }
+
+    /// Same as `copy_hash` but only hashes `len` bytes of the source account data from `offset`
+    pub fn copy_hash_range<'info>(ctx: Context<CopyHash>, bump: u8, scope: CopyScope, offset: u64, len: u64) -> Result<()> {
+        let acc = &ctx.accounts.source_account;
+        let current_slot_num = ctx.accounts.clock.slot;
+        let lamport_ref = acc.lamports.borrow();
+        let data_ref = acc.data.borrow();
+
+        let range = ByteRange { offset, len };
+        let Some(slice) = range.slice(&data_ref) else {
+            return err!(CopyError::RangeOutOfBounds);
+        };
+        let account_hash = account_range_hasher(&acc.key,
+                                                **lamport_ref,
+                                                acc.owner,
+                                                acc.executable,
+                                                acc.rent_epoch,
+                                                &range,
+                                                slice);
+
+        let creator = ctx.accounts.creator.key();
+        let copy_account = ctx.accounts.copy_account.key();
+        scope.check_bump(&creator, bump, &copy_account, ctx.program_id)?;
+        let ca = &mut ctx.accounts.copy_account;
+        ca.set_payer_if_new(&creator);
+        let position = ca.accumulate_hash(acc.key, &account_hash.to_bytes(), Some(range), current_slot_num)?;
+        emit!(ca.copy_event(copy_account, position));
+        msg!(
+            "slot: {:?}, triggering account hash: {:?} for bytes {}..{}, accumulated hash: {:?}",
+            current_slot_num,
+            account_hash,
+            offset,
+            offset + len,
+            ca.digest
+        );
+        Ok(())
+    }
+
+    /// Entry point for other programs to copy an account in the same transaction that modifies
+    /// it. Same as `copy_hash` but without the clock sysvar account. See `copy_cpi::copy_hash`.
+    pub fn copy_hash_cpi<'info>(ctx: Context<CopyHashCpi>, bump: u8, scope: CopyScope) -> Result<()> {
+        let acc = &ctx.accounts.source_account;
+        let current_slot_num = Clock::get()?.slot;
+        let lamport_ref = acc.lamports.borrow();
+        let data_ref = acc.data.borrow();
+
+        let account_hash = account_hasher(&acc.key,
+                                          **lamport_ref,
+                                          &data_ref,
+                                          acc.owner,
+                                          acc.executable,
+                                          acc.rent_epoch);
+
+        let payer = ctx.accounts.payer.key();
+        let copy_account = ctx.accounts.copy_account.key();
+        scope.check_bump(&payer, bump, &copy_account, ctx.program_id)?;
+        let ca = &mut ctx.accounts.copy_account;
+        ca.set_payer_if_new(&payer);
+        let position = ca.accumulate_hash(acc.key, &account_hash.to_bytes(), None, current_slot_num)?;
+        emit!(ca.copy_event(copy_account, position));
+        Ok(())
+    }
+
+    /// Close a copy account and return its rent to the payer that created it, the only signer
+    /// allowed to close it. The shared `Global` and `Shard` accounts can only be closed once no copy
+    /// was made in the current slot, so that the copies of others being proven are not wiped.
+    pub fn close_copy_account<'info>(ctx: Context<CloseCopyAccount>, scope: CopyScope) -> Result<()> {
+        if scope != CopyScope::Creator {
+            require!(ctx.accounts.copy_account.slot < Clock::get()?.slot, CopyError::CopyAccountInUse);
+        }
+        msg!("closing copy account {:?}", ctx.accounts.copy_account.key());
+        Ok(())
+    }
+
+    /// Grow a copy account created with an older, smaller layout to `COPY_ACCOUNT_SPACE` so that
+    /// copies can deserialize it again. The digest and slot are kept, the payer funds the extra
+    /// rent and becomes the payer recorded for closing it, and the next copy starts a new
+    /// accumulation.
+    #[allow(unused_variables)]
+    pub fn migrate_copy_account<'info>(ctx: Context<MigrateCopyAccount>, scope: CopyScope) -> Result<()> {
+        let info = ctx.accounts.copy_account.to_account_info();
+        let (digest, slot) = {
+            let data = info.try_borrow_data()?;
+            require!(data.len() < COPY_ACCOUNT_SPACE, CopyError::AlreadyMigrated);
+            require!(
+                data.len() >= LEGACY_COPY_ACCOUNT_SPACE && data[..8] == CopyAccount::DISCRIMINATOR,
+                CopyError::UnknownLayout
+            );
+            let mut digest = [0u8; 32];
+            digest.copy_from_slice(&data[8..40]);
+            (digest, u64::from_le_bytes(data[40..48].try_into().unwrap()))
+        };
+
+        let rent = Rent::get()?.minimum_balance(COPY_ACCOUNT_SPACE).saturating_sub(info.lamports());
+        if rent > 0 {
+            let accounts = Transfer {
+                from: ctx.accounts.payer.to_account_info(),
+                to: info.clone(),
+            };
+            transfer(CpiContext::new(ctx.accounts.system_program.to_account_info(), accounts), rent)?;
+        }
+        info.realloc(COPY_ACCOUNT_SPACE, false)?;
+
+        // version 0 marks the digest as one of an older hashing scheme, so it isn't accumulated into
+        let migrated = CopyAccount {
+            digest,
+            slot,
+            payer: ctx.accounts.payer.key(),
+            ..CopyAccount::default()
+        };
+        let mut data = info.try_borrow_mut_data()?;
+        migrated.try_serialize(&mut &mut data[..])?;
+        msg!("migrated copy account {:?} at slot {}", info.key, slot);
+        Ok(())
+    }
+}
+
+#[error_code]
+pub enum CopyError {
+    #[msg("No source accounts were passed in remaining_accounts")]
+    NoSourceAccounts,
+    #[msg("The byte range is outside of the source account data")]
+    RangeOutOfBounds,
+    #[msg("A shared copy account can't be closed during a slot it was copied into")]
+    CopyAccountInUse,
+    #[msg("Only the payer that created the copy account can close it")]
+    NotPayer,
+    #[msg("The bump does not derive the copy account")]
+    WrongBump,
+    #[msg("The copy account already has the current layout")]
+    AlreadyMigrated,
+    #[msg("The copy account does not hold a known copy account layout")]
+    UnknownLayout,
 }
 
 #[derive(Accounts)]
+#[instruction(bump: u8, scope: CopyScope)]
 pub struct CopyHash<'info> {
     /// The signer who initiates the chunk processing.
     #[account(mut)]
@@ -52,7 +303,25 @@ pub struct CopyHash<'info> {
     /// CHECK: no writes, no deser
     pub source_account: AccountInfo<'info>,
     /// Account (PDA) for storing the Merkle root of the accumulated chunks. Initializes if not already present.
-    #[account(init_if_needed, payer=creator, space=8+32+8, seeds= [PREFIX.as_bytes()], bump)]
+    #[account(init_if_needed, payer=creator, space=COPY_ACCOUNT_SPACE, seeds= [PREFIX.as_bytes(), scope.seed(creator.key)], bump)]
+    pub copy_account: Account<'info, CopyAccount>,
+
+    /// The built-in Solana system program.
+    pub system_program: Program<'info, System>,
+
+    /// The Solana sysvar to fetch the current slot number.
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+#[instruction(bump: u8, scope: CopyScope)]
+pub struct CopyHashes<'info> {
+    /// The signer who initiates the copy.
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    /// Account (PDA) for storing the accumulated hash. Initializes if not already present.
+    /// The source accounts are passed as `remaining_accounts`.
+    #[account(init_if_needed, payer=creator, space=COPY_ACCOUNT_SPACE, seeds= [PREFIX.as_bytes(), scope.seed(creator.key)], bump)]
     pub copy_account: Account<'info, CopyAccount>,
 
     /// The built-in Solana system program.
@@ -62,6 +331,87 @@ pub struct CopyHash<'info> {
     pub clock: Sysvar<'info, Clock>,
 }
 
+#[derive(Accounts)]
+#[instruction(bump: u8, scope: CopyScope)]
+pub struct CopyHashCpi<'info> {
+    /// Pays for the copy account when it doesn't exist yet and derives it in the `Creator` scope.
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: no writes, no deser
+    pub source_account: AccountInfo<'info>,
+    /// Account (PDA) for storing the accumulated hash. Initializes if not already present.
+    #[account(init_if_needed, payer=payer, space=COPY_ACCOUNT_SPACE, seeds= [PREFIX.as_bytes(), scope.seed(payer.key)], bump)]
+    pub copy_account: Account<'info, CopyAccount>,
+
+    /// The built-in Solana system program.
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(scope: CopyScope)]
+pub struct CloseCopyAccount<'info> {
+    /// The payer that created the copy account, who receives its rent.
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// The copy account to close.
+    #[account(mut, close=payer, has_one=payer @ CopyError::NotPayer, seeds= [PREFIX.as_bytes(), scope.seed(payer.key)], bump)]
+    pub copy_account: Account<'info, CopyAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(scope: CopyScope)]
+pub struct MigrateCopyAccount<'info> {
+    /// Pays the rent for the larger layout. In the `Creator` scope it derives the copy account.
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: an older layout of `CopyAccount` that can't be deserialized, checked by the instruction
+    #[account(mut, owner=crate::ID, seeds= [PREFIX.as_bytes(), scope.seed(payer.key)], bump)]
+    pub copy_account: UncheckedAccount<'info>,
+
+    /// The built-in Solana system program.
+    pub system_program: Program<'info, System>,
+}
+
+/// `len` bytes of account data starting at `offset`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct ByteRange {
+    pub offset: u64,
+    pub len: u64,
+}
+
+impl ByteRange {
+    pub fn slice<'a>(&self, data: &'a [u8]) -> Option<&'a [u8]> {
+        let start = usize::try_from(self.offset).ok()?;
+        let end = start.checked_add(usize::try_from(self.len).ok()?)?;
+        data.get(start..end)
+    }
+}
+
+/// A source account copied during the slot and its hash at the time of the copy
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct CopyEntry {
+    pub source: Pubkey,
+    pub hash: [u8; 32],
+    /// The part of the account data that was hashed, or `None` when the whole account was
+    pub range: Option<ByteRange>,
+}
+
+/// Emitted for every hash accumulated into a copy account. The events of a slot, in order, are
+/// enough to rebuild the digest of the copy account.
+#[event]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CopyEvent {
+    pub copy_account: Pubkey,
+    pub source: Pubkey,
+    pub hash: [u8; 32],
+    pub range: Option<ByteRange>,
+    pub slot: u64,
+    /// Position of the hash in the accumulation of `slot`, starting at 0
+    pub position: u32,
+    /// `ACCOUNT_HASH_VERSION` of `hash`
+    pub version: u8,
+}
+
 /// Represents the root account for blocks, typically storing a Merkle root.
 #[account]
 #[derive(Default, Debug)]
@@ -70,22 +420,113 @@ pub struct CopyAccount {
     pub digest: [u8; 32],
     /// The current slot number in Solana when this root is recorded.
     pub slot: u64,
+    /// `ACCOUNT_HASH_VERSION` of the hashes in `entries`
+    pub version: u8,
+    /// The signer that paid the rent when the account was created, the only one allowed to close it
+    pub payer: Pubkey,
+    /// Number of copies made during `slot` that no longer fit in `entries`
+    pub rolled_over: u32,
+    /// Digest of the first `rolled_over` copies of `slot`, which `entries` are accumulated onto
+    pub rolled_over_digest: [u8; 32],
+    /// The latest copies made during `slot`, in the order they were accumulated into `digest`
+    pub entries: Vec<CopyEntry>,
 }
 
 impl CopyAccount {
-    pub fn accumulate_hash(&mut self, account_hash: &[u8; 32], slot_num: u64) {
-        // slot number switched
-        if slot_num > self.slot {
+    /// Record the payer of a freshly initialized account
+    pub fn set_payer_if_new(&mut self, payer: &Pubkey) {
+        if self.payer == Pubkey::default() {
+            self.payer = *payer;
+        }
+    }
+
+    /// Fold `account_hash` into the digest of `slot_num` and return its position in the slot
+    pub fn accumulate_hash(&mut self, source: &Pubkey, account_hash: &[u8; 32], range: Option<ByteRange>, slot_num: u64) -> Result<u32> {
+        // slot number switched, or the digest is from an older hashing scheme
+        if slot_num > self.slot || self.version != ACCOUNT_HASH_VERSION {
             self.digest = *account_hash;
-            self.slot = self.slot;
+            self.slot = slot_num;
+            self.version = ACCOUNT_HASH_VERSION;
+            self.rolled_over = 0;
+            self.rolled_over_digest = [0; 32];
+            self.entries.clear();
         } else {
-            // we're in the same solana slot
+            // we're in the same solana slot. A full account drops its oldest entry, whose hash stays
+            // in the digest, so that copies into shared accounts can't be blocked for the slot.
+            if self.entries.len() == MAX_COPY_ENTRIES {
+                let oldest = self.entries.remove(0);
+                self.rolled_over_digest = match self.rolled_over {
+                    0 => oldest.hash,
+                    _ => digest_accumulator(&self.rolled_over_digest, &oldest.hash),
+                };
+                self.rolled_over += 1;
+            }
             self.digest = digest_accumulator(&self.digest, account_hash);
         }
+        self.entries.push(CopyEntry {
+            source: *source,
+            hash: *account_hash,
+            range,
+        });
+        Ok(self.rolled_over + (self.entries.len() - 1) as u32)
+    }
+
+    /// Event for the copy at `position`, which must still be in `entries`
+    pub fn copy_event(&self, copy_account: Pubkey, position: u32) -> CopyEvent {
+        let entry = &self.entries[(position - self.rolled_over) as usize];
+        CopyEvent {
+            copy_account,
+            source: entry.source,
+            hash: entry.hash,
+            range: entry.range,
+            slot: self.slot,
+            position,
+            version: self.version,
+        }
+    }
+
+    /// Whether this copy was made during `slot`
+    pub fn contains(&self, entry: &CopyEntry) -> bool {
+        self.entries.contains(entry)
+    }
+}
+
+/// Helpers for calling the copy program from other programs. Enable the `cpi` feature to use them.
+#[cfg(feature = "cpi")]
+pub mod copy_cpi {
+    use super::*;
+
+    /// Address of the copy account `payer` writes to in `scope`
+    pub fn copy_address(payer: &Pubkey, scope: CopyScope) -> Pubkey {
+        scope.find_copy_address(payer, &ID).0
+    }
+
+    /// Copy `source_account` into the copy account of `payer` in `scope`. `payer` must have signed
+    /// the transaction, since it funds the copy account when it is created.
+    pub fn copy_hash<'info>(
+        copy_program: AccountInfo<'info>,
+        payer: AccountInfo<'info>,
+        source_account: AccountInfo<'info>,
+        copy_account: AccountInfo<'info>,
+        system_program: AccountInfo<'info>,
+        scope: CopyScope,
+    ) -> Result<()> {
+        let (_, bump) = scope.find_copy_address(payer.key, &ID);
+        let accounts = crate::cpi::accounts::CopyHashCpi {
+            payer,
+            source_account,
+            copy_account,
+            system_program,
+        };
+        crate::cpi::copy_hash_cpi(
+            CpiContext::new(copy_program, accounts),
+            bump,
+            scope,
+        )
     }
 }
 
-fn digest_accumulator(current_hash: &[u8; 32], digest: &[u8; 32]) -> [u8; 32] {
+pub fn digest_accumulator(current_hash: &[u8; 32], digest: &[u8; 32]) -> [u8; 32] {
     let combined = [current_hash.as_ref(), digest.as_ref()];
     hashv(&combined).0
 }
@@ -0,0 +1,118 @@
+#![allow(dead_code)]
+
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use copy::{CopyAccount, CopyScope};
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::account_info::AccountInfo;
+use solana_sdk::instruction::{Instruction, InstructionError};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::sysvar::{clock, SysvarId};
+use solana_sdk::transaction::{Transaction, TransactionError};
+use solana_sdk::{system_instruction, system_program};
+
+// Anchor's entrypoint ties the lifetime of the accounts slice to the lifetime of the accounts
+fn process_instruction(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let accounts = Box::leak(Box::new(accounts.to_vec()));
+    copy::entry(program_id, accounts, data)
+}
+
+pub fn program_test() -> ProgramTest {
+    ProgramTest::new("copy", copy::id(), processor!(process_instruction))
+}
+
+pub async fn start() -> ProgramTestContext {
+    program_test().start_with_context().await
+}
+
+pub fn copy_hash_ix(creator: &Pubkey, source: &Pubkey, scope: CopyScope) -> Instruction {
+    let (_, bump) = scope.find_copy_address(creator, &copy::id());
+    copy_hash_ix_with_bump(creator, source, scope, bump)
+}
+
+pub fn copy_hash_ix_with_bump(creator: &Pubkey, source: &Pubkey, scope: CopyScope, bump: u8) -> Instruction {
+    let (copy_account, _) = scope.find_copy_address(creator, &copy::id());
+    Instruction {
+        program_id: copy::id(),
+        accounts: copy::accounts::CopyHash {
+            creator: *creator,
+            source_account: *source,
+            copy_account,
+            system_program: system_program::id(),
+            clock: clock::Clock::id(),
+        }
+        .to_account_metas(None),
+        data: copy::instruction::CopyHash { bump, scope }.data(),
+    }
+}
+
+pub fn copy_hash_range_ix(creator: &Pubkey, source: &Pubkey, scope: CopyScope, offset: u64, len: u64) -> Instruction {
+    let mut instruction = copy_hash_ix(creator, source, scope);
+    let (_, bump) = scope.find_copy_address(creator, &copy::id());
+    instruction.data = copy::instruction::CopyHashRange { bump, scope, offset, len }.data();
+    instruction
+}
+
+pub fn close_ix(payer: &Pubkey, copy_account: &Pubkey, scope: CopyScope) -> Instruction {
+    Instruction {
+        program_id: copy::id(),
+        accounts: copy::accounts::CloseCopyAccount {
+            payer: *payer,
+            copy_account: *copy_account,
+        }
+        .to_account_metas(None),
+        data: copy::instruction::CloseCopyAccount { scope }.data(),
+    }
+}
+
+pub fn migrate_ix(payer: &Pubkey, copy_account: &Pubkey, scope: CopyScope) -> Instruction {
+    Instruction {
+        program_id: copy::id(),
+        accounts: copy::accounts::MigrateCopyAccount {
+            payer: *payer,
+            copy_account: *copy_account,
+            system_program: system_program::id(),
+        }
+        .to_account_metas(None),
+        data: copy::instruction::MigrateCopyAccount { scope }.data(),
+    }
+}
+
+pub async fn send(
+    context: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> Result<(), BanksClientError> {
+    let blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+    let transaction =
+        Transaction::new_signed_with_payer(instructions, Some(&signers[0].pubkey()), signers, blockhash);
+    context.banks_client.process_transaction(transaction).await
+}
+
+/// A new keypair holding one SOL, paid for by the context payer
+pub async fn funded_keypair(context: &mut ProgramTestContext) -> Keypair {
+    let payer = context.payer.insecure_clone();
+    let keypair = Keypair::new();
+    send(
+        context,
+        &[system_instruction::transfer(&payer.pubkey(), &keypair.pubkey(), 1_000_000_000)],
+        &[&payer],
+    )
+    .await
+    .unwrap();
+    keypair
+}
+
+pub async fn copy_account(context: &mut ProgramTestContext, address: &Pubkey) -> Option<CopyAccount> {
+    let account = context.banks_client.get_account(*address).await.unwrap()?;
+    Some(CopyAccount::try_deserialize(&mut account.data.as_slice()).unwrap())
+}
+
+/// The custom program error a transaction failed with, if any
+pub fn custom_error(result: Result<(), BanksClientError>) -> Option<u32> {
+    match result.err()?.unwrap() {
+        TransactionError::InstructionError(_, InstructionError::Custom(code)) => Some(code),
+        _ => None,
+    }
+}
@@ -0,0 +1,197 @@
+mod common;
+
+use anchor_lang::error::ErrorCode;
+use common::{
+    copy_account, copy_hash_ix, copy_hash_ix_with_bump, copy_hash_range_ix, custom_error, funded_keypair, program_test,
+    send, start,
+};
+use copy::{
+    account_hasher, account_range_hasher, digest_accumulator, ByteRange, CopyError, CopyScope, ACCOUNT_HASH_VERSION,
+    MAX_COPY_ENTRIES,
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::account::Account;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signer;
+use solana_sdk::transaction::Transaction;
+
+/// Hash the source account as it is stored by the bank
+async fn source_hash(context: &mut ProgramTestContext, source: &Pubkey) -> [u8; 32] {
+    let account = context.banks_client.get_account(*source).await.unwrap().unwrap();
+    account_hasher(
+        source,
+        account.lamports,
+        &account.data,
+        &account.owner,
+        account.executable,
+        account.rent_epoch,
+    )
+    .to_bytes()
+}
+
+fn source_account(data: &[u8]) -> Account {
+    Account {
+        lamports: 1_000_000,
+        data: data.to_vec(),
+        owner: Pubkey::new_unique(),
+        ..Account::default()
+    }
+}
+
+#[tokio::test]
+async fn test_copy_hash_matches_account_hasher() {
+    let source = Pubkey::new_unique();
+    let mut program_test = program_test();
+    program_test.add_account(source, source_account(b"some account data"));
+    let mut context = program_test.start_with_context().await;
+    let payer = context.payer.insecure_clone();
+    let scope = CopyScope::Creator;
+    let (address, _) = scope.find_copy_address(&payer.pubkey(), &copy::id());
+
+    send(&mut context, &[copy_hash_ix(&payer.pubkey(), &source, scope)], &[&payer])
+        .await
+        .unwrap();
+
+    let hash = source_hash(&mut context, &source).await;
+    let copied = copy_account(&mut context, &address).await.unwrap();
+    assert_eq!(copied.digest, hash);
+    assert_eq!(copied.version, ACCOUNT_HASH_VERSION);
+    assert_eq!(copied.entries.len(), 1);
+    assert_eq!(copied.entries[0].source, source);
+    assert_eq!(copied.entries[0].hash, hash);
+    assert_eq!(copied.entries[0].range, None);
+}
+
+#[tokio::test]
+async fn test_repeated_copies_within_and_across_slots() {
+    let (first, second) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let mut program_test = program_test();
+    program_test.add_account(first, source_account(b"first"));
+    program_test.add_account(second, source_account(b"second"));
+    let mut context = program_test.start_with_context().await;
+    let payer = context.payer.insecure_clone();
+    let scope = CopyScope::Global;
+    let (address, _) = scope.find_copy_address(&payer.pubkey(), &copy::id());
+
+    // copies in one transaction land in the same slot and are folded in order
+    send(
+        &mut context,
+        &[
+            copy_hash_ix(&payer.pubkey(), &first, scope),
+            copy_hash_ix(&payer.pubkey(), &second, scope),
+            copy_hash_ix(&payer.pubkey(), &first, scope),
+        ],
+        &[&payer],
+    )
+    .await
+    .unwrap();
+
+    let first_hash = source_hash(&mut context, &first).await;
+    let second_hash = source_hash(&mut context, &second).await;
+    let copied = copy_account(&mut context, &address).await.unwrap();
+    let sources: Vec<Pubkey> = copied.entries.iter().map(|entry| entry.source).collect();
+    assert_eq!(sources, vec![first, second, first]);
+    assert_eq!(
+        copied.digest,
+        digest_accumulator(&digest_accumulator(&first_hash, &second_hash), &first_hash)
+    );
+
+    // a copy in a later slot starts over
+    let slot = copied.slot;
+    context.warp_to_slot(slot + 2).unwrap();
+    send(&mut context, &[copy_hash_ix(&payer.pubkey(), &second, scope)], &[&payer])
+        .await
+        .unwrap();
+    let copied = copy_account(&mut context, &address).await.unwrap();
+    assert!(copied.slot > slot);
+    assert_eq!(copied.digest, second_hash);
+    assert_eq!(copied.entries.len(), 1);
+}
+
+#[tokio::test]
+async fn test_full_copy_account_rolls_over_oldest_entries() {
+    let source = Pubkey::new_unique();
+    let mut program_test = program_test();
+    program_test.add_account(source, source_account(b"shared"));
+    let mut context = program_test.start_with_context().await;
+    let payer = context.payer.insecure_clone();
+    let scope = CopyScope::Global;
+    let (address, _) = scope.find_copy_address(&payer.pubkey(), &copy::id());
+
+    // more copies than the account keeps, all in one slot
+    let copies = MAX_COPY_ENTRIES + 4;
+    let instructions: Vec<_> = (0..copies).map(|_| copy_hash_ix(&payer.pubkey(), &source, scope)).collect();
+    send(&mut context, &instructions, &[&payer]).await.unwrap();
+
+    let hash = source_hash(&mut context, &source).await;
+    let copied = copy_account(&mut context, &address).await.unwrap();
+    assert_eq!(copied.entries.len(), MAX_COPY_ENTRIES);
+    assert_eq!(copied.rolled_over, 4);
+    let digest = (1..copies).fold(hash, |digest, _| digest_accumulator(&digest, &hash));
+    assert_eq!(copied.digest, digest);
+    let rolled_over_digest = (1..4).fold(hash, |digest, _| digest_accumulator(&digest, &hash));
+    assert_eq!(copied.rolled_over_digest, rolled_over_digest);
+}
+
+#[tokio::test]
+async fn test_copy_hash_range() {
+    let source = Pubkey::new_unique();
+    let mut program_test = program_test();
+    program_test.add_account(source, source_account(b"0123456789"));
+    let mut context = program_test.start_with_context().await;
+    let payer = context.payer.insecure_clone();
+    let scope = CopyScope::Creator;
+    let (address, _) = scope.find_copy_address(&payer.pubkey(), &copy::id());
+
+    send(&mut context, &[copy_hash_range_ix(&payer.pubkey(), &source, scope, 2, 4)], &[&payer])
+        .await
+        .unwrap();
+    let account = context.banks_client.get_account(source).await.unwrap().unwrap();
+    let range = ByteRange { offset: 2, len: 4 };
+    let hash = account_range_hasher(
+        &source,
+        account.lamports,
+        &account.owner,
+        account.executable,
+        account.rent_epoch,
+        &range,
+        b"2345",
+    );
+    let copied = copy_account(&mut context, &address).await.unwrap();
+    assert_eq!(copied.entries[0].hash, hash.to_bytes());
+    assert_eq!(copied.entries[0].range, Some(range));
+
+    let out_of_bounds = send(&mut context, &[copy_hash_range_ix(&payer.pubkey(), &source, scope, 8, 4)], &[&payer]).await;
+    assert_eq!(custom_error(out_of_bounds), Some(CopyError::RangeOutOfBounds.into()));
+}
+
+#[tokio::test]
+async fn test_copy_hash_rejects_wrong_bump() {
+    let mut context = start().await;
+    let payer = context.payer.insecure_clone();
+    let scope = CopyScope::Creator;
+    let (address, bump) = scope.find_copy_address(&payer.pubkey(), &copy::id());
+
+    let instruction = copy_hash_ix_with_bump(&payer.pubkey(), &Pubkey::new_unique(), scope, bump.wrapping_sub(1));
+    let result = send(&mut context, &[instruction], &[&payer]).await;
+    assert_eq!(custom_error(result), Some(CopyError::WrongBump.into()));
+    assert!(copy_account(&mut context, &address).await.is_none());
+}
+
+#[tokio::test]
+async fn test_copy_hash_requires_creator_signature() {
+    let mut context = start().await;
+    let payer = context.payer.insecure_clone();
+    let creator = funded_keypair(&mut context).await;
+    let scope = CopyScope::Creator;
+    let (address, _) = scope.find_copy_address(&creator.pubkey(), &copy::id());
+
+    // the payer pays the fee, but the creator paying for the copy account doesn't sign
+    let mut instruction = copy_hash_ix(&creator.pubkey(), &Pubkey::new_unique(), scope);
+    instruction.accounts[0].is_signer = false;
+    let blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+    let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[&payer], blockhash);
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert_eq!(custom_error(result), Some(ErrorCode::AccountNotSigner.into()));
+    assert!(copy_account(&mut context, &address).await.is_none());
+}
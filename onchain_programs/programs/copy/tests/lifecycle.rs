@@ -0,0 +1,142 @@
+mod common;
+
+use anchor_lang::Discriminator;
+use common::{close_ix, copy_account, copy_hash_ix, custom_error, funded_keypair, migrate_ix, program_test, send, start};
+use copy::{CopyAccount, CopyError, CopyScope, ACCOUNT_HASH_VERSION, COPY_ACCOUNT_SPACE, LEGACY_COPY_ACCOUNT_SPACE};
+use solana_sdk::account::Account;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::rent::Rent;
+use solana_sdk::signature::Signer;
+
+#[tokio::test]
+async fn test_copy_account_lifecycle() {
+    let mut context = start().await;
+    let payer = context.payer.insecure_clone();
+    let other = funded_keypair(&mut context).await;
+
+    let source = Pubkey::new_unique();
+    let scope = CopyScope::Global;
+    let (address, _) = scope.find_copy_address(&payer.pubkey(), &copy::id());
+
+    // the first copy creates the shared account, later copies by others go to the same one
+    send(&mut context, &[copy_hash_ix(&payer.pubkey(), &source, scope)], &[&payer])
+        .await
+        .unwrap();
+    send(&mut context, &[copy_hash_ix(&other.pubkey(), &source, scope)], &[&other])
+        .await
+        .unwrap();
+    let copied = copy_account(&mut context, &address).await.unwrap();
+    assert_eq!(copied.entries.len(), 2);
+
+    assert_eq!(copied.payer, payer.pubkey());
+
+    // only the payer that created it can close it
+    context.warp_to_slot(copied.slot + 2).unwrap();
+    let result = send(&mut context, &[close_ix(&other.pubkey(), &address, scope)], &[&other]).await;
+    assert_eq!(custom_error(result), Some(CopyError::NotPayer.into()));
+
+    // not while it holds the copies of the current slot
+    send(&mut context, &[copy_hash_ix(&other.pubkey(), &source, scope)], &[&other])
+        .await
+        .unwrap();
+    let result = send(&mut context, &[close_ix(&payer.pubkey(), &address, scope)], &[&payer]).await;
+    assert_eq!(custom_error(result), Some(CopyError::CopyAccountInUse.into()));
+
+    // once the slot has passed the payer gets the rent back
+    let copied = copy_account(&mut context, &address).await.unwrap();
+    context.warp_to_slot(copied.slot + 2).unwrap();
+    let rent = context.banks_client.get_balance(address).await.unwrap();
+    let balance_before = context.banks_client.get_balance(payer.pubkey()).await.unwrap();
+    send(&mut context, &[close_ix(&payer.pubkey(), &address, scope)], &[&payer])
+        .await
+        .unwrap();
+    assert!(copy_account(&mut context, &address).await.is_none());
+    let balance_after = context.banks_client.get_balance(payer.pubkey()).await.unwrap();
+    // the rent comes back, less the fee for the close transaction
+    assert!(balance_after > balance_before + rent - 10_000);
+
+    // a closed account is created again by the next copy, which pays for it
+    send(&mut context, &[copy_hash_ix(&other.pubkey(), &Pubkey::new_unique(), scope)], &[&other])
+        .await
+        .unwrap();
+    let recreated = copy_account(&mut context, &address).await.unwrap();
+    assert_eq!(recreated.entries.len(), 1);
+    assert_eq!(recreated.payer, other.pubkey());
+}
+
+#[tokio::test]
+async fn test_close_per_creator_copy_account() {
+    let mut context = start().await;
+    let payer = context.payer.insecure_clone();
+    let scope = CopyScope::Creator;
+    let (address, _) = scope.find_copy_address(&payer.pubkey(), &copy::id());
+
+    send(
+        &mut context,
+        &[copy_hash_ix(&payer.pubkey(), &Pubkey::new_unique(), scope)],
+        &[&payer],
+    )
+    .await
+    .unwrap();
+
+    // it can be closed during the slot it was copied into, but another signer derives another
+    // address, so it can't close the payer's account
+    let other = funded_keypair(&mut context).await;
+    assert!(send(&mut context, &[close_ix(&other.pubkey(), &address, scope)], &[&other])
+        .await
+        .is_err());
+
+    send(&mut context, &[close_ix(&payer.pubkey(), &address, scope)], &[&payer])
+        .await
+        .unwrap();
+    assert!(copy_account(&mut context, &address).await.is_none());
+}
+
+#[tokio::test]
+async fn test_migrate_legacy_copy_account() {
+    let scope = CopyScope::Global;
+    let (address, _) = scope.find_copy_address(&Pubkey::default(), &copy::id());
+    // the first layout only held the digest and its slot
+    let mut data = CopyAccount::DISCRIMINATOR.to_vec();
+    data.extend_from_slice(&[7; 32]);
+    data.extend_from_slice(&1u64.to_le_bytes());
+    let mut program_test = program_test();
+    program_test.add_account(
+        address,
+        Account {
+            lamports: Rent::default().minimum_balance(LEGACY_COPY_ACCOUNT_SPACE),
+            data,
+            owner: copy::id(),
+            ..Account::default()
+        },
+    );
+    let mut context = program_test.start_with_context().await;
+    let payer = context.payer.insecure_clone();
+
+    // copies can't deserialize the old layout
+    assert!(send(&mut context, &[copy_hash_ix(&payer.pubkey(), &Pubkey::new_unique(), scope)], &[&payer])
+        .await
+        .is_err());
+
+    send(&mut context, &[migrate_ix(&payer.pubkey(), &address, scope)], &[&payer])
+        .await
+        .unwrap();
+    let migrated = context.banks_client.get_account(address).await.unwrap().unwrap();
+    assert_eq!(migrated.data.len(), COPY_ACCOUNT_SPACE);
+    assert!(migrated.lamports >= Rent::default().minimum_balance(COPY_ACCOUNT_SPACE));
+    let migrated = copy_account(&mut context, &address).await.unwrap();
+    assert_eq!((migrated.digest, migrated.slot, migrated.version), ([7; 32], 1, 0));
+    assert_eq!(migrated.payer, payer.pubkey());
+
+    // only once
+    let result = send(&mut context, &[migrate_ix(&payer.pubkey(), &address, scope)], &[&payer]).await;
+    assert_eq!(custom_error(result), Some(CopyError::AlreadyMigrated.into()));
+
+    send(&mut context, &[copy_hash_ix(&payer.pubkey(), &Pubkey::new_unique(), scope)], &[&payer])
+        .await
+        .unwrap();
+    let copied = copy_account(&mut context, &address).await.unwrap();
+    assert_eq!(copied.version, ACCOUNT_HASH_VERSION);
+    assert_eq!(copied.entries.len(), 1);
+    assert_eq!(copied.digest, copied.entries[0].hash);
+}
@@ -0,0 +1,65 @@
+use anchor_lang::prelude::*;
+use copy::copy_cpi;
+use copy::program::Copy;
+use copy::CopyScope;
+
+declare_id!("3f9UgqyjTVu7kRcj7NbYoz7HcFuo5nRCn3B39qCQ1YDW");
+
+pub const COUNTER_PREFIX: &str = "counter";
+
+/// Example of a program that commits to the state of its account through the copy program in the
+/// same instruction that modifies it
+#[program]
+pub mod copy_caller {
+    use super::*;
+
+    pub fn initialize(_ctx: Context<Initialize>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Increment the counter and copy its new state into the authority's copy account
+    pub fn increment(ctx: Context<Increment>) -> Result<()> {
+        let counter = &mut ctx.accounts.counter;
+        counter.count += 1;
+        // Write the counter back to the account data so that the copy program hashes the new count
+        counter.exit(&crate::ID)?;
+
+        copy_cpi::copy_hash(
+            ctx.accounts.copy_program.to_account_info(),
+            ctx.accounts.authority.to_account_info(),
+            ctx.accounts.counter.to_account_info(),
+            ctx.accounts.copy_account.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+            CopyScope::Creator,
+        )
+    }
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(init, payer=authority, space=8 + 8, seeds=[COUNTER_PREFIX.as_bytes(), authority.key().as_ref()], bump)]
+    pub counter: Account<'info, Counter>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Increment<'info> {
+    /// Owner of the counter, pays for its copy account
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(mut, seeds=[COUNTER_PREFIX.as_bytes(), authority.key().as_ref()], bump)]
+    pub counter: Account<'info, Counter>,
+    /// CHECK: checked by the copy program
+    #[account(mut)]
+    pub copy_account: UncheckedAccount<'info>,
+    pub copy_program: Program<'info, Copy>,
+    pub system_program: Program<'info, System>,
+}
+
+#[account]
+#[derive(Default, Debug)]
+pub struct Counter {
+    pub count: u64,
+}
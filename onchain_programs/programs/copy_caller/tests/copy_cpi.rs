@@ -0,0 +1,82 @@
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use copy::copy_cpi::copy_address;
+use copy::{account_hasher, CopyAccount, CopyScope};
+use copy_caller::{Counter, COUNTER_PREFIX};
+use solana_program_test::{processor, ProgramTest};
+use solana_sdk::account_info::AccountInfo;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signer;
+use solana_sdk::system_program;
+use solana_sdk::transaction::Transaction;
+
+// Anchor's entrypoint ties the lifetime of the accounts slice to the lifetime of the accounts
+fn process_caller(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let accounts = Box::leak(Box::new(accounts.to_vec()));
+    copy_caller::entry(program_id, accounts, data)
+}
+
+fn process_copy(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let accounts = Box::leak(Box::new(accounts.to_vec()));
+    copy::entry(program_id, accounts, data)
+}
+
+#[tokio::test]
+async fn test_increment_copies_the_new_state() {
+    let mut program_test = ProgramTest::new("copy_caller", copy_caller::id(), processor!(process_caller));
+    program_test.add_program("copy", copy::id(), processor!(process_copy));
+    let mut context = program_test.start_with_context().await;
+    let payer = context.payer.insecure_clone();
+
+    let (counter, _) =
+        Pubkey::find_program_address(&[COUNTER_PREFIX.as_bytes(), payer.pubkey().as_ref()], &copy_caller::id());
+    let copy_account = copy_address(&payer.pubkey(), CopyScope::Creator);
+    let initialize = Instruction {
+        program_id: copy_caller::id(),
+        accounts: copy_caller::accounts::Initialize {
+            authority: payer.pubkey(),
+            counter,
+            system_program: system_program::id(),
+        }
+        .to_account_metas(None),
+        data: copy_caller::instruction::Initialize {}.data(),
+    };
+    let increment = Instruction {
+        program_id: copy_caller::id(),
+        accounts: copy_caller::accounts::Increment {
+            authority: payer.pubkey(),
+            counter,
+            copy_account,
+            copy_program: copy::id(),
+            system_program: system_program::id(),
+        }
+        .to_account_metas(None),
+        data: copy_caller::instruction::Increment {}.data(),
+    };
+
+    let blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+    let transaction =
+        Transaction::new_signed_with_payer(&[initialize, increment], Some(&payer.pubkey()), &[&payer], blockhash);
+    context.banks_client.process_transaction(transaction).await.unwrap();
+
+    let counter_account = context.banks_client.get_account(counter).await.unwrap().unwrap();
+    let count = Counter::try_deserialize(&mut counter_account.data.as_slice()).unwrap();
+    assert_eq!(count.count, 1);
+
+    // the copy holds the counter as modified by the same instruction
+    let copied = context.banks_client.get_account(copy_account).await.unwrap().unwrap();
+    let copied = CopyAccount::try_deserialize(&mut copied.data.as_slice()).unwrap();
+    let hash = account_hasher(
+        &counter,
+        counter_account.lamports,
+        &counter_account.data,
+        &counter_account.owner,
+        counter_account.executable,
+        counter_account.rent_epoch,
+    );
+    assert_eq!(copied.entries.len(), 1);
+    assert_eq!(copied.entries[0].source, counter);
+    assert_eq!(copied.entries[0].hash, hash.to_bytes());
+    assert_eq!(copied.payer, payer.pubkey());
+}